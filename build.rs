@@ -0,0 +1,74 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Dirs of the disk copied by the installer
+const DIRS: [&str; 3] = ["bin", "ini", "tmp"];
+
+// Files created by the installer instead of being copied
+const SKIPPED: [&str; 1] = ["ini/passwords.csv"];
+
+// Create a ustar archive of the disk to be embedded in the installer
+fn main() -> io::Result<()> {
+    println!("cargo:rerun-if-changed=dsk");
+    let mut entries = Vec::new();
+    for dir in DIRS.iter() {
+        collect(Path::new("dsk"), dir, &mut entries)?;
+    }
+    let mut buf = Vec::new();
+    for (name, data) in entries {
+        append(&mut buf, &name, data.as_deref());
+    }
+    buf.resize(buf.len() + 2 * 512, 0);
+    let out = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out).join("dsk.tar"), buf)
+}
+
+// Collect the names of the dirs and files of a tree with the contents of the
+// files, sorted to get the same archive on every build.
+fn collect(root: &Path, name: &str, entries: &mut Vec<(String, Option<Vec<u8>>)>) -> io::Result<()> {
+    let path = root.join(name);
+    if SKIPPED.contains(&name) {
+        return Ok(());
+    }
+    if path.is_dir() {
+        entries.push((name.to_string(), None));
+        let mut names: Vec<String> = fs::read_dir(&path)?.filter_map(|e| {
+            e.ok().and_then(|e| e.file_name().into_string().ok())
+        }).collect();
+        names.sort();
+        for child in names {
+            collect(root, &format!("{}/{}", name, child), entries)?;
+        }
+    } else {
+        entries.push((name.to_string(), Some(fs::read(&path)?)));
+    }
+    Ok(())
+}
+
+fn append(buf: &mut Vec<u8>, name: &str, data: Option<&[u8]>) {
+    let mut header = [0u8; 512];
+    let (name, mode, size, kind) = match data {
+        Some(data) => (name.to_string(), 0o644, data.len(), b'0'),
+        None => (format!("{}/", name), 0o755, 0, b'5'),
+    };
+    assert!(name.len() <= 100, "name too long in archive: {}", name);
+    header[0..name.len()].copy_from_slice(name.as_bytes());
+    header[100..108].copy_from_slice(format!("{:07o}\0", mode).as_bytes());
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    header[124..136].copy_from_slice(format!("{:011o}\0", size).as_bytes());
+    header[136..148].copy_from_slice(b"00000000000\0");
+    header[156] = kind;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[148..156].copy_from_slice(b"        ");
+    let sum: u32 = header.iter().map(|b| *b as u32).sum();
+    header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+    buf.extend_from_slice(&header);
+    if let Some(data) = data {
+        buf.extend_from_slice(data);
+        buf.resize((buf.len() + 511) / 512 * 512, 0);
+    }
+}
@@ -1,7 +1,9 @@
+use crate::api::syscall;
 use crate::sys;
+use crate::sys::fs::ControlRequest;
 use core::fmt;
 
-pub use crate::sys::console::{ETX_KEY, EOT_KEY};
+pub use crate::sys::console::{ETX_KEY, EOT_KEY, ECHO_MODE, RAW_MODE};
 
 #[derive(Clone, Copy)]
 pub struct Style {
@@ -90,3 +92,51 @@ pub fn is_printable(c: char) -> bool {
         true // TODO
     }
 }
+
+// The mode of the console is controlled through the stdin handle
+pub fn mode() -> Option<u8> {
+    let mut buf = [0];
+    syscall::control(0, ControlRequest::GetConsoleMode, &mut buf).map(|_| buf[0])
+}
+
+pub fn set_mode(mode: u8) -> Result<(), ()> {
+    let mut buf = [mode];
+    syscall::control(0, ControlRequest::SetConsoleMode, &mut buf).map(|_| ()).ok_or(())
+}
+
+pub fn enable_echo() {
+    if let Some(mode) = mode() {
+        set_mode(mode | ECHO_MODE).ok();
+    }
+}
+
+pub fn disable_echo() {
+    if let Some(mode) = mode() {
+        set_mode(mode & !ECHO_MODE).ok();
+    }
+}
+
+pub fn enable_raw() {
+    if let Some(mode) = mode() {
+        set_mode(mode | RAW_MODE).ok();
+    }
+}
+
+pub fn disable_raw() {
+    if let Some(mode) = mode() {
+        set_mode(mode & !RAW_MODE).ok();
+    }
+}
+
+// Return the number of columns and rows of the terminal on stdout, falling
+// back to the default size when stdout is not a console
+pub fn size() -> (usize, usize) {
+    let mut buf = [0; 4];
+    if syscall::control(1, ControlRequest::GetConsoleSize, &mut buf).is_some() {
+        let cols = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+        let rows = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+        (cols, rows)
+    } else {
+        (80, 25)
+    }
+}
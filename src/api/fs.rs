@@ -1,13 +1,13 @@
 use crate::sys;
 use crate::sys::fs::OpenFlag;
+use crate::api::path;
 use crate::api::syscall;
 
-use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use alloc::vec;
 
-pub use crate::sys::fs::{FileInfo, DeviceType};
+pub use crate::sys::fs::{ControlRequest, FileInfo, DeviceType};
 
 pub trait FileIO {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()>;
@@ -33,15 +33,10 @@ pub fn filename(pathname: &str) -> &str {
     &pathname[i..n]
 }
 
-// Transform "foo.txt" into "/path/to/foo.txt"
+// Transform "foo.txt" into "/path/to/foo.txt" and "../bar.txt" into
+// "/path/bar.txt" from the current dir of the process
 pub fn realpath(pathname: &str) -> String {
-    if pathname.starts_with('/') {
-        pathname.into()
-    } else {
-        let dirname = sys::process::dir();
-        let sep = if dirname.ends_with('/') { "" } else { "/" };
-        format!("{}{}{}", dirname, sep, pathname)
-    }
+    path::join(&sys::process::dir(), pathname)
 }
 
 pub fn exists(path: &str) -> bool {
@@ -94,6 +89,48 @@ pub fn create_device(path: &str, kind: DeviceType) -> Option<usize> {
     None
 }
 
+// Create a symbolic link to the target path
+pub fn create_link(path: &str, target: &str) -> Option<usize> {
+    let flags = OpenFlag::Create as usize | OpenFlag::Link as usize;
+    if let Some(handle) = syscall::open(path, flags) {
+        if syscall::write(handle, target.as_bytes()).is_some() {
+            return Some(handle);
+        }
+        syscall::close(handle);
+    }
+    None
+}
+
+// Return the target path of a symbolic link
+pub fn read_link(path: &str) -> Result<String, ()> {
+    let flags = OpenFlag::Link as usize;
+    if let Some(handle) = syscall::open(path, flags) {
+        let mut buf = vec![0; 512];
+        let res = syscall::read(handle, &mut buf);
+        syscall::close(handle);
+        if let Some(bytes) = res {
+            buf.resize(bytes, 0);
+            return Ok(String::from_utf8_lossy(&buf).to_string());
+        }
+    }
+    Err(())
+}
+
+pub fn link(source: &str, dest: &str) -> Result<(), ()> {
+    syscall::link(source, dest)
+}
+
+pub fn rename(source: &str, dest: &str) -> Result<(), ()> {
+    syscall::rename(source, dest)
+}
+
+// Subscribe to the changes of a path, or of the entries of a dir, and return
+// a handle from which each event is read as a line like "create /tmp/foo.txt"
+pub fn watch(path: &str) -> Option<usize> {
+    let flags = OpenFlag::Watch as usize;
+    syscall::open(path, flags)
+}
+
 pub fn read(path: &str, buf: &mut [u8]) -> Result<usize, ()> {
     if let Some(info) = syscall::info(&path) {
         let res = if info.is_device() { open_device(&path) } else { open_file(&path) };
@@ -162,23 +199,48 @@ pub fn reopen(path: &str, handle: usize) -> Result<usize, ()> {
     Err(())
 }
 
-pub fn read_dir(path: &str) -> Result<Vec<FileInfo>, ()> {
+// Iterate over the entries of a dir, reading them one by one from its handle
+pub struct ReadDir {
+    handle: Option<usize>,
+    buf: Vec<u8>,
+}
+
+impl ReadDir {
+    fn close(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            syscall::close(handle);
+        }
+    }
+}
+
+impl Iterator for ReadDir {
+    type Item = FileInfo;
+
+    fn next(&mut self) -> Option<FileInfo> {
+        let handle = self.handle?;
+        let info = match syscall::read_dir(handle, &mut self.buf) {
+            Some(n) if n > 0 => FileInfo::parse(&self.buf[0..n]),
+            _ => None,
+        };
+        if info.is_none() {
+            self.close();
+        }
+        info
+    }
+}
+
+impl Drop for ReadDir {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+pub fn read_dir(path: &str) -> Result<ReadDir, ()> {
     if let Some(info) = syscall::info(&path) {
         if info.is_dir() {
-            if let Ok(buf) = read_to_bytes(path) {
-                let mut res = Vec::new();
-                let mut i = 0;
-                let n = buf.len();
-                while i < n {
-                    let j = i + 14 + buf[i + 13] as usize;
-                    if j > n {
-                        break;
-                    }
-                    let info = FileInfo::from(&buf[i..j]);
-                    res.push(info);
-                    i = j;
-                }
-                return Ok(res);
+            if let Some(handle) = open_dir(&path) {
+                let buf = vec![0; 512];
+                return Ok(ReadDir { handle: Some(handle), buf });
             }
         }
     }
@@ -40,11 +40,13 @@ pub mod console;
 pub mod font;
 pub mod fs;
 pub mod io;
+pub mod path;
 pub mod process;
 pub mod prompt;
 pub mod random;
 pub mod regex;
 pub mod syscall;
+pub mod tar;
 pub mod time;
 pub mod vga;
-// TODO: add mod wildcard
+pub mod wildcard;
@@ -0,0 +1,60 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+// Remove the "." and ".." components, the repeated slashes, and the trailing
+// slash of a path without following links. A ".." at the root of an absolute
+// path stays at the root while the leading ".." of a relative path are kept.
+pub fn normalize(path: &str) -> String {
+    let is_absolute = path.starts_with('/');
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if parts.last().map_or(false, |last| *last != "..") {
+                    parts.pop();
+                } else if !is_absolute {
+                    parts.push(part);
+                }
+            }
+            _ => parts.push(part),
+        }
+    }
+    let path = parts.join("/");
+    if is_absolute {
+        format!("/{}", path)
+    } else if path.is_empty() {
+        ".".into()
+    } else {
+        path
+    }
+}
+
+// Join a path to a base dir unless it is absolute, and normalize the result
+pub fn join(base: &str, path: &str) -> String {
+    if path.starts_with('/') {
+        normalize(path)
+    } else {
+        normalize(&format!("{}/{}", base, path))
+    }
+}
+
+#[test_case]
+fn test_path() {
+    assert_eq!(normalize("/"), "/");
+    assert_eq!(normalize("//usr///alice/"), "/usr/alice");
+    assert_eq!(normalize("/usr/./alice/.."), "/usr");
+    assert_eq!(normalize("/../.."), "/");
+    assert_eq!(normalize("./foo/../.."), "..");
+    assert_eq!(normalize("../../foo"), "../../foo");
+    assert_eq!(normalize("foo/."), "foo");
+    assert_eq!(normalize("."), ".");
+    assert_eq!(normalize(""), ".");
+
+    assert_eq!(join("/usr/alice", "foo.txt"), "/usr/alice/foo.txt");
+    assert_eq!(join("/usr/alice", "../bob/"), "/usr/bob");
+    assert_eq!(join("/usr/alice", "/tmp/./foo"), "/tmp/foo");
+    assert_eq!(join("/", ".."), "/");
+    assert_eq!(join("/", ""), "/");
+}
@@ -1,6 +1,6 @@
 use crate::syscall;
 use crate::sys::syscall::number::*;
-use crate::sys::fs::FileInfo;
+use crate::sys::fs::{ControlRequest, FileInfo};
 
 pub fn exit(code: usize) -> usize {
     unsafe { syscall!(EXIT, code as u64) }
@@ -31,6 +31,93 @@ pub fn delete(path: &str) -> Result<(), ()> {
     }
 }
 
+pub fn chmod(path: &str, mode: u16) -> Result<(), ()> {
+    let path_ptr = path.as_ptr() as usize;
+    let path_len = path.len() as usize;
+    let res = unsafe { syscall!(CHMOD, path_ptr, path_len, mode) } as isize;
+    if res.is_negative() {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn chown(path: &str, uid: u16) -> Result<(), ()> {
+    let path_ptr = path.as_ptr() as usize;
+    let path_len = path.len() as usize;
+    let res = unsafe { syscall!(CHOWN, path_ptr, path_len, uid) } as isize;
+    if res.is_negative() {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+// Set the access and modification times of a file
+pub fn touch(path: &str, time: u64) -> Result<(), ()> {
+    let path_ptr = path.as_ptr() as usize;
+    let path_len = path.len() as usize;
+    let res = unsafe { syscall!(TOUCH, path_ptr, path_len, time) } as isize;
+    if res.is_negative() {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+// Create a hard link to a file
+pub fn link(source: &str, dest: &str) -> Result<(), ()> {
+    let source_ptr = source.as_ptr() as usize;
+    let source_len = source.len() as usize;
+    let dest_ptr = dest.as_ptr() as usize;
+    let dest_len = dest.len() as usize;
+    let res = unsafe { syscall!(LINK, source_ptr, source_len, dest_ptr, dest_len) } as isize;
+    if res.is_negative() {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+// Mount a device on a dir
+pub fn mount(dev: &str, path: &str) -> Result<(), ()> {
+    let dev_ptr = dev.as_ptr() as usize;
+    let dev_len = dev.len() as usize;
+    let path_ptr = path.as_ptr() as usize;
+    let path_len = path.len() as usize;
+    let res = unsafe { syscall!(MOUNT, dev_ptr, dev_len, path_ptr, path_len) } as isize;
+    if res.is_negative() {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn umount(path: &str) -> Result<(), ()> {
+    let path_ptr = path.as_ptr() as usize;
+    let path_len = path.len() as usize;
+    let res = unsafe { syscall!(UMOUNT, path_ptr, path_len) } as isize;
+    if res.is_negative() {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+// Move a file or a dir to another path on the same filesystem
+pub fn rename(source: &str, dest: &str) -> Result<(), ()> {
+    let source_ptr = source.as_ptr() as usize;
+    let source_len = source.len() as usize;
+    let dest_ptr = dest.as_ptr() as usize;
+    let dest_len = dest.len() as usize;
+    let res = unsafe { syscall!(RENAME, source_ptr, source_len, dest_ptr, dest_len) } as isize;
+    if res.is_negative() {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
 pub fn info(path: &str) -> Option<FileInfo> {
     let path_ptr = path.as_ptr() as usize;
     let path_len = path.len() as usize;
@@ -75,6 +162,31 @@ pub fn read(handle: usize, buf: &mut [u8]) -> Option<usize> {
     }
 }
 
+// Read the next entry of a dir handle into a serialized file info
+pub fn read_dir(handle: usize, buf: &mut [u8]) -> Option<usize> {
+    let ptr = buf.as_ptr() as usize;
+    let len = buf.len() as usize;
+    let res = unsafe { syscall!(READ_DIR, handle, ptr, len) } as isize;
+    if res.is_negative() {
+        None
+    } else {
+        Some(res as usize)
+    }
+}
+
+// Send a control request to a device handle with its argument in the buffer,
+// and return the length of the result written back into it
+pub fn control(handle: usize, request: ControlRequest, buf: &mut [u8]) -> Option<usize> {
+    let ptr = buf.as_ptr() as usize;
+    let len = buf.len() as usize;
+    let res = unsafe { syscall!(CONTROL, handle, request as usize, ptr, len) } as isize;
+    if res.is_negative() {
+        None
+    } else {
+        Some(res as usize)
+    }
+}
+
 pub fn write(handle: usize, buf: &[u8]) -> Option<usize> {
     let ptr = buf.as_ptr() as usize;
     let len = buf.len() as usize;
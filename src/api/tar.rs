@@ -0,0 +1,268 @@
+use crate::api::fs;
+use crate::api::syscall;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+// Archives in the ustar format are made of a header block for each entry
+// followed by the contents of the files padded to the size of a block, and
+// two empty blocks at the end.
+//
+// Header structure:
+// 0..100 => name
+// 100..108 => mode
+// 108..116 => uid
+// 116..124 => gid
+// 124..136 => size
+// 136..148 => mtime
+// 148..156 => checksum
+// 156 => type
+// 157..257 => link target
+// 257..263 => magic
+// 263..265 => version
+// 345..500 => prefix of the name
+//
+// The numbers are stored in octal and the checksum is the sum of the bytes
+// of the header, with the checksum field filled with spaces.
+
+const BLOCK_SIZE: usize = 512;
+
+const TYPE_FILE: u8 = b'0';
+const TYPE_LINK: u8 = b'2';
+const TYPE_DIR: u8 = b'5';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Link,
+}
+
+// The target of a symbolic link is stored as its data like in MFS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub kind: EntryKind,
+    pub name: String,
+    pub mode: u16,
+    pub uid: u16,
+    pub time: u64,
+    pub data: Vec<u8>,
+}
+
+pub fn parse(buf: &[u8]) -> Result<Vec<Entry>, ()> {
+    let mut res = Vec::new();
+    let mut i = 0;
+    while i + BLOCK_SIZE <= buf.len() {
+        let header = &buf[i..(i + BLOCK_SIZE)];
+        if header.iter().all(|b| *b == 0) {
+            break; // End of archive
+        }
+        if &header[257..262] != b"ustar" || read_octal(&header[148..156])? != checksum(header) {
+            return Err(());
+        }
+        let mut name = read_str(&header[0..100]);
+        let prefix = read_str(&header[345..500]);
+        if !prefix.is_empty() {
+            name = format!("{}/{}", prefix, name);
+        }
+        let mode = read_octal(&header[100..108])? as u16;
+        let uid = read_octal(&header[108..116])? as u16;
+        let size = read_octal(&header[124..136])? as usize;
+        let time = read_octal(&header[136..148])?;
+        i += BLOCK_SIZE;
+        let (kind, data) = match header[156] {
+            TYPE_FILE | 0 => {
+                if i + size > buf.len() {
+                    return Err(());
+                }
+                (EntryKind::File, buf[i..(i + size)].to_vec())
+            }
+            TYPE_DIR => (EntryKind::Dir, Vec::new()),
+            TYPE_LINK => (EntryKind::Link, read_str(&header[157..257]).into_bytes()),
+            _ => return Err(()), // Unsupported type
+        };
+        if kind == EntryKind::File {
+            i += padded_len(size);
+        }
+        let name = name.trim_end_matches('/').to_string();
+        res.push(Entry { kind, name, mode, uid, time, data });
+    }
+    Ok(res)
+}
+
+pub fn build(entries: &[Entry]) -> Result<Vec<u8>, ()> {
+    let mut res = Vec::new();
+    for entry in entries {
+        let mut header = vec![0; BLOCK_SIZE];
+        let (prefix, name) = split_name(&entry.name, entry.kind == EntryKind::Dir)?;
+        header[0..name.len()].copy_from_slice(name.as_bytes());
+        header[345..(345 + prefix.len())].copy_from_slice(prefix.as_bytes());
+        write_octal(&mut header[100..108], entry.mode as u64);
+        write_octal(&mut header[108..116], entry.uid as u64);
+        write_octal(&mut header[116..124], 0);
+        let size = if entry.kind == EntryKind::File { entry.data.len() } else { 0 };
+        write_octal(&mut header[124..136], size as u64);
+        write_octal(&mut header[136..148], entry.time);
+        header[156] = match entry.kind {
+            EntryKind::File => TYPE_FILE,
+            EntryKind::Dir => TYPE_DIR,
+            EntryKind::Link => {
+                if entry.data.len() > 100 {
+                    return Err(());
+                }
+                header[157..(157 + entry.data.len())].copy_from_slice(&entry.data);
+                TYPE_LINK
+            }
+        };
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        let sum = checksum(&header);
+        write_octal(&mut header[148..155], sum);
+        header[155] = b' ';
+        res.extend_from_slice(&header);
+        if entry.kind == EntryKind::File {
+            res.extend_from_slice(&entry.data);
+            res.resize(res.len() + padded_len(size) - size, 0);
+        }
+    }
+    res.resize(res.len() + 2 * BLOCK_SIZE, 0);
+    Ok(res)
+}
+
+// Return the entries of the tree at the given path, named relatively to the
+// parent dir of this path.
+pub fn collect(path: &str) -> Result<Vec<Entry>, ()> {
+    let path = fs::realpath(path);
+    let path = path.trim_end_matches('/');
+    let info = syscall::info(path).ok_or(())?;
+    let mut res = Vec::new();
+    let name = fs::filename(path);
+    if name.is_empty() {
+        for info in fs::read_dir("/")? {
+            collect_entry(&format!("/{}", info.name()), &info.name(), &info, &mut res)?;
+        }
+    } else {
+        collect_entry(path, name, &info, &mut res)?;
+    }
+    Ok(res)
+}
+
+fn collect_entry(path: &str, name: &str, info: &fs::FileInfo, res: &mut Vec<Entry>) -> Result<(), ()> {
+    let name = name.to_string();
+    let mode = info.mode();
+    let uid = info.uid();
+    let time = info.time();
+    if info.is_link() {
+        let data = fs::read_link(path)?.into_bytes();
+        res.push(Entry { kind: EntryKind::Link, name, mode, uid, time, data });
+    } else if info.is_dir() {
+        res.push(Entry { kind: EntryKind::Dir, name: name.clone(), mode, uid, time, data: Vec::new() });
+        for child in fs::read_dir(path)? {
+            let child_path = format!("{}/{}", path, child.name());
+            let child_name = format!("{}/{}", name, child.name());
+            collect_entry(&child_path, &child_name, &child, res)?;
+        }
+    } else if info.is_file() {
+        let data = fs::read_to_bytes(path)?;
+        res.push(Entry { kind: EntryKind::File, name, mode, uid, time, data });
+    } // Devices are skipped
+    Ok(())
+}
+
+// Create the file, dir or link of an entry in the given dir, and return its
+// path. Existing dirs are kept and existing files are overwritten.
+pub fn unpack(entry: &Entry, dest: &str) -> Result<String, ()> {
+    let name = entry.name.trim_start_matches('/');
+    if name.split('/').any(|s| s == "..") {
+        return Err(()); // Outside of the destination
+    }
+    let path = format!("{}/{}", fs::realpath(dest).trim_end_matches('/'), name);
+    match entry.kind {
+        EntryKind::Dir => {
+            if !fs::is_dir(&path) {
+                let handle = fs::create_dir(&path).ok_or(())?;
+                syscall::close(handle);
+            }
+        }
+        EntryKind::File => {
+            fs::write(&path, &entry.data)?;
+        }
+        EntryKind::Link => {
+            let target = String::from_utf8_lossy(&entry.data);
+            let handle = fs::create_link(&path, &target).ok_or(())?;
+            syscall::close(handle);
+        }
+    }
+    if entry.kind != EntryKind::Link {
+        // The metadata cannot be stored on every filesystem
+        syscall::chmod(&path, entry.mode).ok();
+        if entry.time > 0 {
+            syscall::touch(&path, entry.time).ok();
+        }
+    }
+    Ok(path)
+}
+
+// Split a name longer than 100 bytes at a slash into a prefix and a name
+fn split_name(name: &str, is_dir: bool) -> Result<(String, String), ()> {
+    let name = if is_dir { format!("{}/", name) } else { name.to_string() };
+    if name.len() <= 100 {
+        return Ok((String::new(), name));
+    }
+    let n = name.len();
+    for (i, _) in name.match_indices('/') {
+        if i <= 155 && n - i - 1 <= 100 {
+            return Ok((name[..i].to_string(), name[(i + 1)..].to_string()));
+        }
+    }
+    Err(())
+}
+
+fn checksum(header: &[u8]) -> u64 {
+    header.iter().enumerate().map(|(i, b)| {
+        if (148..156).contains(&i) { b' ' as u64 } else { *b as u64 }
+    }).sum()
+}
+
+fn padded_len(size: usize) -> usize {
+    (size + BLOCK_SIZE - 1) / BLOCK_SIZE * BLOCK_SIZE
+}
+
+fn read_str(buf: &[u8]) -> String {
+    let n = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..n]).to_string()
+}
+
+fn read_octal(buf: &[u8]) -> Result<u64, ()> {
+    let s = read_str(buf);
+    let s = s.trim_matches(|c: char| c == ' ' || c == '\0');
+    if s.is_empty() {
+        Ok(0)
+    } else {
+        u64::from_str_radix(s, 8).map_err(|_| ())
+    }
+}
+
+// Write a number in octal padded with zeros and followed by a null byte
+fn write_octal(buf: &mut [u8], n: u64) {
+    let width = buf.len() - 1;
+    let s = format!("{:0width$o}", n, width = width);
+    buf[..width].copy_from_slice(&s.as_bytes()[(s.len() - width)..]);
+    buf[width] = 0;
+}
+
+#[test_case]
+fn test_tar() {
+    let entries = vec![
+        Entry { kind: EntryKind::Dir, name: "tmp".into(), mode: 0o755, uid: 0, time: 1000, data: Vec::new() },
+        Entry { kind: EntryKind::File, name: "tmp/alice.txt".into(), mode: 0o644, uid: 1, time: 2000, data: b"Hello, World!".to_vec() },
+        Entry { kind: EntryKind::Link, name: "tmp/bob.txt".into(), mode: 0o777, uid: 0, time: 3000, data: b"alice.txt".to_vec() },
+        Entry { kind: EntryKind::File, name: format!("tmp/{}/test", "a".repeat(120)), mode: 0o644, uid: 0, time: 0, data: vec![42; 600] },
+    ];
+    let buf = build(&entries).unwrap();
+    assert_eq!(buf.len(), 4 * BLOCK_SIZE + BLOCK_SIZE + 2 * BLOCK_SIZE + 2 * BLOCK_SIZE);
+    assert_eq!(parse(&buf), Ok(entries));
+    assert_eq!(parse(&buf[1..]), Err(()));
+}
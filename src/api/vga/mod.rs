@@ -1,5 +1,31 @@
+use crate::api::syscall;
+use crate::sys::fs::ControlRequest;
+use alloc::vec::Vec;
+
 pub mod color;
 pub mod palette;
 
 pub use color::Color;
 pub use palette::Palette;
+
+// The palette and the font of the VGA display are set through the stdout
+// handle of the console
+pub fn set_palette(palette: &Palette) -> Result<(), ()> {
+    let mut buf = Vec::with_capacity(palette.colors.len() * 3);
+    for &(r, g, b) in palette.colors.iter() {
+        buf.extend_from_slice(&[r, g, b]);
+    }
+    syscall::control(1, ControlRequest::SetVgaPalette, &mut buf).map(|_| ()).ok_or(())
+}
+
+// Show or hide the cell of the mouse cursor
+pub fn set_mouse_cursor(enabled: bool) -> Result<(), ()> {
+    let mut buf = [enabled as u8];
+    syscall::control(1, ControlRequest::SetMouseCursor, &mut buf).map(|_| ()).ok_or(())
+}
+
+// Set a font from the bytes of a PSF file
+pub fn set_font(buf: &[u8]) -> Result<(), ()> {
+    let mut buf = buf.to_vec();
+    syscall::control(1, ControlRequest::SetVgaFont, &mut buf).map(|_| ()).ok_or(())
+}
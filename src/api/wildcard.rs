@@ -0,0 +1,183 @@
+use crate::api::fs;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+// Return true if the string contains a wildcard character
+pub fn is_glob(s: &str) -> bool {
+    s.contains(|c| c == '*' || c == '?' || c == '[')
+}
+
+// Match a name with a pattern where `*` matches any sequence of characters,
+// `?` matches a single character, and `[...]` matches a single character in
+// a set like `[abc]` or a range like `[a-z]`, or not in it with `[!...]`.
+pub fn is_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    match_chars(&pattern, &name)
+}
+
+// Match the pattern from left to right and go back to the last star when the
+// name does not match, to let it match one more character.
+fn match_chars(p: &[char], s: &[char]) -> bool {
+    let (mut i, mut j) = (0, 0);
+    let mut star = None; // Positions in the pattern and the name after a star
+    while j < s.len() {
+        if p.get(i) == Some(&'*') {
+            i += 1;
+            star = Some((i, j));
+        } else if let Some(n) = match_token(&p[i..], s[j]) {
+            i += n;
+            j += 1;
+        } else if let Some((k, l)) = star {
+            i = k;
+            j = l + 1;
+            star = Some((k, j));
+        } else {
+            return false;
+        }
+    }
+    p[i..].iter().all(|&c| c == '*')
+}
+
+// Match a character with the token at the start of a pattern and return the
+// length of the token
+fn match_token(p: &[char], c: char) -> Option<usize> {
+    match p.first()? {
+        '?' => Some(1),
+        '[' => match match_class(&p[1..], c) {
+            Some((true, n)) => Some(n + 1),
+            Some((false, _)) => None,
+            None if c == '[' => Some(1), // Unclosed
+            None => None,
+        },
+        x if *x == c => Some(1),
+        _ => None,
+    }
+}
+
+// Match a character with the set following a `[` in a pattern and return the
+// result with the length of the set, or None if the set is not closed.
+fn match_class(p: &[char], c: char) -> Option<(bool, usize)> {
+    let negate = matches!(p.first(), Some('!') | Some('^'));
+    let mut i = if negate { 1 } else { 0 };
+    let start = i;
+    let mut found = false;
+    while i < p.len() {
+        if p[i] == ']' && i > start {
+            return Some((found != negate, i + 1));
+        }
+        if i + 2 < p.len() && p[i + 1] == '-' && p[i + 2] != ']' {
+            found |= p[i] <= c && c <= p[i + 2];
+            i += 3;
+        } else {
+            found |= p[i] == c;
+            i += 1;
+        }
+    }
+    None
+}
+
+// Expand a pattern into the sorted list of existing paths matching it, where
+// each component of the path can contain wildcards and `**` matches any
+// number of dirs. Relative patterns give paths relative to the current dir,
+// and dot files are only matched by patterns starting with a dot.
+pub fn expand(pattern: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    paths.push(if pattern.starts_with('/') { "/".to_string() } else { String::new() });
+    let parts: Vec<&str> = pattern.split('/').filter(|part| !part.is_empty()).collect();
+    for part in parts {
+        let mut next = Vec::new();
+        for path in &paths {
+            if part == "**" {
+                next.push(path.clone());
+                walk_dirs(path, &mut next);
+            } else if is_glob(part) {
+                for name in read_dir(path) {
+                    if is_match(part, &name) && (part.starts_with('.') || !name.starts_with('.')) {
+                        next.push(join(path, &name));
+                    }
+                }
+            } else {
+                next.push(join(path, part));
+            }
+        }
+        paths = next;
+    }
+    let mut res: Vec<String> = paths.into_iter().filter(|path| {
+        !path.is_empty() && (fs::exists(path) || fs::read_link(path).is_ok())
+    }).collect();
+    res.sort();
+    res.dedup();
+    res
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+fn read_dir(path: &str) -> Vec<String> {
+    let path = if path.is_empty() { "." } else { path };
+    match fs::read_dir(path) {
+        Ok(entries) => entries.map(|entry| entry.name()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// Add the subdirs of a dir recursively, except those starting with a dot
+fn walk_dirs(path: &str, paths: &mut Vec<String>) {
+    let dir = if path.is_empty() { "." } else { path };
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries {
+            if entry.is_dir() && !entry.name().starts_with('.') {
+                let subdir = join(path, &entry.name());
+                paths.push(subdir.clone());
+                walk_dirs(&subdir, paths);
+            }
+        }
+    }
+}
+
+#[test_case]
+fn test_wildcard() {
+    assert!(is_match("*.txt", "foo.txt"));
+    assert!(is_match("*", ""));
+    assert!(is_match("f?o*", "foo.txt"));
+    assert!(is_match("[a-c]at", "bat"));
+    assert!(is_match("[!a-c]at", "rat"));
+    assert!(is_match("[]]", "]"));
+    assert!(is_match("[ab", "[ab"));
+    assert!(!is_match("*.txt", "foo.lsp"));
+    assert!(!is_match("?", ""));
+    assert!(!is_match("[a-c]at", "rat"));
+    assert!(is_match("*a*b", "xaxb"));
+    assert!(!is_match("a*a*a*a*a*a*a*a*b", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"));
+}
+
+#[test_case]
+fn test_expand() {
+    use crate::sys::fs::{dismount, format_mem, mount_mem};
+    mount_mem();
+    format_mem();
+    fs::create_dir("/tmp");
+    fs::create_dir("/tmp/log");
+    fs::write("/tmp/a.log", b"a").ok();
+    fs::write("/tmp/b.txt", b"b").ok();
+    fs::write("/tmp/log/c.log", b"c").ok();
+    fs::write("/tmp/.d.log", b"d").ok();
+
+    assert_eq!(expand("/tmp/*.log"), ["/tmp/a.log"]);
+    assert_eq!(expand("/tmp/.*.log"), ["/tmp/.d.log"]);
+    assert_eq!(expand("/tmp/**/*.log"), ["/tmp/a.log", "/tmp/log/c.log"]);
+    assert_eq!(expand("/tmp/*.lsp"), Vec::<String>::new());
+    assert_eq!(expand("//tmp/[ab].*"), ["/tmp/a.log", "/tmp/b.txt"]);
+
+    dismount();
+}
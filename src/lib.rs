@@ -29,6 +29,7 @@ pub fn init(boot_info: &'static BootInfo) {
     sys::pic::init(); // Enable interrupts
     sys::serial::init();
     sys::keyboard::init();
+    sys::mouse::init();
     sys::time::init();
 
     log!("MOROS v{}\n", env!("CARGO_PKG_VERSION"));
@@ -37,7 +38,9 @@ pub fn init(boot_info: &'static BootInfo) {
     sys::pci::init(); // Require MEM
     sys::net::init(); // Require PCI
     sys::ata::init();
-    sys::fs::init(); // Require ATA
+    sys::ahci::init(); // Require PCI
+    sys::virtio::init(); // Require PCI
+    sys::fs::init(); // Require ATA, AHCI and VirtIO
     sys::clock::init(); // Require MEM
 }
 
@@ -35,6 +35,12 @@ fn user_boot() {
             println!("MFS is not mounted to '/'");
         }
         println!("Running console in diskless mode");
+        if !sys::fs::is_mounted() {
+            // Temporary files are kept in memory on a RAM root
+            if sys::fs::mount_mem_disk("/", sys::fs::mem_size()).is_ok() {
+                sys::fs::Dir::create("/tmp");
+            }
+        }
         usr::shell::main(&["shell"]);
     }
 }
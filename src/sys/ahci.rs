@@ -0,0 +1,383 @@
+use crate::sys;
+use crate::sys::allocator::PhysBuf;
+use alloc::string::String;
+use alloc::vec::Vec;
+use bit_field::BitField;
+use core::fmt;
+use core::hint::spin_loop;
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::PhysAddr;
+
+// SATA drives attached to an AHCI controller
+//
+// See "Serial ATA Advanced Host Controller Interface (AHCI) 1.3.1" (2013)
+
+pub const BLOCK_SIZE: usize = 512;
+
+// Maximum number of blocks transferred by a single command
+pub const MAX_BLOCKS: usize = 64;
+
+// Signature of a SATA drive in the port registers
+const SATA_SIGNATURE: u32 = 0x0000_0101;
+
+// Layout of the memory shared with a port, where the command list must be
+// aligned on 1 KB, the received FIS on 256 bytes, and the command table on
+// 128 bytes.
+const COMMAND_LIST_OFFSET: usize = 0;
+const FIS_OFFSET: usize = 1024;
+const COMMAND_TABLE_OFFSET: usize = 1280;
+const PRDT_OFFSET: usize = COMMAND_TABLE_OFFSET + 0x80;
+const PORT_MEM_SIZE: usize = PRDT_OFFSET + 16;
+
+// Host controller registers, followed by the registers of 32 ports
+const HBA_GHC: usize = 0x04;
+const HBA_PI: usize = 0x0C;
+const HBA_SIZE: usize = 0x100 + 32 * 0x80;
+
+// Port registers
+const PORT_CLB: usize = 0x00;
+const PORT_FB: usize = 0x08;
+const PORT_IS: usize = 0x10;
+const PORT_CMD: usize = 0x18;
+const PORT_TFD: usize = 0x20;
+const PORT_SIG: usize = 0x24;
+const PORT_SSTS: usize = 0x28;
+const PORT_SERR: usize = 0x30;
+const PORT_CI: usize = 0x38;
+
+// Bits of the port command register
+const CMD_ST: usize = 0; // Start
+const CMD_FRE: usize = 4; // FIS receive enable
+const CMD_FR: usize = 14; // FIS receive running
+const CMD_CR: usize = 15; // Command list running
+
+// Bits of the task file data register
+const TFD_ERR: usize = 0;
+const TFD_DRQ: usize = 3;
+const TFD_BSY: usize = 7;
+
+// Task file error status in the port interrupt status register
+const IS_TFES: usize = 30;
+
+const FIS_TYPE_REG_H2D: u8 = 0x27;
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    ReadDmaExt = 0x25,
+    WriteDmaExt = 0x35,
+    Identify = 0xEC,
+}
+
+lazy_static! {
+    static ref PORTS: Mutex<Vec<Port>> = Mutex::new(Vec::new());
+}
+
+// Registers of the controller are memory mapped at the address given by the
+// BAR5 of its PCI configuration, which is mapped with the cache disabled.
+#[derive(Clone, Copy)]
+struct Registers {
+    addr: u64,
+}
+
+impl Registers {
+    fn read(&self, offset: usize) -> u32 {
+        let ptr = (self.addr + offset as u64) as *const u32;
+        unsafe { core::ptr::read_volatile(ptr) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        let ptr = (self.addr + offset as u64) as *mut u32;
+        unsafe { core::ptr::write_volatile(ptr, value) }
+    }
+}
+
+struct Port {
+    regs: Registers,
+    mem: PhysBuf,
+    offset: usize, // Aligned start of the command list in the buffer
+    buf: PhysBuf, // Data of a command
+    blocks: u64,
+    model: String,
+    serial: String,
+}
+
+impl Port {
+    fn new(regs: Registers) -> Option<Self> {
+        let mem = PhysBuf::new(PORT_MEM_SIZE + 1024);
+        let offset = (1024 - (mem.addr() as usize % 1024)) % 1024;
+        let buf = PhysBuf::new(MAX_BLOCKS * BLOCK_SIZE);
+        let mut port = Self {
+            regs, mem, offset, buf, blocks: 0, model: String::new(), serial: String::new()
+        };
+        port.stop().ok()?;
+        let addr = port.mem.addr() + port.offset as u64;
+        port.regs.write(PORT_CLB, (addr + COMMAND_LIST_OFFSET as u64) as u32);
+        port.regs.write(PORT_CLB + 4, ((addr + COMMAND_LIST_OFFSET as u64) >> 32) as u32);
+        port.regs.write(PORT_FB, (addr + FIS_OFFSET as u64) as u32);
+        port.regs.write(PORT_FB + 4, ((addr + FIS_OFFSET as u64) >> 32) as u32);
+        port.regs.write(PORT_SERR, 0xFFFF_FFFF); // Write 1s to clear the bits
+        port.regs.write(PORT_IS, 0xFFFF_FFFF);
+        port.start();
+        port.identify().ok()?;
+        Some(port)
+    }
+
+    fn stop(&mut self) -> Result<(), ()> {
+        let mut cmd = self.regs.read(PORT_CMD);
+        cmd.set_bit(CMD_ST, false);
+        cmd.set_bit(CMD_FRE, false);
+        self.regs.write(PORT_CMD, cmd);
+        self.wait(PORT_CMD, |cmd| !cmd.get_bit(CMD_CR) && !cmd.get_bit(CMD_FR))
+    }
+
+    fn start(&mut self) {
+        let mut cmd = self.regs.read(PORT_CMD);
+        cmd.set_bit(CMD_FRE, true);
+        cmd.set_bit(CMD_ST, true);
+        self.regs.write(PORT_CMD, cmd);
+    }
+
+    fn wait(&self, offset: usize, f: impl Fn(u32) -> bool) -> Result<(), ()> {
+        let start = sys::clock::uptime();
+        while !f(self.regs.read(offset)) {
+            if sys::clock::uptime() - start > 1.0 {
+                debug!("AHCI hanged while polling port register {:#X}", offset);
+                return Err(());
+            }
+            spin_loop();
+        }
+        Ok(())
+    }
+
+    fn identify(&mut self) -> Result<(), ()> {
+        self.send_command(Command::Identify, 0, BLOCK_SIZE)?;
+        let mut res = [0; 256];
+        for (i, word) in res.iter_mut().enumerate() {
+            *word = u16::from_le_bytes([self.buf[2 * i], self.buf[2 * i + 1]]);
+        }
+        let (model, serial, blocks) = parse_identify(&res);
+        self.model = model;
+        self.serial = serial;
+        self.blocks = blocks;
+        Ok(())
+    }
+
+    // Fill the first slot of the command list with a command FIS and a
+    // single region of data, then issue it and poll its completion.
+    fn send_command(&mut self, cmd: Command, block: u64, len: usize) -> Result<(), ()> {
+        let is_write = cmd == Command::WriteDmaExt;
+        let count = (len / BLOCK_SIZE) as u16;
+        let addr = self.mem.addr() + self.offset as u64;
+        let table = addr + COMMAND_TABLE_OFFSET as u64;
+
+        // Command header
+        let mut flags = 5u32; // Length of the command FIS in dwords
+        flags.set_bit(6, is_write);
+        flags.set_bits(16..32, 1); // Number of PRDT entries
+        let i = self.offset + COMMAND_LIST_OFFSET;
+        self.mem[i..i + 4].copy_from_slice(&flags.to_le_bytes());
+        self.mem[i + 4..i + 8].copy_from_slice(&0u32.to_le_bytes()); // Bytes transferred
+        self.mem[i + 8..i + 16].copy_from_slice(&table.to_le_bytes());
+
+        // Command FIS
+        let i = self.offset + COMMAND_TABLE_OFFSET;
+        let fis = command_fis(cmd, block, count);
+        self.mem[i..i + fis.len()].copy_from_slice(&fis);
+
+        // Physical region descriptor
+        let i = self.offset + PRDT_OFFSET;
+        self.mem[i..i + 8].copy_from_slice(&self.buf.addr().to_le_bytes());
+        self.mem[i + 8..i + 12].copy_from_slice(&0u32.to_le_bytes());
+        self.mem[i + 12..i + 16].copy_from_slice(&((len - 1) as u32).to_le_bytes());
+
+        self.wait(PORT_TFD, |tfd| !tfd.get_bit(TFD_BSY) && !tfd.get_bit(TFD_DRQ))?;
+        self.regs.write(PORT_IS, 0xFFFF_FFFF);
+        self.regs.write(PORT_CI, 1);
+        let res = self.wait(PORT_CI, |ci| !ci.get_bit(0) || self.regs.read(PORT_IS).get_bit(IS_TFES));
+        if res.is_err() || self.regs.read(PORT_IS).get_bit(IS_TFES) || self.regs.read(PORT_TFD).get_bit(TFD_ERR) {
+            debug!("AHCI command error: {:#X}", self.regs.read(PORT_TFD));
+            return Err(());
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, block: u64, buf: &mut [u8]) -> Result<(), ()> {
+        let n = sys::fs::blocks_count(buf.len(), BLOCK_SIZE, MAX_BLOCKS)? * BLOCK_SIZE;
+        self.check_range(block, n)?;
+        self.send_command(Command::ReadDmaExt, block, n)?;
+        buf.copy_from_slice(&self.buf[0..n]);
+        Ok(())
+    }
+
+    fn write(&mut self, block: u64, buf: &[u8]) -> Result<(), ()> {
+        let n = sys::fs::blocks_count(buf.len(), BLOCK_SIZE, MAX_BLOCKS)? * BLOCK_SIZE;
+        self.check_range(block, n)?;
+        self.buf[0..n].copy_from_slice(buf);
+        self.send_command(Command::WriteDmaExt, block, n)
+    }
+
+    fn check_range(&self, block: u64, len: usize) -> Result<(), ()> {
+        if block + (len / BLOCK_SIZE) as u64 > self.blocks {
+            return Err(());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Drive {
+    pub id: usize,
+    blocks: u64,
+    model: String,
+    serial: String,
+}
+
+impl Drive {
+    pub fn open(id: usize) -> Option<Self> {
+        let ports = PORTS.lock();
+        ports.get(id).map(|port| {
+            let model = port.model.clone();
+            let serial = port.serial.clone();
+            Self { id, blocks: port.blocks, model, serial }
+        })
+    }
+
+    pub const fn block_size(&self) -> u32 {
+        BLOCK_SIZE as u32
+    }
+
+    pub fn block_count(&self) -> u64 {
+        self.blocks
+    }
+}
+
+impl fmt::Display for Drive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes = self.block_size() as usize * self.block_count() as usize;
+        let (size, unit) = sys::fs::humanized_size(bytes);
+        write!(f, "{} {} ({} {})", self.model, self.serial, size, unit)
+    }
+}
+
+pub fn list() -> Vec<Drive> {
+    let n = PORTS.lock().len();
+    (0..n).filter_map(Drive::open).collect()
+}
+
+// Read blocks into a buffer of a multiple of the block size, splitting the
+// transfer into commands of up to `MAX_BLOCKS`.
+pub fn read(id: usize, block: u64, buf: &mut [u8]) -> Result<(), ()> {
+    let mut ports = PORTS.lock();
+    let port = ports.get_mut(id).ok_or(())?;
+    sys::fs::read_chunks(block, buf, MAX_BLOCKS * BLOCK_SIZE, BLOCK_SIZE, |addr, chunk| {
+        port.read(addr, chunk)
+    })
+}
+
+pub fn write(id: usize, block: u64, buf: &[u8]) -> Result<(), ()> {
+    let mut ports = PORTS.lock();
+    let port = ports.get_mut(id).ok_or(())?;
+    sys::fs::write_chunks(block, buf, MAX_BLOCKS * BLOCK_SIZE, BLOCK_SIZE, |addr, chunk| {
+        port.write(addr, chunk)
+    })
+}
+
+pub fn init() {
+    if let Some(mut pci_device) = sys::pci::find_class(0x01, 0x06) {
+        if pci_device.prog != 0x01 { // AHCI 1.0
+            return;
+        }
+        pci_device.enable_bus_mastering();
+
+        let abar = (pci_device.base_addresses[5] & 0xFFFF_FFF0) as u64;
+        let addr = match sys::mem::map_mmio(PhysAddr::new(abar), HBA_SIZE as u64) {
+            Some(addr) => addr.as_u64(),
+            None => return,
+        };
+        let hba = Registers { addr };
+        let mut ghc = hba.read(HBA_GHC);
+        ghc.set_bit(31, true); // AHCI enable
+        hba.write(HBA_GHC, ghc);
+
+        let implemented = hba.read(HBA_PI);
+        let mut ports = PORTS.lock();
+        for i in 0..32 {
+            if !implemented.get_bit(i) {
+                continue;
+            }
+            let regs = Registers { addr: addr + 0x100 + 0x80 * i as u64 };
+            let ssts = regs.read(PORT_SSTS);
+            let is_present = ssts.get_bits(0..4) == 3 && ssts.get_bits(8..12) == 1;
+            if is_present && regs.read(PORT_SIG) == SATA_SIGNATURE {
+                if let Some(port) = Port::new(regs) {
+                    ports.push(port);
+                }
+            }
+        }
+    }
+    for drive in list() {
+        log!("AHCI {} {}\n", drive.id, drive);
+    }
+}
+
+// Return the model, the serial number, and the number of blocks of a drive
+// from its IDENTIFY data, with the LBA48 count or else the LBA28 count.
+fn parse_identify(res: &[u16; 256]) -> (String, String, u64) {
+    let buf = res.map(u16::to_be_bytes).concat();
+    let serial = String::from_utf8_lossy(&buf[20..40]).trim().into();
+    let model = String::from_utf8_lossy(&buf[54..94]).trim().into();
+    let mut blocks = (0..4).fold(0, |acc, i| acc | (res[100 + i] as u64) << (16 * i));
+    if blocks == 0 {
+        blocks = (res[60] as u64) | (res[61] as u64) << 16;
+    }
+    (model, serial, blocks)
+}
+
+// Register Host to Device FIS of a command with a 48-bit LBA
+fn command_fis(cmd: Command, block: u64, count: u16) -> [u8; 20] {
+    let bytes = block.to_le_bytes();
+    [
+        FIS_TYPE_REG_H2D,
+        0x80, // Command
+        cmd as u8,
+        0, // Features
+        bytes[0], bytes[1], bytes[2],
+        1 << 6, // LBA mode
+        bytes[3], bytes[4], bytes[5],
+        0, // Features
+        count as u8, (count >> 8) as u8,
+        0, 0, 0, 0, 0, 0,
+    ]
+}
+
+#[test_case]
+fn test_parse_identify() {
+    let mut res = [0x2020; 256]; // Spaces
+    res[27] = u16::from_be_bytes(*b"QE");
+    res[28] = u16::from_be_bytes(*b"MU");
+    res[60] = 0x5678;
+    res[61] = 0x0012;
+    for i in 100..104 {
+        res[i] = 0;
+    }
+    let (model, serial, blocks) = parse_identify(&res);
+    assert_eq!(model, "QEMU");
+    assert_eq!(serial, "");
+    assert_eq!(blocks, 0x0012_5678);
+
+    res[100] = 0x0001;
+    res[102] = 0x0002;
+    assert_eq!(parse_identify(&res).2, 0x0002_0000_0001);
+}
+
+#[test_case]
+fn test_command_fis() {
+    let fis = command_fis(Command::ReadDmaExt, 0x0605_0403_0201, 0x0140);
+    assert_eq!(fis[0], FIS_TYPE_REG_H2D);
+    assert_eq!(fis[2], 0x25);
+    assert_eq!(fis[4..7], [0x01, 0x02, 0x03]);
+    assert_eq!(fis[8..11], [0x04, 0x05, 0x06]);
+    assert_eq!(fis[12..14], [0x40, 0x01]);
+}
@@ -1,10 +1,12 @@
 use crate::sys;
+use crate::sys::allocator::PhysBuf;
 use alloc::string::String;
 use alloc::vec::Vec;
 use bit_field::BitField;
 use core::convert::TryInto;
 use core::fmt;
 use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, Ordering};
 use lazy_static::lazy_static;
 use spin::Mutex;
 use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
@@ -13,17 +15,128 @@ use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
 
 pub const BLOCK_SIZE: usize = 512;
 
+// Size of the blocks of CD-ROM drives
+pub const ATAPI_BLOCK_SIZE: usize = 2048;
+
+// Maximum number of blocks transferred by a single command
+pub const MAX_BLOCKS: usize = 64;
+
+// Number of blocks addressable with 28-bit LBA commands (128 GB)
+const LBA28_LIMIT: u64 = 1 << 28;
+
 #[repr(u16)]
 #[derive(Debug, Clone, Copy)]
 enum Command {
     Read = 0x20,
+    ReadExt = 0x24,
+    ReadDmaExt = 0x25,
     Write = 0x30,
+    WriteExt = 0x34,
+    WriteDmaExt = 0x35,
+    Packet = 0xA0,
+    IdentifyPacket = 0xA1,
+    ReadDma = 0xC8,
+    WriteDma = 0xCA,
     Identify = 0xEC,
 }
 
+// Set by the IRQ 14 and 15 handlers when a bus signals the completion of a
+// DMA transfer
+static IRQS: [AtomicBool; 2] = [AtomicBool::new(false), AtomicBool::new(false)];
+
+// See "Programming Interface for Bus Master IDE Controller" (1994)
+#[derive(Clone)]
+struct BusMaster {
+    command_register: Port<u8>,
+    status_register: Port<u8>,
+    prdt_register: Port<u32>,
+
+    // The Physical Region Descriptor Table and the buffer it describes
+    prdt: PhysBuf,
+    prdt_offset: usize,
+    buf: PhysBuf,
+}
+
+impl BusMaster {
+    fn new(io_base: u16) -> Self {
+        let prdt = PhysBuf::new(8 * 4 + 4);
+        let buf = PhysBuf::new(MAX_BLOCKS * BLOCK_SIZE);
+
+        // The table must be aligned on 4 bytes and not cross a 64 KB boundary
+        let mut prdt_offset = (4 - (prdt.addr() % 4) as usize) % 4;
+        if (prdt.addr() as usize + prdt_offset) % 0x10000 > 0x10000 - 16 {
+            prdt_offset += 16;
+        }
+
+        Self {
+            command_register: Port::new(io_base + 0),
+            status_register: Port::new(io_base + 2),
+            prdt_register: Port::new(io_base + 4),
+            prdt, prdt_offset, buf,
+        }
+    }
+
+    // Describe the first `len` bytes of the buffer in the table, splitting
+    // the regions crossing a 64 KB boundary.
+    fn setup(&mut self, len: usize, is_read: bool) {
+        let mut addr = self.buf.addr();
+        let end = addr + len as u64;
+        let mut i = self.prdt_offset;
+        while addr < end {
+            let boundary = (addr & !0xFFFF) + 0x10000;
+            let next = boundary.min(end);
+            let count = (next - addr) as u16; // 0 would mean 64 KB
+            let flags: u16 = if next == end { 1 << 15 } else { 0 }; // End of table
+            self.prdt[i..(i + 4)].copy_from_slice(&(addr as u32).to_le_bytes());
+            self.prdt[(i + 4)..(i + 6)].copy_from_slice(&count.to_le_bytes());
+            self.prdt[(i + 6)..(i + 8)].copy_from_slice(&flags.to_le_bytes());
+            addr = next;
+            i += 8;
+        }
+        unsafe {
+            self.command_register.write(0); // Stop any transfer
+            self.status_register.write(0b110); // Clear error and interrupt bits
+            self.prdt_register.write((self.prdt.addr() as usize + self.prdt_offset) as u32);
+            self.command_register.write(if is_read { 1 << 3 } else { 0 });
+        }
+    }
+
+    fn start(&mut self) {
+        unsafe {
+            let cmd = self.command_register.read();
+            self.command_register.write(cmd | 1);
+        }
+    }
+
+    // Stop the transfer and return an error if the controller failed
+    fn stop(&mut self) -> Result<(), ()> {
+        unsafe {
+            let cmd = self.command_register.read();
+            self.command_register.write(cmd & !1);
+            let status = self.status_register.read();
+            self.status_register.write(0b110);
+            if status.get_bit(1) {
+                Err(())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn is_interrupt(&mut self) -> bool {
+        unsafe { self.status_register.read().get_bit(2) }
+    }
+}
+
+impl fmt::Debug for BusMaster {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BusMaster {{ prdt: {:#X} }}", self.prdt.addr())
+    }
+}
+
 enum IdentifyResponse {
     Ata([u16; 256]),
-    Atapi,
+    Atapi([u16; 256]),
     Sata,
     None,
 }
@@ -62,6 +175,14 @@ pub struct Bus {
     alternate_status_register: PortReadOnly<u8>,
     control_register: PortWriteOnly<u8>,
     drive_blockess_register: PortReadOnly<u8>,
+
+    bus_master: Option<BusMaster>,
+
+    // Support of 48-bit LBA commands by each drive, set by IDENTIFY
+    lba48: [bool; 2],
+
+    // Packet devices like CD-ROM drives, set by IDENTIFY
+    atapi: [bool; 2],
 }
 
 impl Bus {
@@ -83,9 +204,18 @@ impl Bus {
             alternate_status_register: PortReadOnly::new(ctrl_base + 0),
             control_register: PortWriteOnly::new(ctrl_base + 0),
             drive_blockess_register: PortReadOnly::new(ctrl_base + 1),
+
+            bus_master: None,
+
+            lba48: [false; 2],
+            atapi: [false; 2],
         }
     }
 
+    fn enable_dma(&mut self, bus_master_base: u16) {
+        self.bus_master = Some(BusMaster::new(bus_master_base));
+    }
+
     fn check_floating_bus(&mut self) -> Result<(), ()> {
         match self.status() {
             0xFF | 0x7F => Err(()),
@@ -153,26 +283,46 @@ impl Bus {
         Ok(())
     }
 
-    fn write_command_params(&mut self, drive: u8, block: u32) -> Result<(), ()> {
+    fn write_command_params(&mut self, drive: u8, block: u64, count: u8, lba48: bool) -> Result<(), ()> {
+        let bytes = block.to_le_bytes();
+        if lba48 {
+            // The high bytes are written first in the same registers
+            unsafe {
+                self.sector_count_register.write(0);
+                self.lba0_register.write(bytes[3]);
+                self.lba1_register.write(bytes[4]);
+                self.lba2_register.write(bytes[5]);
+                self.sector_count_register.write(count);
+                self.lba0_register.write(bytes[0]);
+                self.lba1_register.write(bytes[1]);
+                self.lba2_register.write(bytes[2]);
+                self.drive_register.write(0x40 | (drive << 4)); // Bit 6 => LBA
+            }
+            return Ok(());
+        }
         let lba = true;
-        let mut bytes = block.to_le_bytes();
-        bytes[3].set_bit(4, drive > 0);
-        bytes[3].set_bit(5, true);
-        bytes[3].set_bit(6, lba);
-        bytes[3].set_bit(7, true);
+        let mut drive_byte = bytes[3];
+        drive_byte.set_bit(4, drive > 0);
+        drive_byte.set_bit(5, true);
+        drive_byte.set_bit(6, lba);
+        drive_byte.set_bit(7, true);
         unsafe {
-            self.sector_count_register.write(1);
+            self.sector_count_register.write(count);
             self.lba0_register.write(bytes[0]);
             self.lba1_register.write(bytes[1]);
             self.lba2_register.write(bytes[2]);
-            self.drive_register.write(bytes[3]);
+            self.drive_register.write(drive_byte);
         }
         Ok(())
     }
 
-    fn write_command(&mut self, cmd: Command) -> Result<(), ()> {
+    fn send_command(&mut self, cmd: Command) {
         unsafe { self.command_register.write(cmd as u8) }
         self.wait(400); // Wait at least 400 ns
+    }
+
+    fn write_command(&mut self, cmd: Command) -> Result<(), ()> {
+        self.send_command(cmd);
         self.status(); // Ignore results of first read
         self.clear_interrupt();
         if self.status() == 0 { // Drive does not exist
@@ -188,19 +338,71 @@ impl Bus {
         Ok(())
     }
 
-    fn setup_pio(&mut self, drive: u8, block: u32) -> Result<(), ()> {
+    fn setup_pio(&mut self, drive: u8, block: u64, count: u8, lba48: bool) -> Result<(), ()> {
         self.select_drive(drive)?;
-        self.write_command_params(drive, block)?;
+        self.write_command_params(drive, block, count, lba48)?;
         Ok(())
     }
 
-    fn read(&mut self, drive: u8, block: u32, buf: &mut [u8]) -> Result<(), ()> {
-        debug_assert!(buf.len() == BLOCK_SIZE);
-        self.setup_pio(drive, block)?;
-        self.write_command(Command::Read)?;
-        for chunk in buf.chunks_mut(2) {
-            let data = self.read_data().to_le_bytes();
-            chunk.clone_from_slice(&data);
+    // Use 48-bit LBA commands only for the blocks beyond the range of 28-bit
+    // LBA commands, if the drive supports them.
+    fn is_lba48(&self, drive: u8, block: u64, count: u8) -> Result<bool, ()> {
+        if block + count as u64 <= LBA28_LIMIT {
+            Ok(false)
+        } else if self.lba48[drive as usize] {
+            Ok(true)
+        } else {
+            Err(())
+        }
+    }
+
+    fn block_size(&self, drive: u8) -> usize {
+        if self.atapi[drive as usize] {
+            ATAPI_BLOCK_SIZE
+        } else {
+            BLOCK_SIZE
+        }
+    }
+
+    // Read or write up to MAX_BLOCKS consecutive blocks
+    fn read(&mut self, drive: u8, block: u64, buf: &mut [u8]) -> Result<(), ()> {
+        if self.atapi[drive as usize] {
+            return self.read_atapi(drive, block, buf);
+        }
+        let count = sys::fs::blocks_count(buf.len(), BLOCK_SIZE, MAX_BLOCKS)? as u8;
+        let lba48 = self.is_lba48(drive, block, count)?;
+        if self.bus_master.is_some() {
+            self.read_dma(drive, block, count, lba48, buf)
+        } else {
+            self.read_pio(drive, block, count, lba48, buf)
+        }
+    }
+
+    fn write(&mut self, drive: u8, block: u64, buf: &[u8]) -> Result<(), ()> {
+        if self.atapi[drive as usize] {
+            return Err(()); // Read-only
+        }
+        let count = sys::fs::blocks_count(buf.len(), BLOCK_SIZE, MAX_BLOCKS)? as u8;
+        let lba48 = self.is_lba48(drive, block, count)?;
+        if self.bus_master.is_some() {
+            self.write_dma(drive, block, count, lba48, buf)
+        } else {
+            self.write_pio(drive, block, count, lba48, buf)
+        }
+    }
+
+    fn read_pio(&mut self, drive: u8, block: u64, count: u8, lba48: bool, buf: &mut [u8]) -> Result<(), ()> {
+        self.setup_pio(drive, block, count, lba48)?;
+        self.write_command(if lba48 { Command::ReadExt } else { Command::Read })?;
+        for (i, sector) in buf.chunks_mut(BLOCK_SIZE).enumerate() {
+            if i > 0 { // Wait for the next sector
+                self.poll(Status::BSY, false)?;
+                self.poll(Status::DRQ, true)?;
+            }
+            for chunk in sector.chunks_mut(2) {
+                let data = self.read_data().to_le_bytes();
+                chunk.clone_from_slice(&data);
+            }
         }
         if self.is_error() {
             debug!("ATA read: data error");
@@ -211,13 +413,18 @@ impl Bus {
         }
     }
 
-    fn write(&mut self, drive: u8, block: u32, buf: &[u8]) -> Result<(), ()> {
-        debug_assert!(buf.len() == BLOCK_SIZE);
-        self.setup_pio(drive, block)?;
-        self.write_command(Command::Write)?;
-        for chunk in buf.chunks(2) {
-            let data = u16::from_le_bytes(chunk.try_into().unwrap());
-            self.write_data(data);
+    fn write_pio(&mut self, drive: u8, block: u64, count: u8, lba48: bool, buf: &[u8]) -> Result<(), ()> {
+        self.setup_pio(drive, block, count, lba48)?;
+        self.write_command(if lba48 { Command::WriteExt } else { Command::Write })?;
+        for (i, sector) in buf.chunks(BLOCK_SIZE).enumerate() {
+            if i > 0 { // Wait for the next sector
+                self.poll(Status::BSY, false)?;
+                self.poll(Status::DRQ, true)?;
+            }
+            for chunk in sector.chunks(2) {
+                let data = u16::from_le_bytes(chunk.try_into().unwrap());
+                self.write_data(data);
+            }
         }
         if self.is_error() {
             debug!("ATA write: data error");
@@ -228,24 +435,143 @@ impl Bus {
         }
     }
 
+    fn read_dma(&mut self, drive: u8, block: u64, count: u8, lba48: bool, buf: &mut [u8]) -> Result<(), ()> {
+        let cmd = if lba48 { Command::ReadDmaExt } else { Command::ReadDma };
+        self.transfer_dma(drive, block, count, lba48, buf.len(), cmd)?;
+        if let Some(bus_master) = &self.bus_master {
+            buf.copy_from_slice(&bus_master.buf[0..buf.len()]);
+        }
+        Ok(())
+    }
+
+    fn write_dma(&mut self, drive: u8, block: u64, count: u8, lba48: bool, buf: &[u8]) -> Result<(), ()> {
+        if let Some(bus_master) = &mut self.bus_master {
+            bus_master.buf[0..buf.len()].copy_from_slice(buf);
+        }
+        let cmd = if lba48 { Command::WriteDmaExt } else { Command::WriteDma };
+        self.transfer_dma(drive, block, count, lba48, buf.len(), cmd)
+    }
+
+    fn transfer_dma(&mut self, drive: u8, block: u64, count: u8, lba48: bool, len: usize, cmd: Command) -> Result<(), ()> {
+        let is_read = matches!(cmd, Command::ReadDma | Command::ReadDmaExt);
+        let irq = &IRQS[self.id as usize];
+        self.bus_master.as_mut().ok_or(())?.setup(len, is_read);
+        self.setup_pio(drive, block, count, lba48)?;
+        irq.store(false, Ordering::SeqCst);
+        self.send_command(cmd);
+        let bus_master = self.bus_master.as_mut().ok_or(())?;
+        bus_master.start();
+
+        // Wait for the completion interrupt instead of polling the drive
+        let start = sys::clock::uptime();
+        let mut res = Ok(());
+        while !irq.load(Ordering::SeqCst) && !bus_master.is_interrupt() {
+            if sys::clock::uptime() - start > 1.0 {
+                debug!("ATA hanged while waiting for DMA transfer");
+                res = Err(());
+                break;
+            }
+            sys::time::halt();
+        }
+        if bus_master.stop().is_err() {
+            debug!("ATA DMA: bus master error");
+            res = Err(());
+        }
+        self.clear_interrupt();
+        if self.is_error() {
+            debug!("ATA DMA: data error");
+            self.debug();
+            res = Err(());
+        }
+        res
+    }
+
+    // Send a SCSI command packet to an ATAPI drive and read its response in
+    // PIO mode, where the drive gives the size of each data transfer.
+    fn packet(&mut self, drive: u8, packet: &[u8; 12], buf: &mut [u8]) -> Result<usize, ()> {
+        self.select_drive(drive)?;
+        unsafe {
+            self.features_register.write(0); // PIO mode
+            self.lba1_register.write(ATAPI_BLOCK_SIZE as u8); // Max byte count
+            self.lba2_register.write((ATAPI_BLOCK_SIZE >> 8) as u8);
+        }
+        self.write_command(Command::Packet)?;
+        for chunk in packet.chunks(2) {
+            self.write_data(u16::from_le_bytes(chunk.try_into().unwrap()));
+        }
+        let mut i = 0;
+        loop {
+            self.wait(400); // Wait at least 400 ns
+            self.poll(Status::BSY, false)?;
+            if self.is_error() {
+                debug!("ATAPI packet {:#04X} errored", packet[0]);
+                self.debug();
+                return Err(());
+            }
+            if !self.status().get_bit(Status::DRQ as usize) {
+                break; // End of the response
+            }
+            let n = self.lba1() as usize | (self.lba2() as usize) << 8;
+            for _ in 0..(n / 2) {
+                let data = self.read_data().to_le_bytes();
+                if i + 2 <= buf.len() {
+                    buf[i..(i + 2)].copy_from_slice(&data);
+                }
+                i += 2;
+            }
+        }
+        Ok(i.min(buf.len()))
+    }
+
+    fn read_atapi(&mut self, drive: u8, block: u64, buf: &mut [u8]) -> Result<(), ()> {
+        let count = buf.len() / ATAPI_BLOCK_SIZE;
+        if buf.len() % ATAPI_BLOCK_SIZE != 0 || count == 0 || block > u32::MAX as u64 {
+            return Err(());
+        }
+        let lba = (block as u32).to_be_bytes();
+        let cmd = [0x28, 0, lba[0], lba[1], lba[2], lba[3], 0, (count >> 8) as u8, count as u8, 0, 0, 0]; // READ (10)
+        if self.packet(drive, &cmd, buf)? == buf.len() {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    // Return the number of blocks of the disc in an ATAPI drive
+    fn atapi_capacity(&mut self, drive: u8) -> Option<u64> {
+        let cmd = [0x25, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]; // READ CAPACITY
+        let mut buf = [0; 8];
+        if self.packet(drive, &cmd, &mut buf).ok()? != buf.len() {
+            return None;
+        }
+        let last_block = u32::from_be_bytes(buf[0..4].try_into().unwrap());
+        Some(last_block as u64 + 1)
+    }
+
     fn identify_drive(&mut self, drive: u8) -> Result<IdentifyResponse, ()> {
         if self.check_floating_bus().is_err() {
             return Ok(IdentifyResponse::None);
         }
         self.select_drive(drive)?;
-        self.write_command_params(drive, 0)?;
-        if self.write_command(Command::Identify).is_err() {
-            if self.status() == 0 {
-                return Ok(IdentifyResponse::None);
-            } else {
-                return Err(());
-            }
+        self.write_command_params(drive, 0, 1, false)?;
+        let res = self.write_command(Command::Identify);
+        if res.is_err() && self.status() == 0 {
+            return Ok(IdentifyResponse::None);
         }
-        match (self.lba1(), self.lba2()) {
-            (0x00, 0x00) => Ok(IdentifyResponse::Ata([(); 256].map(|_| { self.read_data() }))),
-            (0x14, 0xEB) => Ok(IdentifyResponse::Atapi),
-            (0x3C, 0x3C) => Ok(IdentifyResponse::Sata),
-            (_, _) => Err(()),
+        match (res, self.lba1(), self.lba2()) {
+            (Ok(()), 0x00, 0x00) => {
+                let res = [(); 256].map(|_| { self.read_data() });
+                self.lba48[drive as usize] = res[83].get_bit(10);
+                Ok(IdentifyResponse::Ata(res))
+            }
+            (_, 0x14, 0xEB) => { // Packet devices abort IDENTIFY with a signature
+                self.write_command(Command::IdentifyPacket)?;
+                let res = [(); 256].map(|_| { self.read_data() });
+                self.atapi[drive as usize] = true;
+                Ok(IdentifyResponse::Atapi(res))
+            }
+            (_, 0x3C, 0x3C) => Ok(IdentifyResponse::Sata), // See `sys::ahci`
+            (_, _, _) => Err(()),
         }
     }
 
@@ -270,6 +596,10 @@ impl Bus {
 
 lazy_static! {
     pub static ref BUSES: Mutex<Vec<Bus>> = Mutex::new(Vec::new());
+
+    // Drives identified during the initialization, with the size of the disc
+    // in the ATAPI drives at that time.
+    static ref DRIVES: Mutex<Vec<Drive>> = Mutex::new(Vec::new());
 }
 
 pub fn init() {
@@ -277,81 +607,133 @@ pub fn init() {
         let mut buses = BUSES.lock();
         buses.push(Bus::new(0, 0x1F0, 0x3F6, 14));
         buses.push(Bus::new(1, 0x170, 0x376, 15));
+
+        // Use the bus master of the IDE controller for DMA transfers
+        if let Some(mut dev) = sys::pci::find_class(0x01, 0x01) {
+            let bar = dev.base_addresses[4];
+            if dev.prog.get_bit(7) && bar.get_bit(0) { // Bus master in I/O space
+                dev.enable_bus_mastering();
+                let base = (bar & 0xFFFC) as u16;
+                buses[0].enable_dma(base);
+                buses[1].enable_dma(base + 8);
+                log!("ATA DMA enabled\n");
+            }
+        }
     }
+    sys::idt::set_irq_handler(14, primary_interrupt_handler);
+    sys::idt::set_irq_handler(15, secondary_interrupt_handler);
 
-    for drive in list() {
-        log!("ATA {}:{} {}\n", drive.bus, drive.dsk, drive);
+    let mut drives = Vec::new();
+    for bus in 0..2 {
+        for dsk in 0..2 {
+            if let Some(drive) = Drive::identify(bus, dsk) {
+                log!("ATA {}:{} {}\n", drive.bus, drive.dsk, drive);
+                drives.push(drive)
+            }
+        }
     }
+    *DRIVES.lock() = drives;
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Drive {
     pub bus: u8,
     pub dsk: u8,
-    blocks: u32,
+    blocks: u64,
     model: String,
     serial: String,
+    atapi: bool,
 }
 
 impl Drive {
     pub fn open(bus: u8, dsk: u8) -> Option<Self> {
+        let drives = DRIVES.lock();
+        drives.iter().find(|drive| drive.bus == bus && drive.dsk == dsk).cloned()
+    }
+
+    fn identify(bus: u8, dsk: u8) -> Option<Self> {
         let mut buses = BUSES.lock();
-        if let Ok(IdentifyResponse::Ata(res)) = buses[bus as usize].identify_drive(dsk) {
-            let buf = res.map(u16::to_be_bytes).concat();
-            let serial = String::from_utf8_lossy(&buf[20..40]).trim().into();
-            let model = String::from_utf8_lossy(&buf[54..94]).trim().into();
-            let blocks = u32::from_be_bytes(buf[120..124].try_into().unwrap()).rotate_left(16);
-            Some(Self { bus, dsk, model, serial, blocks })
+        let (res, atapi) = match buses[bus as usize].identify_drive(dsk) {
+            Ok(IdentifyResponse::Ata(res)) => (res, false),
+            Ok(IdentifyResponse::Atapi(res)) => (res, true),
+            _ => return None,
+        };
+        let buf = res.map(u16::to_be_bytes).concat();
+        let serial = String::from_utf8_lossy(&buf[20..40]).trim().into();
+        let model = String::from_utf8_lossy(&buf[54..94]).trim().into();
+        let blocks = if atapi { // Size of the disc, if any
+            buses[bus as usize].atapi_capacity(dsk).unwrap_or(0)
+        } else if res[83].get_bit(10) { // LBA48 supported
+            (0..4).fold(0, |acc, i| acc | (res[100 + i] as u64) << (16 * i))
         } else {
-            None
-        }
+            u32::from_be_bytes(buf[120..124].try_into().unwrap()).rotate_left(16) as u64
+        };
+        Some(Self { bus, dsk, model, serial, blocks, atapi })
     }
 
     pub const fn block_size(&self) -> u32 {
-        BLOCK_SIZE as u32
+        if self.atapi {
+            ATAPI_BLOCK_SIZE as u32
+        } else {
+            BLOCK_SIZE as u32
+        }
     }
 
-    pub fn block_count(&self) -> u32 {
-        self.blocks
+    pub fn is_atapi(&self) -> bool {
+        self.atapi
     }
 
-    fn humanized_size(&self) -> (usize, String) {
-        let size = self.block_size() as usize;
-        let count = self.block_count() as usize;
-        let bytes = size * count;
-        if bytes >> 20 < 1000 {
-            (bytes >> 20, String::from("MB"))
-        } else {
-            (bytes >> 30, String::from("GB"))
-        }
+    pub fn block_count(&self) -> u64 {
+        self.blocks
     }
 }
 
 impl fmt::Display for Drive {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let (size, unit) = self.humanized_size();
+        let bytes = self.block_size() as usize * self.block_count() as usize;
+        let (size, unit) = sys::fs::humanized_size(bytes);
         write!(f, "{} {} ({} {})", self.model, self.serial, size, unit)
     }
 }
 
 pub fn list() -> Vec<Drive> {
-    let mut res = Vec::new();
-    for bus in 0..2 {
-        for dsk in 0..2 {
-            if let Some(drive) = Drive::open(bus, dsk) {
-                res.push(drive)
-            }
-        }
-    }
-    res
+    DRIVES.lock().clone()
+}
+
+fn primary_interrupt_handler() {
+    IRQS[0].store(true, Ordering::SeqCst);
 }
 
-pub fn read(bus: u8, drive: u8, block: u32, buf: &mut [u8]) -> Result<(), ()> {
+fn secondary_interrupt_handler() {
+    IRQS[1].store(true, Ordering::SeqCst);
+}
+
+// Read or write consecutive blocks, split into commands of MAX_BLOCKS
+pub fn read(bus: u8, drive: u8, block: u64, buf: &mut [u8]) -> Result<(), ()> {
     let mut buses = BUSES.lock();
-    buses[bus as usize].read(drive, block, buf)
+    let bus = &mut buses[bus as usize];
+    let bs = bus.block_size(drive);
+    sys::fs::read_chunks(block, buf, MAX_BLOCKS * BLOCK_SIZE, bs, |addr, chunk| {
+        bus.read(drive, addr, chunk)
+    })
 }
 
-pub fn write(bus: u8, drive: u8, block: u32, buf: &[u8]) -> Result<(), ()> {
+pub fn write(bus: u8, drive: u8, block: u64, buf: &[u8]) -> Result<(), ()> {
     let mut buses = BUSES.lock();
-    buses[bus as usize].write(drive, block, buf)
+    let bus = &mut buses[bus as usize];
+    let bs = bus.block_size(drive);
+    sys::fs::write_chunks(block, buf, MAX_BLOCKS * BLOCK_SIZE, bs, |addr, chunk| {
+        bus.write(drive, addr, chunk)
+    })
+}
+
+#[test_case]
+fn test_lba48() {
+    let mut bus = Bus::new(0, 0x1F0, 0x3F6, 14);
+    assert_eq!(bus.is_lba48(0, 0, 1), Ok(false));
+    assert_eq!(bus.is_lba48(0, LBA28_LIMIT - 1, 1), Ok(false));
+    assert_eq!(bus.is_lba48(0, LBA28_LIMIT - 1, 2), Err(()));
+    bus.lba48[0] = true;
+    assert_eq!(bus.is_lba48(0, LBA28_LIMIT, 1), Ok(true));
+    assert_eq!(bus.is_lba48(1, LBA28_LIMIT, 1), Err(()));
 }
@@ -1,10 +1,68 @@
 use crate::sys;
 use crate::sys::cmos::CMOS;
+use crate::sys::fs::FileIO;
 
 use time::{OffsetDateTime, Duration};
 
 const DAYS_BEFORE_MONTH: [u64; 13] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334, 365];
 
+// The clock devices are read as a big-endian f64 of 8 bytes
+#[derive(Debug, Clone)]
+pub struct Uptime;
+
+impl Uptime {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn size() -> usize {
+        core::mem::size_of::<f64>()
+    }
+}
+
+impl FileIO for Uptime {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        read_time(uptime(), buf)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Realtime;
+
+impl Realtime {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn size() -> usize {
+        core::mem::size_of::<f64>()
+    }
+}
+
+impl FileIO for Realtime {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        read_time(realtime(), buf)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+}
+
+fn read_time(time: f64, buf: &mut [u8]) -> Result<usize, ()> {
+    let bytes = time.to_be_bytes();
+    let n = bytes.len();
+    if buf.len() < n {
+        return Err(());
+    }
+    buf[0..n].copy_from_slice(&bytes);
+    Ok(n)
+}
+
 // NOTE: This clock is monotonic
 pub fn uptime() -> f64 {
     sys::time::time_between_ticks() * sys::time::ticks() as f64
@@ -14,12 +72,10 @@ pub fn uptime() -> f64 {
 pub fn realtime() -> f64 {
     let rtc = CMOS::new().rtc(); // Assuming GMT
 
-    let timestamp = 86400 * days_before_year(rtc.year as u64)
-                  + 86400 * days_before_month(rtc.year as u64, rtc.month as u64)
-                  + 86400 * (rtc.day - 1) as u64
-                  +  3600 * rtc.hour as u64
-                  +    60 * rtc.minute as u64
-                  +         rtc.second as u64;
+    let timestamp = timestamp(
+        rtc.year as u64, rtc.month as u64, rtc.day as u64,
+        rtc.hour as u64, rtc.minute as u64, rtc.second as u64
+    );
 
     let fract = sys::time::time_between_ticks()
               * (sys::time::ticks() - sys::time::last_rtc_update()) as f64;
@@ -27,6 +83,12 @@ pub fn realtime() -> f64 {
     (timestamp as f64) + fract
 }
 
+// Convert a date and time in GMT into a Unix timestamp
+pub fn timestamp(year: u64, month: u64, day: u64, hour: u64, minute: u64, second: u64) -> u64 {
+    let days = days_before_year(year) + days_before_month(year, month) + day - 1;
+    86400 * days + 3600 * hour + 60 * minute + second
+}
+
 fn days_before_year(year: u64) -> u64 {
     (1970..year).fold(0, |days, y| {
         days + if is_leap_year(y) { 366 } else { 365 }
@@ -67,3 +129,13 @@ fn test_uptime() {
 fn test_realtime() {
     assert!(realtime() > 1234567890.0);
 }
+
+#[test_case]
+fn test_clock_devices() {
+    let mut buf = [0; 8];
+    assert_eq!(Uptime::new().read(&mut buf), Ok(8));
+    assert!(f64::from_be_bytes(buf) > 0.0);
+    assert_eq!(Realtime::new().read(&mut buf), Ok(8));
+    assert!(f64::from_be_bytes(buf) > 1234567890.0);
+    assert_eq!(Realtime::new().read(&mut buf[0..4]), Err(()));
+}
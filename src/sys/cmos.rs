@@ -1,3 +1,6 @@
+use crate::sys::fs::FileIO;
+
+use alloc::format;
 use core::hint::spin_loop;
 
 use bit_field::BitField;
@@ -24,7 +27,7 @@ enum Interrupt {
     Update = 1 << 4,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RTC {
     pub year: u16,
     pub month: u8,
@@ -34,6 +37,61 @@ pub struct RTC {
     pub second: u8,
 }
 
+// The RTC device is read and written as a date like "2022-04-01T12:34:56"
+impl RTC {
+    pub fn new() -> Self {
+        CMOS::new().rtc()
+    }
+
+    pub const fn size() -> usize {
+        19
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let b = s.as_bytes();
+        if b.len() != Self::size() || b[4] != b'-' || b[7] != b'-' || b[10] != b'T' || b[13] != b':' || b[16] != b':' {
+            return None;
+        }
+        let rtc = Self {
+            year: s.get(0..4)?.parse().ok()?,
+            month: s.get(5..7)?.parse().ok()?,
+            day: s.get(8..10)?.parse().ok()?,
+            hour: s.get(11..13)?.parse().ok()?,
+            minute: s.get(14..16)?.parse().ok()?,
+            second: s.get(17..19)?.parse().ok()?,
+        };
+        let is_valid = (2000..2100).contains(&rtc.year)
+            && (1..=12).contains(&rtc.month)
+            && (1..=31).contains(&rtc.day)
+            && rtc.hour < 24 && rtc.minute < 60 && rtc.second < 60;
+        if is_valid { Some(rtc) } else { None }
+    }
+}
+
+impl FileIO for RTC {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        *self = CMOS::new().rtc();
+        let date = format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            self.year, self.month, self.day,
+            self.hour, self.minute, self.second
+        );
+        let n = date.len();
+        if buf.len() < n {
+            return Err(());
+        }
+        buf[0..n].copy_from_slice(date.as_bytes());
+        Ok(n)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+        let s = core::str::from_utf8(buf).map_err(|_| ())?;
+        *self = RTC::parse(s.trim_end()).ok_or(())?;
+        CMOS::new().update_rtc(self);
+        Ok(buf.len())
+    }
+}
+
 pub struct CMOS {
     addr: Port<u8>,
     data: Port<u8>,
@@ -91,6 +149,51 @@ impl CMOS {
         rtc
     }
 
+    pub fn update_rtc(&mut self, rtc: &RTC) {
+        let b = self.read_register(Register::B);
+
+        let mut second = rtc.second;
+        let mut minute = rtc.minute;
+        let mut hour = rtc.hour;
+        let mut day = rtc.day;
+        let mut month = rtc.month;
+        let mut year = (rtc.year - 2000) as u8;
+
+        let mut pm = 0;
+        if b & 0x02 == 0 { // 12 hour format
+            if hour >= 12 {
+                pm = 0x80;
+            }
+            hour %= 12;
+            if hour == 0 {
+                hour = 12;
+            }
+        }
+
+        if b & 0x04 == 0 { // BCD Mode
+            second = ((second / 10) << 4) | (second % 10);
+            minute = ((minute / 10) << 4) | (minute % 10);
+            hour = ((hour / 10) << 4) | (hour % 10);
+            day = ((day / 10) << 4) | (day % 10);
+            month = ((month / 10) << 4) | (month % 10);
+            year = ((year / 10) << 4) | (year % 10);
+        }
+        hour |= pm;
+
+        // The updates of the RTC are stopped while the registers are written
+        interrupts::without_interrupts(|| {
+            self.wait_end_of_update();
+            self.write_register(Register::B, b | 0x80);
+            self.write_register(Register::Second, second);
+            self.write_register(Register::Minute, minute);
+            self.write_register(Register::Hour, hour);
+            self.write_register(Register::Day, day);
+            self.write_register(Register::Month, month);
+            self.write_register(Register::Year, year);
+            self.write_register(Register::B, b);
+        });
+    }
+
     pub fn enable_periodic_interrupt(&mut self) {
         self.enable_interrupt(Interrupt::Periodic);
     }
@@ -160,6 +263,13 @@ impl CMOS {
         }
     }
 
+    fn write_register(&mut self, reg: Register, value: u8) {
+        unsafe {
+            self.addr.write(reg as u8);
+            self.data.write(value);
+        }
+    }
+
     fn enable_nmi(&mut self) {
         unsafe {
             let prev = self.addr.read();
@@ -174,3 +284,13 @@ impl CMOS {
         }
     }
 }
+
+#[test_case]
+fn test_rtc_parse() {
+    let rtc = RTC::parse("2022-04-01T12:34:56").unwrap();
+    assert_eq!((rtc.year, rtc.month, rtc.day), (2022, 4, 1));
+    assert_eq!((rtc.hour, rtc.minute, rtc.second), (12, 34, 56));
+    assert!(RTC::parse("2022-04-01 12:34:56").is_none());
+    assert!(RTC::parse("2022-13-01T12:34:56").is_none());
+    assert!(RTC::parse("2022-04-01T12:34").is_none());
+}
@@ -1,5 +1,7 @@
+use crate::api;
+use crate::api::vga::Palette;
 use crate::sys;
-use crate::sys::fs::FileIO;
+use crate::sys::fs::{ControlRequest, FileIO};
 use alloc::string::String;
 use alloc::string::ToString;
 use core::fmt;
@@ -16,10 +18,66 @@ lazy_static! {
 #[derive(Debug, Clone)]
 pub struct Console;
 
+pub const ECHO_MODE: u8 = 1 << 0;
+pub const RAW_MODE:  u8 = 1 << 1;
+
 impl Console {
     pub fn new() -> Self {
         Self {}
     }
+
+    pub fn control(&mut self, request: ControlRequest, buf: &mut [u8]) -> Result<usize, ()> {
+        match request {
+            ControlRequest::GetConsoleMode => {
+                let mode = buf.first_mut().ok_or(())?;
+                *mode = 0;
+                if is_echo_enabled() {
+                    *mode |= ECHO_MODE;
+                }
+                if is_raw_enabled() {
+                    *mode |= RAW_MODE;
+                }
+                Ok(1)
+            }
+            ControlRequest::SetConsoleMode => {
+                let mode = *buf.first().ok_or(())?;
+                if mode & ECHO_MODE != 0 { enable_echo() } else { disable_echo() }
+                if mode & RAW_MODE != 0 { enable_raw() } else { disable_raw() }
+                Ok(1)
+            }
+            ControlRequest::GetConsoleSize => {
+                if buf.len() < 4 {
+                    return Err(());
+                }
+                buf[0..2].copy_from_slice(&(cols() as u16).to_be_bytes());
+                buf[2..4].copy_from_slice(&(rows() as u16).to_be_bytes());
+                Ok(4)
+            }
+            ControlRequest::SetVgaPalette if cfg!(feature = "video") => {
+                // 16 RGB colors
+                if buf.len() != 16 * 3 {
+                    return Err(());
+                }
+                let mut palette = Palette::default();
+                for (i, rgb) in buf.chunks(3).enumerate() {
+                    palette.colors[i] = (rgb[0], rgb[1], rgb[2]);
+                }
+                sys::vga::set_palette(palette);
+                Ok(buf.len())
+            }
+            ControlRequest::SetVgaFont if cfg!(feature = "video") => {
+                let font = api::font::from_bytes(buf)?;
+                sys::vga::set_font(&font);
+                Ok(buf.len())
+            }
+            ControlRequest::SetMouseCursor if cfg!(feature = "video") => {
+                let enabled = *buf.first().ok_or(())?;
+                sys::mouse::set_cursor(enabled != 0);
+                Ok(1)
+            }
+            _ => Err(()),
+        }
+    }
 }
 
 impl FileIO for Console {
@@ -193,3 +251,22 @@ pub fn print_fmt(args: fmt::Arguments) {
         sys::serial::print_fmt(args);
     }
 }
+
+#[test_case]
+fn test_console_control() {
+    let mut console = Console::new();
+    let mut buf = [0; 4];
+    assert_eq!(console.control(ControlRequest::GetConsoleSize, &mut buf), Ok(4));
+    assert_eq!(u16::from_be_bytes([buf[0], buf[1]]) as usize, cols());
+    assert_eq!(u16::from_be_bytes([buf[2], buf[3]]) as usize, rows());
+
+    let mut mode = [0];
+    assert_eq!(console.control(ControlRequest::GetConsoleMode, &mut mode), Ok(1));
+    let saved = mode[0];
+    assert_eq!(console.control(ControlRequest::SetConsoleMode, &mut [RAW_MODE]), Ok(1));
+    assert!(!is_echo_enabled());
+    assert!(is_raw_enabled());
+    assert_eq!(console.control(ControlRequest::SetConsoleMode, &mut [saved]), Ok(1));
+    assert_eq!(console.control(ControlRequest::SetNetDebug, &mut mode), Err(()));
+    assert_eq!(console.control(ControlRequest::GetConsoleMode, &mut []), Err(()));
+}
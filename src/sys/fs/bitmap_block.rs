@@ -1,11 +1,19 @@
 use super::block::Block;
+use super::block_device::BlockDeviceIO;
+use super::journal;
+use super::mount;
 use super::super_block;
 use super::super_block::SuperBlock;
 
+use alloc::collections::btree_set::BTreeSet;
+use alloc::vec;
 use bit_field::BitField;
 
 pub const BITMAP_SIZE: usize = 8 * super::BLOCK_SIZE;
 
+// Number of bitmap blocks cleared in a transaction
+const FREE_ALL_BATCH: u32 = 32;
+
 // A BitmapBlock store the allocation status of BITMAP_SIZE blocks, or 8
 // data blocks per byte (1 per bit) of a bitmap block.
 pub struct BitmapBlock {}
@@ -18,10 +26,23 @@ impl BitmapBlock {
         sb.bitmap_area() + (i / size / 8)
     }
 
+    // NOTE: Before version 6 only the first `block_size` bits of a bitmap
+    // block were used, so the same bit was shared by multiple blocks.
     fn buffer_index(addr: u32) -> usize {
         let sb = SuperBlock::read();
         let i = (addr - sb.data_area()) as usize;
-        i % sb.block_size() as usize
+        if sb.has_full_bitmap() {
+            i % BITMAP_SIZE
+        } else {
+            i % sb.block_size() as usize
+        }
+    }
+
+    pub fn is_alloc(addr: u32) -> bool {
+        let block = Block::read(BitmapBlock::block_index(addr));
+        let bitmap = block.data();
+        let i = BitmapBlock::buffer_index(addr);
+        bitmap[i / 8].get_bit(i % 8)
     }
 
     pub fn alloc(addr: u32) {
@@ -65,11 +86,43 @@ impl BitmapBlock {
     }
 }
 
-pub fn free_all() {
+// Write zeros into the bitmap area in batches of blocks small enough to be
+// committed in a transaction of the journal.
+pub fn free_all() -> Result<(), ()> {
     let sb = SuperBlock::read();
     let a = sb.bitmap_area();
     let b = sb.data_area();
-    for addr in a..b {
-        Block::new(addr).write();
+    let mut addr = a;
+    while addr < b {
+        let n = (b - addr).min(FREE_ALL_BATCH);
+        journal::transaction(|| {
+            for i in 0..n {
+                Block::new(addr + i).write();
+            }
+        })?;
+        addr += n;
+    }
+    Ok(())
+}
+
+// Rewrite the bitmap area with the given used blocks in the layout of the
+// version of the given superblock, and return the number of allocated blocks.
+pub fn rebuild(sb: &SuperBlock, used: &BTreeSet<u32>) -> u32 {
+    let n = (sb.data_area() - sb.bitmap_area()) as usize;
+    let mut buf = vec![0; n * super::BLOCK_SIZE];
+    let mut alloc_count = 0;
+    for addr in used {
+        let mut i = (addr - sb.data_area()) as usize;
+        if !sb.has_full_bitmap() {
+            i = (i / BITMAP_SIZE) * BITMAP_SIZE + i % sb.block_size() as usize;
+        }
+        if i / 8 < buf.len() && !buf[i / 8].get_bit(i % 8) {
+            buf[i / 8].set_bit(i % 8, true);
+            alloc_count += 1;
+        }
+    }
+    if let Some(Err(())) = mount::with_device(|dev| dev.write_blocks(sb.bitmap_area(), &buf)) {
+        debug!("MFS: could not write blocks from {:#x}", sb.bitmap_area());
     }
+    alloc_count
 }
@@ -1,5 +1,7 @@
 use super::bitmap_block::BitmapBlock;
 use super::block_device::BlockDeviceIO;
+use super::journal;
+use super::mount;
 
 use core::convert::TryInto;
 
@@ -41,20 +43,22 @@ impl Block {
     }
 
     pub fn read(addr: u32) -> Self {
+        if let Some(buf) = journal::read(addr) {
+            return Self { addr, buf };
+        }
         let mut buf = [0; super::BLOCK_SIZE];
-        if let Some(ref block_device) = *super::block_device::BLOCK_DEVICE.lock() {
-            if block_device.read(addr, &mut buf).is_err() {
-                debug!("MFS: could not read block {:#x}", addr);
-            }
+        if let Some(Err(())) = mount::with_device(|dev| dev.read(addr, &mut buf)) {
+            debug!("MFS: could not read block {:#x}", addr);
         }
         Self { addr, buf }
     }
 
     pub fn write(&self) {
-        if let Some(ref mut block_device) = *super::block_device::BLOCK_DEVICE.lock() {
-            if block_device.write(self.addr, &self.buf).is_err() {
-                debug!("MFS: could not write block {:#x}", self.addr);
-            }
+        if journal::write(self.addr, &self.buf) {
+            return;
+        }
+        if let Some(Err(())) = mount::with_device(|dev| dev.write(self.addr, &self.buf)) {
+            debug!("MFS: could not write block {:#x}", self.addr);
         }
     }
 
@@ -77,6 +81,34 @@ impl Block {
     */
 }
 
+// Read consecutive blocks with a single request to the device, or one block
+// at a time in a transaction where some of them could be pending.
+pub fn read_blocks(addr: u32, buf: &mut [u8]) {
+    if journal::is_active() {
+        for (i, data) in buf.chunks_mut(super::BLOCK_SIZE).enumerate() {
+            data.copy_from_slice(Block::read(addr + i as u32).data());
+        }
+        return;
+    }
+    if let Some(Err(())) = mount::with_device(|dev| dev.read_blocks(addr, buf)) {
+        debug!("MFS: could not read blocks from {:#x}", addr);
+    }
+}
+
+pub fn write_blocks(addr: u32, buf: &[u8]) {
+    if journal::is_active() {
+        for (i, data) in buf.chunks(super::BLOCK_SIZE).enumerate() {
+            let mut block = Block::new(addr + i as u32);
+            block.data_mut().copy_from_slice(data);
+            block.write();
+        }
+        return;
+    }
+    if let Some(Err(())) = mount::with_device(|dev| dev.write_blocks(addr, buf)) {
+        debug!("MFS: could not write blocks from {:#x}", addr);
+    }
+}
+
 pub struct LinkedBlock {
     block: Block
 }
@@ -115,7 +147,7 @@ impl LinkedBlock {
     }
 
     pub fn next(&self) -> Option<Self> {
-        let addr = u32::from_be_bytes(self.block.buf[0..4].try_into().unwrap());
+        let addr = self.next_addr();
         if addr == 0 {
             None
         } else {
@@ -123,6 +155,10 @@ impl LinkedBlock {
         }
     }
 
+    pub fn next_addr(&self) -> u32 {
+        u32::from_be_bytes(self.block.buf[0..4].try_into().unwrap())
+    }
+
     pub fn alloc_next(&mut self) -> Option<Self> {
         let new_block = LinkedBlock::alloc()?;
         self.set_next_addr(new_block.addr());
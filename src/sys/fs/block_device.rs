@@ -1,21 +1,67 @@
 use super::bitmap_block::BitmapBlock;
 use super::dir::Dir;
-use super::super_block::SuperBlock;
+use super::mfs::Mfs;
+use super::mount;
+use super::partition;
+use super::super_block::{SuperBlock, SUPERBLOCK_ADDR};
 
 use crate::sys;
 
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
-use lazy_static::lazy_static;
-use spin::Mutex;
+use core::convert::TryFrom;
+use core::fmt;
 
-lazy_static! {
-    pub static ref BLOCK_DEVICE: Mutex<Option<BlockDevice>> = Mutex::new(None);
-}
+// Free memory left when allocating the blocks of a RAM disk
+const MEM_RESERVE: usize = 1 << 20;
 
 pub enum BlockDevice {
     Mem(MemBlockDevice),
     Ata(AtaBlockDevice),
+    Ahci(AhciBlockDevice),
+    Virtio(VirtioBlockDevice),
+    Partition(PartitionBlockDevice),
+}
+
+impl BlockDevice {
+    pub fn name(&self) -> String {
+        match self {
+            BlockDevice::Mem(_) => "mem".into(),
+            BlockDevice::Ata(dev) => format!("/dev/ata/{}/{}", dev.dev.bus, dev.dev.dsk),
+            BlockDevice::Ahci(dev) => format!("/dev/ahci/{}", dev.dev.id),
+            BlockDevice::Virtio(dev) => format!("/dev/virtio/{}", dev.dev.id),
+            BlockDevice::Partition(dev) => format!("{}/{}", dev.dev.name(), dev.n),
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            BlockDevice::Mem(_) => false,
+            BlockDevice::Ata(dev) => dev.dev.is_atapi(),
+            BlockDevice::Ahci(_) => false,
+            BlockDevice::Virtio(dev) => dev.dev.is_read_only(),
+            BlockDevice::Partition(dev) => dev.dev.is_read_only(),
+        }
+    }
+
+    // MFS starts after the bootloader and the kernel on a whole disk, but it
+    // owns the whole space of a partition
+    pub fn superblock_addr(&self) -> u32 {
+        match self {
+            BlockDevice::Partition(_) => 0,
+            _ => SUPERBLOCK_ADDR,
+        }
+    }
+}
+
+impl fmt::Debug for BlockDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BlockDevice({})", self.name())
+    }
 }
 
 pub trait BlockDeviceIO {
@@ -23,6 +69,65 @@ pub trait BlockDeviceIO {
     fn write(&mut self, addr: u32, buf: &[u8]) -> Result<(), ()>;
     fn block_size(&self) -> usize;
     fn block_count(&self) -> usize;
+
+    // Read consecutive blocks into a buffer of a multiple of the block size,
+    // which devices supporting multi-block transfers can do in fewer commands.
+    fn read_blocks(&self, addr: u32, buf: &mut [u8]) -> Result<(), ()> {
+        let bs = self.block_size();
+        for (i, block) in buf.chunks_mut(bs).enumerate() {
+            self.read(addr + i as u32, block)?;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, addr: u32, buf: &[u8]) -> Result<(), ()> {
+        let bs = self.block_size();
+        for (i, block) in buf.chunks(bs).enumerate() {
+            self.write(addr + i as u32, block)?;
+        }
+        Ok(())
+    }
+}
+
+// Return the number of blocks in a buffer of a transfer of up to `max`
+// blocks, or an error if the buffer is empty, too large, or not a multiple
+// of the block size.
+pub fn blocks_count(len: usize, block_size: usize, max: usize) -> Result<usize, ()> {
+    let n = len / block_size;
+    if len % block_size == 0 && 0 < n && n <= max {
+        Ok(n)
+    } else {
+        Err(())
+    }
+}
+
+// Split a transfer of consecutive blocks into the chunks of `len` bytes given
+// to a driver with the address of their first block.
+pub fn read_chunks<F>(addr: u64, buf: &mut [u8], len: usize, block_size: usize, mut f: F) -> Result<(), ()>
+    where F: FnMut(u64, &mut [u8]) -> Result<(), ()>
+{
+    for (i, chunk) in buf.chunks_mut(len).enumerate() {
+        f(addr + (i * len / block_size) as u64, chunk)?;
+    }
+    Ok(())
+}
+
+pub fn write_chunks<F>(addr: u64, buf: &[u8], len: usize, block_size: usize, mut f: F) -> Result<(), ()>
+    where F: FnMut(u64, &[u8]) -> Result<(), ()>
+{
+    for (i, chunk) in buf.chunks(len).enumerate() {
+        f(addr + (i * len / block_size) as u64, chunk)?;
+    }
+    Ok(())
+}
+
+// Return the size of a disk in MB, or in GB from 1000 MB
+pub fn humanized_size(bytes: usize) -> (usize, &'static str) {
+    if bytes >> 20 < 1000 {
+        (bytes >> 20, "MB")
+    } else {
+        (bytes >> 30, "GB")
+    }
 }
 
 impl BlockDeviceIO for BlockDevice {
@@ -30,6 +135,9 @@ impl BlockDeviceIO for BlockDevice {
         match self {
             BlockDevice::Mem(dev) => dev.read(addr, buf),
             BlockDevice::Ata(dev) => dev.read(addr, buf),
+            BlockDevice::Ahci(dev) => dev.read(addr, buf),
+            BlockDevice::Virtio(dev) => dev.read(addr, buf),
+            BlockDevice::Partition(dev) => dev.read(addr, buf),
         }
     }
 
@@ -37,6 +145,29 @@ impl BlockDeviceIO for BlockDevice {
         match self {
             BlockDevice::Mem(dev) => dev.write(addr, buf),
             BlockDevice::Ata(dev) => dev.write(addr, buf),
+            BlockDevice::Ahci(dev) => dev.write(addr, buf),
+            BlockDevice::Virtio(dev) => dev.write(addr, buf),
+            BlockDevice::Partition(dev) => dev.write(addr, buf),
+        }
+    }
+
+    fn read_blocks(&self, addr: u32, buf: &mut [u8]) -> Result<(), ()> {
+        match self {
+            BlockDevice::Mem(dev) => dev.read_blocks(addr, buf),
+            BlockDevice::Ata(dev) => dev.read_blocks(addr, buf),
+            BlockDevice::Ahci(dev) => dev.read_blocks(addr, buf),
+            BlockDevice::Virtio(dev) => dev.read_blocks(addr, buf),
+            BlockDevice::Partition(dev) => dev.read_blocks(addr, buf),
+        }
+    }
+
+    fn write_blocks(&mut self, addr: u32, buf: &[u8]) -> Result<(), ()> {
+        match self {
+            BlockDevice::Mem(dev) => dev.write_blocks(addr, buf),
+            BlockDevice::Ata(dev) => dev.write_blocks(addr, buf),
+            BlockDevice::Ahci(dev) => dev.write_blocks(addr, buf),
+            BlockDevice::Virtio(dev) => dev.write_blocks(addr, buf),
+            BlockDevice::Partition(dev) => dev.write_blocks(addr, buf),
         }
     }
 
@@ -44,6 +175,9 @@ impl BlockDeviceIO for BlockDevice {
         match self {
             BlockDevice::Mem(dev) => dev.block_size() as usize,
             BlockDevice::Ata(dev) => dev.block_size() as usize,
+            BlockDevice::Ahci(dev) => dev.block_size() as usize,
+            BlockDevice::Virtio(dev) => dev.block_size() as usize,
+            BlockDevice::Partition(dev) => dev.block_size() as usize,
         }
     }
 
@@ -51,37 +185,56 @@ impl BlockDeviceIO for BlockDevice {
         match self {
             BlockDevice::Mem(dev) => dev.block_count() as usize,
             BlockDevice::Ata(dev) => dev.block_count() as usize,
+            BlockDevice::Ahci(dev) => dev.block_count() as usize,
+            BlockDevice::Virtio(dev) => dev.block_count() as usize,
+            BlockDevice::Partition(dev) => dev.block_count() as usize,
         }
     }
 }
 
+// The blocks of a RAM disk are only allocated when they are written, so its
+// size is only an upper bound on the memory used.
 pub struct MemBlockDevice {
-    dev: Vec<[u8; super::BLOCK_SIZE]>,
+    dev: Vec<Option<Box<[u8; super::BLOCK_SIZE]>>>,
 }
 
 impl MemBlockDevice {
     pub fn new(len: usize) -> Self {
-        let dev = vec![[0; super::BLOCK_SIZE]; len];
+        let dev = vec![None; len];
         Self { dev }
     }
-
-    /*
-    pub fn len(&self) -> usize {
-        self.dev.len()
-    }
-    */
 }
 
 impl BlockDeviceIO for MemBlockDevice {
     fn read(&self, block_index: u32, buf: &mut [u8]) -> Result<(), ()> {
-        // TODO: check for overflow
-        buf[..].clone_from_slice(&self.dev[block_index as usize][..]);
+        if buf.len() != super::BLOCK_SIZE {
+            return Err(());
+        }
+        match self.dev.get(block_index as usize) {
+            Some(Some(block)) => buf.copy_from_slice(&block[..]),
+            Some(None) => buf.fill(0),
+            None => return Err(()),
+        }
         Ok(())
     }
 
     fn write(&mut self, block_index: u32, buf: &[u8]) -> Result<(), ()> {
-        // TODO: check for overflow
-        self.dev[block_index as usize][..].clone_from_slice(buf);
+        if buf.len() != super::BLOCK_SIZE {
+            return Err(());
+        }
+        match self.dev.get_mut(block_index as usize) {
+            Some(Some(block)) => block.copy_from_slice(buf),
+            Some(block) => {
+                // Keep some memory for the rest of the system
+                if sys::allocator::memory_free() < MEM_RESERVE {
+                    return Err(());
+                }
+                let mut data = Box::new([0; super::BLOCK_SIZE]);
+                data.copy_from_slice(buf);
+                *block = Some(data);
+            }
+            None => return Err(()),
+        }
         Ok(())
     }
 
@@ -94,15 +247,19 @@ impl BlockDeviceIO for MemBlockDevice {
     }
 }
 
+// Default size of a RAM disk
+pub fn mem_size() -> usize {
+    sys::allocator::memory_size() / 2 // Half the allocatable memory
+}
+
 pub fn mount_mem() {
-    let mem = sys::allocator::memory_size() / 2; // Half the allocatable memory
-    let len = mem / super::BLOCK_SIZE; // TODO: take a size argument
+    let len = mem_size() / super::BLOCK_SIZE;
     let dev = MemBlockDevice::new(len);
-    *BLOCK_DEVICE.lock() = Some(BlockDevice::Mem(dev));
+    mount::mount("/", BlockDevice::Mem(dev), Arc::new(Mfs));
 }
 
 pub fn format_mem() {
-    debug_assert!(is_mounted());
+    debug_assert!(mount::with_device(|_| ()).is_some());
     if let Some(sb) = SuperBlock::new() {
         sb.write();
         let root = Dir::root();
@@ -110,32 +267,143 @@ pub fn format_mem() {
     }
 }
 
-#[derive(Clone)]
-pub struct AtaBlockDevice {
-    dev: sys::ata::Drive
+// Create a RAM disk of the given size in bytes, mount it on the given path,
+// and format it.
+pub fn mount_mem_disk(path: &str, size: usize) -> Result<(), ()> {
+    let len = size / super::BLOCK_SIZE;
+    if len <= SuperBlock::min_block_count() {
+        return Err(());
+    }
+    let dev = MemBlockDevice::new(len);
+    let _dev = mount::select(mount::mount(path, BlockDevice::Mem(dev), Arc::new(Mfs)));
+    format_mem();
+    Ok(())
+}
+
+// A disk driver reading and writing consecutive blocks, which splits the
+// transfers into commands of up to its maximum number of blocks.
+pub trait Drive {
+    fn block_size(&self) -> u32;
+    fn block_count(&self) -> u64;
+    fn read(&self, addr: u64, buf: &mut [u8]) -> Result<(), ()>;
+    fn write(&self, addr: u64, buf: &[u8]) -> Result<(), ()>;
+}
+
+impl Drive for sys::ata::Drive {
+    fn block_size(&self) -> u32 {
+        sys::ata::Drive::block_size(self)
+    }
+
+    fn block_count(&self) -> u64 {
+        sys::ata::Drive::block_count(self)
+    }
+
+    fn read(&self, addr: u64, buf: &mut [u8]) -> Result<(), ()> {
+        sys::ata::read(self.bus, self.dsk, addr, buf)
+    }
+
+    fn write(&self, addr: u64, buf: &[u8]) -> Result<(), ()> {
+        sys::ata::write(self.bus, self.dsk, addr, buf)
+    }
+}
+
+impl Drive for sys::ahci::Drive {
+    fn block_size(&self) -> u32 {
+        sys::ahci::Drive::block_size(self)
+    }
+
+    fn block_count(&self) -> u64 {
+        sys::ahci::Drive::block_count(self)
+    }
+
+    fn read(&self, addr: u64, buf: &mut [u8]) -> Result<(), ()> {
+        sys::ahci::read(self.id, addr, buf)
+    }
+
+    fn write(&self, addr: u64, buf: &[u8]) -> Result<(), ()> {
+        sys::ahci::write(self.id, addr, buf)
+    }
 }
 
+impl Drive for sys::virtio::Drive {
+    fn block_size(&self) -> u32 {
+        sys::virtio::Drive::block_size(self)
+    }
+
+    fn block_count(&self) -> u64 {
+        sys::virtio::Drive::block_count(self)
+    }
+
+    fn read(&self, addr: u64, buf: &mut [u8]) -> Result<(), ()> {
+        sys::virtio::read(self.id, addr, buf)
+    }
+
+    fn write(&self, addr: u64, buf: &[u8]) -> Result<(), ()> {
+        sys::virtio::write(self.id, addr, buf)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DriveBlockDevice<T: Drive> {
+    dev: T
+}
+
+pub type AtaBlockDevice = DriveBlockDevice<sys::ata::Drive>;
+pub type AhciBlockDevice = DriveBlockDevice<sys::ahci::Drive>;
+pub type VirtioBlockDevice = DriveBlockDevice<sys::virtio::Drive>;
+
 impl AtaBlockDevice {
     pub fn new(bus: u8, dsk: u8) -> Option<Self> {
         sys::ata::Drive::open(bus, dsk).map(|dev| {
             Self { dev }
         })
     }
+}
 
-    /*
-    pub fn len(&self) -> usize {
-        self.block_size() * self.block_count()
+impl AhciBlockDevice {
+    pub fn new(id: usize) -> Option<Self> {
+        sys::ahci::Drive::open(id).map(|dev| {
+            Self { dev }
+        })
     }
-    */
 }
 
-impl BlockDeviceIO for AtaBlockDevice {
+impl VirtioBlockDevice {
+    pub fn new(id: usize) -> Option<Self> {
+        sys::virtio::Drive::open(id).map(|dev| {
+            Self { dev }
+        })
+    }
+}
+
+impl<T: Drive> BlockDeviceIO for DriveBlockDevice<T> {
     fn read(&self, block_addr: u32, buf: &mut [u8]) -> Result<(), ()> {
-        sys::ata::read(self.dev.bus, self.dev.dsk, block_addr, buf)
+        if buf.len() != self.block_size() {
+            return Err(());
+        }
+        self.dev.read(block_addr as u64, buf)
     }
 
     fn write(&mut self, block_addr: u32, buf: &[u8]) -> Result<(), ()> {
-        sys::ata::write(self.dev.bus, self.dev.dsk, block_addr, buf)
+        if buf.len() != self.block_size() {
+            return Err(());
+        }
+        self.dev.write(block_addr as u64, buf)
+    }
+
+    // The driver splits the buffer into commands of up to its `MAX_BLOCKS`
+    fn read_blocks(&self, block_addr: u32, buf: &mut [u8]) -> Result<(), ()> {
+        if buf.len() % self.block_size() != 0 {
+            return Err(());
+        }
+        self.dev.read(block_addr as u64, buf)
+    }
+
+    fn write_blocks(&mut self, block_addr: u32, buf: &[u8]) -> Result<(), ()> {
+        if buf.len() % self.block_size() != 0 {
+            return Err(());
+        }
+        self.dev.write(block_addr as u64, buf)
     }
 
     fn block_size(&self) -> usize {
@@ -147,31 +415,252 @@ impl BlockDeviceIO for AtaBlockDevice {
     }
 }
 
-pub fn mount_ata(bus: u8, dsk: u8) {
-    *BLOCK_DEVICE.lock() = AtaBlockDevice::new(bus, dsk).map(BlockDevice::Ata);
+// A partition of a disk found in its MBR or GPT, where the block addresses
+// are relative to the first block of the partition.
+pub struct PartitionBlockDevice {
+    dev: Box<BlockDevice>,
+    n: usize,
+    start: u32,
+    len: u32,
 }
 
-pub fn format_ata() {
-    if let Some(sb) = SuperBlock::new() {
+impl PartitionBlockDevice {
+    // Partitions are numbered from 1 like the entries of their table
+    pub fn new(dev: BlockDevice, n: usize) -> Option<Self> {
+        let p = partition::read(&dev).get(n.checked_sub(1)?).copied().flatten()?;
+        if p.start.checked_add(p.len)? > dev.block_count() as u64 {
+            return None;
+        }
+        let start = u32::try_from(p.start).ok()?;
+        let len = u32::try_from(p.len).ok()?;
+        Some(Self { dev: Box::new(dev), n, start, len })
+    }
+
+    fn addr(&self, addr: u32, len: usize) -> Result<u32, ()> {
+        let n = (len / self.block_size()) as u32;
+        if addr.checked_add(n).map_or(true, |end| end > self.len) {
+            return Err(());
+        }
+        Ok(self.start + addr)
+    }
+}
+
+impl BlockDeviceIO for PartitionBlockDevice {
+    fn read(&self, addr: u32, buf: &mut [u8]) -> Result<(), ()> {
+        self.dev.read(self.addr(addr, buf.len())?, buf)
+    }
+
+    fn write(&mut self, addr: u32, buf: &[u8]) -> Result<(), ()> {
+        let addr = self.addr(addr, buf.len())?;
+        self.dev.write(addr, buf)
+    }
+
+    fn read_blocks(&self, addr: u32, buf: &mut [u8]) -> Result<(), ()> {
+        self.dev.read_blocks(self.addr(addr, buf.len())?, buf)
+    }
+
+    fn write_blocks(&mut self, addr: u32, buf: &[u8]) -> Result<(), ()> {
+        let addr = self.addr(addr, buf.len())?;
+        self.dev.write_blocks(addr, buf)
+    }
+
+    fn block_size(&self) -> usize {
+        self.dev.block_size()
+    }
+
+    fn block_count(&self) -> usize {
+        self.len as usize
+    }
+}
+
+// Open the block device of a disk path like `/dev/ata/<bus>/<dsk>`,
+// `/dev/ahci/<id>` or `/dev/virtio/<id>`, or of one of its partitions with
+// its number appended to the path, like `/dev/ata/0/0/1`.
+pub fn open_device(path: &str) -> Option<BlockDevice> {
+    let fields: Vec<_> = path.split('/').collect();
+    match fields[..] {
+        ["", "dev", "ata", _, _, n] | ["", "dev", "ahci", _, n] | ["", "dev", "virtio", _, n] => {
+            let n = n.parse().ok()?;
+            let dev = open_device(super::dirname(path))?;
+            PartitionBlockDevice::new(dev, n).map(BlockDevice::Partition)
+        }
+        ["", "dev", "ata", bus, dsk] => {
+            let bus = bus.parse().ok()?;
+            let dsk = dsk.parse().ok()?;
+            AtaBlockDevice::new(bus, dsk).map(BlockDevice::Ata)
+        }
+        ["", "dev", "ahci", id] => {
+            let id = id.parse().ok()?;
+            AhciBlockDevice::new(id).map(BlockDevice::Ahci)
+        }
+        ["", "dev", "virtio", id] => {
+            let id = id.parse().ok()?;
+            VirtioBlockDevice::new(id).map(BlockDevice::Virtio)
+        }
+        _ => None,
+    }
+}
+
+// Return the paths of the disks found on the ATA buses and on the AHCI and
+// VirtIO controllers
+pub fn disks() -> Vec<String> {
+    let mut res = Vec::new();
+    for drive in sys::ata::list() {
+        res.push(format!("/dev/ata/{}/{}", drive.bus, drive.dsk));
+    }
+    for drive in sys::ahci::list() {
+        res.push(format!("/dev/ahci/{}", drive.id));
+    }
+    for drive in sys::virtio::list() {
+        res.push(format!("/dev/virtio/{}", drive.id));
+    }
+    res
+}
+
+// Return the numbers and the partitions found on a disk
+pub fn partitions(dev: &BlockDevice) -> Vec<(usize, partition::Partition)> {
+    partition::read(dev).into_iter().enumerate().filter_map(|(i, p)| {
+        p.map(|p| (i + 1, p))
+    }).collect()
+}
+
+// Mount the MFS of a disk to '/'
+pub fn mount_device(dev: BlockDevice) {
+    mount::mount("/", dev, Arc::new(Mfs));
+}
+
+// Format a disk and mount it to '/', or return an error without replacing
+// the current root if the disk is too small or too large for MFS.
+pub fn format_disk(dev: BlockDevice) -> Result<(), ()> {
+    if let Some(sb) = SuperBlock::for_device(&dev) {
+        mount_device(dev);
+
         // Write super_block
         sb.write();
 
+        // Clear the journal
+        super::journal::clear();
+
         // Write zeros into block bitmaps
-        super::bitmap_block::free_all();
+        super::bitmap_block::free_all()?;
 
         // Allocate root dir
         debug_assert!(is_mounted());
         let root = Dir::root();
         BitmapBlock::alloc(root.addr());
+        Ok(())
+    } else {
+        Err(())
     }
 }
 
+// Check if a filesystem is mounted to '/'
 pub fn is_mounted() -> bool {
-    BLOCK_DEVICE.lock().is_some()
+    mount::is_mount_point("/")
 }
 
 pub fn dismount() {
-    *BLOCK_DEVICE.lock() = None;
+    mount::umount_all();
+}
+
+#[test_case]
+fn test_mem_block_device() {
+    let mut dev = MemBlockDevice::new(4);
+    let mut buf = [0xFF; super::BLOCK_SIZE];
+    assert_eq!(dev.read(3, &mut buf), Ok(()));
+    assert_eq!(buf, [0; super::BLOCK_SIZE]);
+    assert_eq!(dev.write(3, &[42; super::BLOCK_SIZE]), Ok(()));
+    assert_eq!(dev.read(3, &mut buf), Ok(()));
+    assert_eq!(buf, [42; super::BLOCK_SIZE]);
+    assert_eq!(dev.read(4, &mut buf), Err(()));
+    assert_eq!(dev.write(4, &buf), Err(()));
+    assert_eq!(dev.write(0, &buf[1..]), Err(()));
+
+    let mut buf = [0; 2 * super::BLOCK_SIZE];
+    assert_eq!(dev.write_blocks(0, &[7; 2 * super::BLOCK_SIZE]), Ok(()));
+    assert_eq!(dev.read_blocks(0, &mut buf), Ok(()));
+    assert_eq!(buf, [7; 2 * super::BLOCK_SIZE]);
+    assert_eq!(dev.read_blocks(3, &mut buf), Err(()));
+}
+
+#[test_case]
+fn test_partition_block_device() {
+    // A disk of 8 blocks with a partition of 4 blocks from the third block,
+    // and a partition exceeding the disk
+    let mut mbr = [0; super::BLOCK_SIZE];
+    mbr[510] = 0x55;
+    mbr[511] = 0xAA;
+    mbr[446 + 4] = 0x83;
+    mbr[446 + 8..446 + 12].copy_from_slice(&2u32.to_le_bytes());
+    mbr[446 + 12..446 + 16].copy_from_slice(&4u32.to_le_bytes());
+    mbr[462 + 4] = 0x83;
+    mbr[462 + 8..462 + 12].copy_from_slice(&6u32.to_le_bytes());
+    mbr[462 + 12..462 + 16].copy_from_slice(&4u32.to_le_bytes());
+    let disk = || {
+        let mut dev = MemBlockDevice::new(8);
+        dev.write(0, &mbr).unwrap();
+        BlockDevice::Mem(dev)
+    };
+    assert!(PartitionBlockDevice::new(disk(), 0).is_none());
+    assert!(PartitionBlockDevice::new(disk(), 2).is_none());
+    assert!(PartitionBlockDevice::new(disk(), 3).is_none());
+
+    let mut dev = PartitionBlockDevice::new(disk(), 1).unwrap();
+    assert_eq!(dev.block_count(), 4);
+    let mut buf = [0; super::BLOCK_SIZE];
+    assert_eq!(dev.write(1, &[42; super::BLOCK_SIZE]), Ok(()));
+    assert_eq!(dev.dev.read(3, &mut buf), Ok(()));
+    assert_eq!(buf, [42; super::BLOCK_SIZE]);
+    assert_eq!(dev.read(1, &mut buf), Ok(()));
+    assert_eq!(buf, [42; super::BLOCK_SIZE]);
+
+    let mut buf = [0; 2 * super::BLOCK_SIZE];
+    assert_eq!(dev.write_blocks(2, &[7; 2 * super::BLOCK_SIZE]), Ok(()));
+    assert_eq!(dev.dev.read_blocks(4, &mut buf), Ok(()));
+    assert_eq!(buf, [7; 2 * super::BLOCK_SIZE]);
+    assert_eq!(dev.read_blocks(3, &mut buf), Err(()));
+    assert_eq!(dev.write_blocks(3, &buf), Err(()));
+    assert_eq!(dev.read(4, &mut buf[0..super::BLOCK_SIZE]), Err(()));
+    assert_eq!(dev.read(u32::MAX, &mut buf[0..super::BLOCK_SIZE]), Err(()));
+}
+
+#[test_case]
+fn test_blocks_count() {
+    let bs = super::BLOCK_SIZE;
+    assert_eq!(blocks_count(bs, bs, 64), Ok(1));
+    assert_eq!(blocks_count(64 * bs, bs, 64), Ok(64));
+    assert_eq!(blocks_count(0, bs, 64), Err(()));
+    assert_eq!(blocks_count(bs + 1, bs, 64), Err(()));
+    assert_eq!(blocks_count(65 * bs, bs, 64), Err(()));
+}
+
+#[test_case]
+fn test_chunks() {
+    let mut addrs = Vec::new();
+    let mut buf = [0; 5 * super::BLOCK_SIZE];
+    let res = read_chunks(8, &mut buf, 2 * super::BLOCK_SIZE, super::BLOCK_SIZE, |addr, chunk| {
+        addrs.push((addr, chunk.len()));
+        Ok(())
+    });
+    assert_eq!(res, Ok(()));
+    assert_eq!(addrs, vec![(8, 1024), (10, 1024), (12, 512)]);
+
+    // Chunks of blocks larger than the blocks of the buffer
+    addrs.clear();
+    let res = write_chunks(8, &buf, 4 * super::BLOCK_SIZE, 2048, |addr, chunk| {
+        addrs.push((addr, chunk.len()));
+        Err(())
+    });
+    assert_eq!(res, Err(()));
+    assert_eq!(addrs, vec![(8, 2048)]);
+}
+
+#[test_case]
+fn test_humanized_size() {
+    assert_eq!(humanized_size(0), (0, "MB"));
+    assert_eq!(humanized_size(32 << 20), (32, "MB"));
+    assert_eq!(humanized_size(999 << 20), (999, "MB"));
+    assert_eq!(humanized_size(2 << 30), (2, "GB"));
 }
 
 #[test_case]
@@ -181,3 +670,13 @@ fn test_mount_mem() {
     assert!(is_mounted());
     dismount();
 }
+
+#[test_case]
+fn test_open_device() {
+    assert!(open_device("mem").is_none());
+    assert!(open_device("/dev/ata/0").is_none());
+    assert!(open_device("/dev/ahci/42").is_none());
+    assert!(open_device("/dev/ahci/42/1").is_none());
+    assert!(open_device("/dev/virtio/a").is_none());
+    assert!(open_device("/dev/virtio/42").is_none());
+}
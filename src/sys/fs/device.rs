@@ -1,28 +1,101 @@
 use super::{dirname, filename, realpath, FileIO};
 use super::dir::Dir;
+use super::dir_entry::DirEntry;
 use super::file::File;
-use super::block::LinkedBlock;
+use super::vfs::Node;
 
+use alloc::boxed::Box;
 use alloc::vec;
 use alloc::vec::Vec;
+use core::convert::TryFrom;
+use crate::sys::clock::{Realtime, Uptime};
+use crate::sys::cmos::RTC;
 use crate::sys::console::Console;
+use crate::sys::mouse::{MouseDevice, MouseEvent};
+use crate::sys::net::Net;
 use crate::sys::random::Random;
+use crate::sys::serial::SerialDevice;
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 #[repr(u8)]
 pub enum DeviceType {
     File = 0,
     Console = 1,
     Random = 2,
     Null = 3,
+    Uptime = 4,
+    Realtime = 5,
+    RTC = 6,
+    Net = 7,
+    Serial0 = 8,
+    Serial1 = 9,
+    Mouse = 10,
 }
 
 impl DeviceType {
+    // The size of a device file is the size of a read from this device
     pub fn buf(self) -> Vec<u8> {
-        if self == DeviceType::Console {
-            vec![self as u8, 0, 0, 0] // 4 bytes
-        } else {
-            vec![self as u8] // 1 byte
+        let len = match self {
+            DeviceType::Console => 4,
+            DeviceType::Uptime => Uptime::size(),
+            DeviceType::Realtime => Realtime::size(),
+            DeviceType::RTC => RTC::size(),
+            DeviceType::Mouse => MouseEvent::size(),
+            _ => 1,
+        };
+        let mut res = vec![0; len];
+        res[0] = self as u8;
+        res
+    }
+}
+
+impl TryFrom<u8> for DeviceType {
+    type Error = ();
+
+    fn try_from(i: u8) -> Result<Self, ()> {
+        match i {
+            0 => Ok(DeviceType::File),
+            1 => Ok(DeviceType::Console),
+            2 => Ok(DeviceType::Random),
+            3 => Ok(DeviceType::Null),
+            4 => Ok(DeviceType::Uptime),
+            5 => Ok(DeviceType::Realtime),
+            6 => Ok(DeviceType::RTC),
+            7 => Ok(DeviceType::Net),
+            8 => Ok(DeviceType::Serial0),
+            9 => Ok(DeviceType::Serial1),
+            10 => Ok(DeviceType::Mouse),
+            _ => Err(()),
+        }
+    }
+}
+
+// Typed requests sent to a device handle with the control syscall
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[repr(usize)]
+pub enum ControlRequest {
+    GetConsoleMode = 1,
+    SetConsoleMode = 2,
+    GetConsoleSize = 3,
+    SetVgaPalette = 4,
+    SetVgaFont = 5,
+    SetNetDebug = 6,
+    SetMouseCursor = 7,
+}
+
+impl TryFrom<usize> for ControlRequest {
+    type Error = ();
+
+    fn try_from(n: usize) -> Result<Self, ()> {
+        match n {
+            1 => Ok(ControlRequest::GetConsoleMode),
+            2 => Ok(ControlRequest::SetConsoleMode),
+            3 => Ok(ControlRequest::GetConsoleSize),
+            4 => Ok(ControlRequest::SetVgaPalette),
+            5 => Ok(ControlRequest::SetVgaFont),
+            6 => Ok(ControlRequest::SetNetDebug),
+            7 => Ok(ControlRequest::SetMouseCursor),
+            _ => Err(()),
         }
     }
 }
@@ -32,20 +105,15 @@ pub enum Device {
     File(File),
     Console(Console),
     Random(Random),
+    Uptime(Uptime),
+    Realtime(Realtime),
+    RTC(RTC),
+    Net(Net),
+    Serial(SerialDevice),
+    Mouse(MouseDevice),
     Null,
 }
 
-impl From<u8> for Device {
-    fn from(i: u8) -> Self {
-        match i {
-            i if i == DeviceType::Console as u8 => Device::Console(Console::new()),
-            i if i == DeviceType::Random as u8 => Device::Random(Random::new()),
-            i if i == DeviceType::Null as u8 => Device::Null,
-            _ => unimplemented!(),
-        }
-    }
-}
-
 impl Device {
     pub fn create(pathname: &str) -> Option<Self> {
         let pathname = realpath(pathname);
@@ -59,20 +127,37 @@ impl Device {
         None
     }
 
+    // Open the device of a device file, or return None if its type is
+    // unknown or if it is a plain file like on the disks formatted before
+    // the clock devices were added.
     pub fn open(pathname: &str) -> Option<Self> {
-        let pathname = realpath(pathname);
-        let dirname = dirname(&pathname);
-        let filename = filename(&pathname);
-        if let Some(dir) = Dir::open(dirname) {
-            if let Some(dir_entry) = dir.find(filename) {
-                if dir_entry.is_device() {
-                    let block = LinkedBlock::read(dir_entry.addr());
-                    let data = block.data();
-                    return Some(data[0].into());
-                }
-            }
+        match device_type(pathname)? {
+            DeviceType::File => None,
+            DeviceType::Console => Some(Device::Console(Console::new())),
+            DeviceType::Random => Some(Device::Random(Random::new())),
+            DeviceType::Null => Some(Device::Null),
+            DeviceType::Uptime => Some(Device::Uptime(Uptime::new())),
+            DeviceType::Realtime => Some(Device::Realtime(Realtime::new())),
+            DeviceType::RTC => Some(Device::RTC(RTC::new())),
+            DeviceType::Net => Some(Device::Net(Net::new())),
+            DeviceType::Serial0 => Some(Device::Serial(SerialDevice::new(0))),
+            DeviceType::Serial1 => Some(Device::Serial(SerialDevice::new(1))),
+            DeviceType::Mouse => Some(Device::Mouse(MouseDevice::new())),
         }
-        None
+    }
+}
+
+// Return the type stored in the first byte of a device file
+pub fn device_type(pathname: &str) -> Option<DeviceType> {
+    let dir_entry = DirEntry::open(pathname)?;
+    if !dir_entry.is_device() {
+        return None;
+    }
+    let mut file = File::from(dir_entry);
+    let mut buf = [0; 1];
+    match file.read(&mut buf) {
+        Ok(1) => DeviceType::try_from(buf[0]).ok(),
+        _ => None,
     }
 }
 
@@ -82,6 +167,12 @@ impl FileIO for Device {
             Device::File(io) => io.read(buf),
             Device::Console(io) => io.read(buf),
             Device::Random(io) => io.read(buf),
+            Device::Uptime(io) => io.read(buf),
+            Device::Realtime(io) => io.read(buf),
+            Device::RTC(io) => io.read(buf),
+            Device::Net(io) => io.read(buf),
+            Device::Serial(io) => io.read(buf),
+            Device::Mouse(io) => io.read(buf),
             Device::Null => Err(()),
         }
     }
@@ -90,7 +181,61 @@ impl FileIO for Device {
             Device::File(io) => io.write(buf),
             Device::Console(io) => io.write(buf),
             Device::Random(io) => io.write(buf),
+            Device::Uptime(io) => io.write(buf),
+            Device::Realtime(io) => io.write(buf),
+            Device::RTC(io) => io.write(buf),
+            Device::Net(io) => io.write(buf),
+            Device::Serial(io) => io.write(buf),
+            Device::Mouse(io) => io.write(buf),
             Device::Null => Ok(0),
         }
     }
 }
+
+impl Node for Device {
+    // Only the file of a device has an offset
+    fn read_at(&mut self, offset: usize, buf: &mut [u8]) -> Result<usize, ()> {
+        match self {
+            Device::File(io) => io.read_at(offset, buf),
+            _ => self.read(buf),
+        }
+    }
+
+    fn write_at(&mut self, offset: usize, buf: &[u8]) -> Result<usize, ()> {
+        match self {
+            Device::File(io) => io.write_at(offset, buf),
+            _ => self.write(buf),
+        }
+    }
+
+    fn control(&mut self, request: ControlRequest, buf: &mut [u8]) -> Result<usize, ()> {
+        match self {
+            Device::Console(io) => io.control(request, buf),
+            Device::Net(io) => io.control(request, buf),
+            _ => Err(()),
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+}
+
+#[test_case]
+fn test_device_type() {
+    assert_eq!(DeviceType::try_from(DeviceType::Mouse as u8), Ok(DeviceType::Mouse));
+    assert_eq!(DeviceType::try_from(255), Err(()));
+    assert_eq!(ControlRequest::try_from(6), Ok(ControlRequest::SetNetDebug));
+    assert_eq!(ControlRequest::try_from(0), Err(()));
+
+    // The clocks are plain device files on old disks
+    super::mount_mem();
+    super::format_mem();
+    let mut dev = Device::create("/rtc").unwrap();
+    dev.write(&DeviceType::File.buf()).unwrap();
+    assert_eq!(device_type("/rtc"), Some(DeviceType::File));
+    assert!(Device::open("/rtc").is_none());
+    dev.write_at(0, &DeviceType::RTC.buf()).unwrap();
+    assert_eq!(device_type("/rtc"), Some(DeviceType::RTC));
+    super::dismount();
+}
@@ -1,36 +1,50 @@
 use super::{dirname, filename, realpath, FileIO};
 use super::super_block::SuperBlock;
-use super::dir_entry::DirEntry;
-use super::read_dir::ReadDir;
+use super::dir_entry::{DirEntry, FileInfo};
+use super::read_dir::{Cursor, ReadDir};
+use super::watch::{self, EventKind};
 use super::bitmap_block::BitmapBlock;
 use super::FileType;
 use super::block::LinkedBlock;
+use super::index_block::{self, IndexBlock};
+use super::file;
+use super::journal;
+use super::mount;
+use super::vfs::{self, Node};
 use crate::sys;
 
 use alloc::boxed::Box;
 use alloc::string::String;
+use alloc::vec::Vec;
 use core::convert::From;
 
+// Maximum number of blocks of a dir freed in a transaction
+const FREE_BATCH: usize = 16;
+
 #[derive(Debug, Clone)]
 pub struct Dir {
     parent: Option<Box<Dir>>,
     name: String,
     addr: u32,
     size: u32,
-    entry_index: u32,
+    cursor: Option<Cursor>, // Position of the next entry read from the dir
+    dev: usize,
 }
 
 impl From<DirEntry> for Dir {
     fn from(entry: DirEntry) -> Self {
-        Self { parent: Some(Box::new(entry.dir())), name: entry.name(), addr: entry.addr(), size: entry.size(), entry_index: 0 }
+        let dev = entry.dir().dev();
+        Self { parent: Some(Box::new(entry.dir())), name: entry.name(), addr: entry.addr(), size: entry.size(), cursor: None, dev }
     }
 }
 
 impl Dir {
+    // Root dir of the selected device
     pub fn root() -> Self {
         let name = String::new();
         let addr = SuperBlock::read().data_area();
-        let mut root = Self { parent: None, name, addr, size: 0, entry_index: 0 };
+        let dev = mount::current();
+        let mut root = Self { parent: None, name, addr, size: 0, cursor: None, dev };
         root.update_size();
         root
     }
@@ -52,22 +66,27 @@ impl Dir {
     }
 
     pub fn open(pathname: &str) -> Option<Self> {
-        if !super::is_mounted() {
-            return None;
-        }
+        let mut links = 0;
+        Self::open_with_links(pathname, &mut links)
+    }
 
+    pub fn open_with_links(pathname: &str, links: &mut usize) -> Option<Self> {
+        let (_dev, pathname) = mount::select_path(&realpath(pathname))?;
         let mut dir = Dir::root();
-        let pathname = realpath(pathname);
 
         if pathname == "/" {
             return Some(dir);
         }
 
+        let mut dirname = String::from("/");
         for name in pathname.trim_start_matches('/').split('/') {
             match dir.find(name) {
                 Some(dir_entry) => {
                     if dir_entry.is_dir() {
                         dir = dir_entry.into()
+                    } else if dir_entry.is_link() {
+                        let target = dir_entry.target(&dirname, links)?;
+                        dir = Dir::open_with_links(&target, links)?;
                     } else {
                         return None;
                     }
@@ -76,6 +95,10 @@ impl Dir {
                     return None
                 },
             }
+            if !dirname.ends_with('/') {
+                dirname.push('/');
+            }
+            dirname.push_str(name);
         }
         Some(dir)
     }
@@ -84,6 +107,19 @@ impl Dir {
         self.addr
     }
 
+    // Id of the device in the mount table
+    pub fn dev(&self) -> usize {
+        self.dev
+    }
+
+    // Path of the dir from the root of the VFS
+    pub fn path(&self) -> String {
+        match &self.parent {
+            Some(parent) => watch::join(&parent.path(), &self.name),
+            None => mount::path(self.dev).unwrap_or_else(|| "/".into()),
+        }
+    }
+
     pub fn find(&self, name: &str) -> Option<DirEntry> {
         for entry in self.entries() {
             if entry.name() == name {
@@ -106,19 +142,65 @@ impl Dir {
         self.create_entry(FileType::Device, name)
     }
 
+    pub fn create_link(&mut self, name: &str) -> Option<DirEntry> {
+        self.create_entry(FileType::Link, name)
+    }
+
+    // Create an entry sharing the blocks of another entry, with the size and
+    // the modification time of the file moved to its root block.
+    pub fn create_hard_link(&mut self, name: &str, source: &DirEntry) -> Option<DirEntry> {
+        if source.dir().dev() != self.dev || self.find(name).is_some() {
+            return None;
+        }
+        let _dev = mount::select(self.dev);
+        journal::transaction(|| {
+            let mut root = IndexBlock::read_root(source.addr());
+            let n = root.links().checked_add(1)?;
+            let mut source = source.clone();
+            if !source.is_shared() {
+                root.share_metadata(source.size(), source.mtime())?;
+                source.dir().update_entry_with(&source.name(), |e| e.set_shared(true)).ok()?;
+                source.set_shared(true);
+            }
+            root.set_links(n);
+            root.write();
+            self.append_entry(source.kind(), name, Some(&source))
+        }).ok().flatten().map(|entry| {
+            watch::post(EventKind::Create, &watch::join(&self.path(), name));
+            entry
+        })
+    }
+
     fn create_entry(&mut self, kind: FileType, name: &str) -> Option<DirEntry> {
         if self.find(name).is_some() {
             return None;
         }
 
+        // The new entry, its first block, the bitmap and the superblock are
+        // written together in a transaction.
+        let _dev = mount::select(self.dev);
+        journal::transaction(|| self.append_entry(kind, name, None)).ok().flatten().map(|entry| {
+            watch::post(EventKind::Create, &watch::join(&self.path(), name));
+            entry
+        })
+    }
+
+    // Append an entry to the dir, with a new block or the block of the given
+    // source entry in the case of a hard link.
+    fn append_entry(&mut self, kind: FileType, name: &str, source: Option<&DirEntry>) -> Option<DirEntry> {
         // Read the whole dir to add an entry at the end
         let mut entries = self.entries();
         while entries.next().is_some() {}
 
+        // Create a new entry
+        let entry_name = truncate(name, u8::MAX as usize);
+        let entry_time = sys::clock::realtime() as u64;
+        let mut entry = DirEntry::new(self.clone(), kind, 0, 0, entry_time, &entry_name);
+        entry.set_format(entries.format());
+
         // Allocate a new block for the dir if no space left for adding the new entry
         let space_left = entries.block.data().len() - entries.block_offset();
-        let entry_len = DirEntry::empty_len() + name.len();
-        if entry_len > space_left {
+        if entry.len() > space_left {
             match entries.block.alloc_next() {
                 None => return None, // Disk is full
                 Some(new_block) => {
@@ -128,33 +210,107 @@ impl Dir {
             }
         }
 
-        // Create a new entry
-        let entry_block = LinkedBlock::alloc().unwrap();
-        let entry_kind = kind as u8;
-        let entry_addr = entry_block.addr();
-        let entry_size = 0u32;
-        let entry_time = sys::clock::realtime() as u64;
-        let entry_name = truncate(name, u8::MAX as usize);
-        let n = entry_name.len();
+        if let Some(source) = source {
+            entry.set_addr(source.addr());
+            entry.set_size(source.size());
+            entry.set_uid(source.uid());
+            entry.set_mode(source.mode());
+            entry.set_ctime(source.ctime());
+            entry.set_mtime(source.mtime());
+            entry.set_shared(source.is_shared());
+        } else {
+            let entry_block = LinkedBlock::alloc()?;
+            entry.set_addr(entry_block.addr());
+        }
+        let bytes = entry.as_bytes();
         let i = entries.block_offset();
         let data = entries.block.data_mut();
-
-        data[i] = entry_kind;
-        data[(i + 1)..(i + 5)].clone_from_slice(&entry_addr.to_be_bytes());
-        data[(i + 5)..(i + 9)].clone_from_slice(&entry_size.to_be_bytes());
-        data[(i + 9)..(i + 17)].clone_from_slice(&entry_time.to_be_bytes());
-        data[i + 17] = n as u8;
-        data[(i + 18)..(i + 18 + n)].clone_from_slice(entry_name.as_bytes());
+        data[i..(i + bytes.len())].clone_from_slice(&bytes);
 
         entries.block.write();
         self.update_size();
 
-        Some(DirEntry::new(self.clone(), kind, entry_addr, entry_size, entry_time, &entry_name))
+        Some(entry)
     }
 
     // Deleting an entry is done by setting the entry address to 0
     // TODO: If the entry is a directory, remove its entries recursively
     pub fn delete_entry(&mut self, name: &str) -> Result<(), ()> {
+        let _dev = mount::select(self.dev);
+        self.remove_entry(name)?;
+        watch::post(EventKind::Delete, &watch::join(&self.path(), name));
+        Ok(())
+    }
+
+    // Move an entry to another dir of the same device without copying its
+    // blocks, by appending a new entry and removing the old one. The files
+    // opened at or below the old path are moved to the new one.
+    pub fn move_entry(&mut self, name: &str, dest: &mut Dir, dest_name: &str) -> Result<(), ()> {
+        if dest.dev != self.dev || dest.find(dest_name).is_some() {
+            return Err(());
+        }
+        let entry = self.find(name).ok_or(())?;
+        let _dev = mount::select(self.dev);
+        journal::transaction(|| {
+            dest.append_entry(entry.kind(), dest_name, Some(&entry)).ok_or(())?;
+            self.unlink_entry(name).map(|_| ())
+        })??;
+        let source = watch::join(&self.path(), name);
+        let dest = watch::join(&dest.path(), dest_name);
+        file::relocate(&source, &dest);
+        watch::post_rename(&source, &dest);
+        Ok(())
+    }
+
+    // The entry is removed in a transaction, and its blocks are then freed in
+    // smaller transactions which could leave orphaned blocks after a crash
+    // that will be found by the consistency checker.
+    fn remove_entry(&mut self, name: &str) -> Result<(), ()> {
+        let sb = SuperBlock::read();
+        let is_indexed = sb.has_indexed_files();
+        let (entry, is_last_link) = journal::transaction(|| {
+            let entry = self.unlink_entry(name)?;
+            if !entry.is_dir() && is_indexed && sb.has_links() {
+                let mut root = IndexBlock::read_root(entry.addr());
+                let n = root.links();
+                if n > 1 {
+                    // The blocks are still used by another hard link
+                    root.set_links(n - 1);
+                    root.write();
+                    return Ok((entry, false));
+                }
+            }
+            Ok((entry, true))
+        })??;
+        if !is_last_link {
+            return Ok(());
+        }
+
+        // Freeing entry blocks
+        if !entry.is_dir() && is_indexed {
+            return index_block::free(entry.addr());
+        }
+        let mut addrs = Vec::new();
+        let mut entry_block = LinkedBlock::read(entry.addr());
+        loop {
+            addrs.push(entry_block.addr());
+            match entry_block.next() {
+                Some(next_block) => entry_block = next_block,
+                None => break,
+            }
+        }
+        for batch in addrs.chunks(FREE_BATCH) {
+            journal::transaction(|| {
+                for addr in batch {
+                    BitmapBlock::free(*addr);
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    // Remove an entry from the dir without freeing its blocks
+    fn unlink_entry(&mut self, name: &str) -> Result<DirEntry, ()> {
         let mut entries = self.entries();
         for entry in &mut entries {
             if entry.name() == name {
@@ -167,17 +323,7 @@ impl Dir {
                 data[i + 4] = 0;
                 entries.block.write();
                 self.update_size();
-
-                // Freeing entry blocks
-                let mut entry_block = LinkedBlock::read(entry.addr());
-                loop {
-                    BitmapBlock::free(entry_block.addr());
-                    match entry_block.next() {
-                        Some(next_block) => entry_block = next_block,
-                        None => break,
-                    }
-                }
-                return Ok(());
+                return Ok(entry);
             }
         }
         Err(())
@@ -185,20 +331,87 @@ impl Dir {
 
     pub fn update_entry(&self, name: &str, size: u32) {
         let time = sys::clock::realtime() as u64;
+        self.update_entry_with(name, |entry| {
+            entry.set_size(size);
+            entry.set_mtime(time);
+        }).ok();
+    }
+
+    // Move the size and the modification time of the hard linked files of
+    // the dir and its subdirs to their root blocks, which is done in place
+    // when migrating to version 6.
+    pub fn migrate_links(&self) -> Result<(), ()> {
+        let _dev = mount::select(self.dev);
+        let entries: Vec<DirEntry> = self.entries().collect();
+        for entry in entries {
+            if entry.is_dir() {
+                Dir::from(entry).migrate_links()?;
+            } else if !entry.is_shared() && IndexBlock::read_root(entry.addr()).links() > 1 {
+                journal::transaction(|| {
+                    let mut root = IndexBlock::read_root(entry.addr());
+                    if root.metadata().is_none() {
+                        root.share_metadata(entry.size(), entry.mtime()).ok_or(())?;
+                        root.write();
+                    }
+                    self.update_entry_with(&entry.name(), |e| e.set_shared(true))
+                })??;
+            }
+        }
+        Ok(())
+    }
+
+    // Modify the metadata of an entry like its mode or its owner and notify
+    // the watchers of the entry
+    pub fn change_entry_with<F>(&self, name: &str, f: F) -> Result<(), ()> where F: FnOnce(&mut DirEntry) {
+        self.update_entry_with(name, f)?;
+        watch::post(EventKind::Change, &watch::join(&self.path(), name));
+        Ok(())
+    }
+
+    // Return the paths of the entries of the dir and its subdirs sharing the
+    // blocks at the given address
+    pub fn find_links(&self, addr: u32) -> Vec<String> {
+        let mut paths = Vec::new();
+        for entry in self.entries() {
+            if entry.is_dir() {
+                paths.extend(Dir::from(entry).find_links(addr));
+            } else if entry.addr() == addr {
+                paths.push(watch::join(&self.path(), &entry.name()));
+            }
+        }
+        paths
+    }
+
+    // Modify the metadata of an entry in place, which is possible because
+    // the length of an entry only depends on its name.
+    pub fn update_entry_with<F>(&self, name: &str, f: F) -> Result<(), ()> where F: FnOnce(&mut DirEntry) {
+        let _dev = mount::select(self.dev);
         let mut entries = self.entries();
-        for entry in &mut entries {
+        for mut entry in &mut entries {
             if entry.name() == name {
                 let i = entries.block_offset() - entry.len();
+                f(&mut entry);
+                let bytes = entry.as_bytes();
                 let data = entries.block.data_mut();
-                data[(i + 5)..(i + 9)].clone_from_slice(&size.to_be_bytes());
-                data[(i + 9)..(i + 17)].clone_from_slice(&time.to_be_bytes());
+                data[i..(i + bytes.len())].clone_from_slice(&bytes);
                 entries.block.write();
-                break;
+                return Ok(());
             }
         }
+        Err(())
+    }
+
+    // Return the info of the entry at the cursor and move it to the next one
+    fn entry_at(&self, cursor: &mut Cursor) -> Option<FileInfo> {
+        let _dev = mount::select(self.dev);
+        let mut entries = ReadDir::resume(self.clone(), *cursor);
+        let entry = entries.next()?;
+        *cursor = entries.cursor();
+        Some(entry.info())
     }
 
     pub fn entries(&self) -> ReadDir {
+        let _dev = mount::select(self.dev);
         ReadDir::from(self.clone())
     }
 
@@ -229,29 +442,28 @@ impl Dir {
 
 impl FileIO for Dir {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
-        let mut i = 0;
-        for entry in self.entries().skip(self.entry_index as usize) {
-            let info = entry.info();
-            let bytes = info.as_bytes();
-            let j = i + bytes.len();
-            if j < buf.len() {
-                buf[i..j].copy_from_slice(&bytes);
-                self.entry_index += 1;
-                i = j;
-            } else {
-                break;
-            }
-        }
-        Ok(i)
+        let mut cursor = self.cursor.unwrap_or_else(|| Cursor::start(self));
+        let res = vfs::read_entries(buf, &mut cursor, |cursor| Ok(self.entry_at(cursor)));
+        self.cursor = Some(cursor);
+        res
     }
+
     fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
         Err(())
     }
 }
 
-// Truncate to the given number of bytes at most while respecting char boundaries
-fn truncate(s: &str, max: usize) -> String {
-    s.char_indices().take_while(|(i, _)| *i <= max).map(|(_, c)| c).collect()
+impl Node for Dir {
+    fn next_entry(&mut self) -> Result<Option<FileInfo>, ()> {
+        let mut cursor = self.cursor.unwrap_or_else(|| Cursor::start(self));
+        let info = self.entry_at(&mut cursor);
+        self.cursor = Some(cursor);
+        Ok(info)
+    }
+
+    fn box_clone(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
 }
 
 #[test_case]
@@ -279,3 +491,45 @@ fn test_dir_delete() {
     assert!(Dir::open("/test").is_none());
     super::dismount();
 }
+
+#[test_case]
+fn test_dir_move_entry() {
+    super::mount_mem();
+    super::format_mem();
+    assert!(Dir::create("/a").is_some());
+    assert!(Dir::create("/b").is_some());
+    let mut a = Dir::open("/a").unwrap();
+    let mut b = Dir::open("/b").unwrap();
+    assert!(a.create_file("test").is_some());
+    assert!(a.move_entry("test", &mut b, "moved").is_ok());
+    assert!(a.find("test").is_none());
+    assert!(b.find("moved").is_some());
+    assert!(b.move_entry("none", &mut a, "test").is_err());
+    super::dismount();
+}
+
+#[test_case]
+fn test_dir_read() {
+    super::mount_mem();
+    super::format_mem();
+    let mut dir = Dir::open("/").unwrap();
+    for name in ["a", "b", "c"].iter() {
+        assert!(dir.create_file(name).is_some());
+    }
+    assert!(dir.delete_entry("b").is_ok());
+
+    let mut dir = Dir::open("/").unwrap();
+    assert_eq!(dir.next_entry().unwrap().unwrap().name(), "a");
+    assert_eq!(dir.next_entry().unwrap().unwrap().name(), "c");
+    assert!(dir.next_entry().unwrap().is_none());
+
+    // An entry that does not fit in the buffer is read by the next call
+    let mut dir = Dir::open("/").unwrap();
+    let n = dir.find("a").unwrap().info().as_bytes().len();
+    let mut buf = [0; 256];
+    assert_eq!(dir.read(&mut buf[0..(n - 1)]), Err(()));
+    assert_eq!(dir.read(&mut buf[0..n]), Ok(n));
+    assert_eq!(dir.read(&mut buf[0..n]), Ok(n));
+    assert_eq!(dir.read(&mut buf[0..n]), Ok(0));
+    super::dismount();
+}
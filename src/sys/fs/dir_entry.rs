@@ -1,22 +1,98 @@
 use super::{dirname, filename, realpath, FileType};
 use super::dir::Dir;
+use super::file::File;
+use super::index_block::IndexBlock;
+use super::super_block::SuperBlock;
+use crate::api::path;
 use alloc::string::String;
 use alloc::vec::Vec;
 
+// Maximum number of symbolic links followed while opening a path
+const MAX_LINKS: usize = 8;
+
+// Dir entry structure (short format):
+// 0 => kind
+// 1..5 => addr
+// 5..9 => size
+// 9..17 => time
+// 17 => name length
+// 18..n => name
+//
+// Dir entry structure (long format since version 4 of MFS):
+// 0 => kind
+// 1..5 => addr
+// 5..9 => size
+// 9..11 => uid
+// 11..13 => mode
+// 13..21 => ctime
+// 21..29 => mtime
+// 29..37 => atime
+// 37 => name length
+// 38..n => name
+//
+// The highest bit of the mode is set since version 6 on the entries of a hard
+// linked file, whose size and modification time are stored in its root block.
+const MODE_SHARED: u16 = 0x8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryFormat {
+    Short,
+    Long,
+}
+
+impl EntryFormat {
+    // NOTE: FS must be mounted
+    pub fn current() -> Self {
+        if SuperBlock::read().has_long_entries() {
+            EntryFormat::Long
+        } else {
+            EntryFormat::Short
+        }
+    }
+
+    pub fn empty_len(&self) -> usize {
+        match self {
+            EntryFormat::Short => 1 + 4 + 4 + 8 + 1,
+            EntryFormat::Long => 1 + 4 + 4 + 2 + 2 + 8 + 8 + 8 + 1,
+        }
+    }
+}
+
+pub fn default_mode(kind: FileType) -> u16 {
+    match kind {
+        FileType::Dir => 0o755,
+        FileType::File => 0o644,
+        FileType::Device => 0o666,
+        FileType::Link => 0o777,
+    }
+}
+
 #[derive(Clone)]
 pub struct DirEntry {
     dir: Dir,
     addr: u32,
+    format: EntryFormat,
+    is_shared: bool,
 
     // FileInfo
     kind: FileType,
     size: u32,
-    time: u64,
+    uid: u16,
+    mode: u16,
+    ctime: u64,
+    mtime: u64,
+    atime: u64,
     name: String,
 }
 
 impl DirEntry {
     pub fn open(pathname: &str) -> Option<Self> {
+        let mut links = 0;
+        Self::open_with_links(pathname, &mut links)
+    }
+
+    // Open the entry of a path without following it if it is a link
+    pub fn open_link(pathname: &str) -> Option<Self> {
         let pathname = realpath(pathname);
         let dirname = dirname(&pathname);
         let filename = filename(&pathname);
@@ -26,27 +102,147 @@ impl DirEntry {
         None
     }
 
+    pub fn open_with_links(pathname: &str, links: &mut usize) -> Option<Self> {
+        let pathname = realpath(pathname);
+        let dirname = dirname(&pathname);
+        let filename = filename(&pathname);
+        let entry = Dir::open_with_links(dirname, links)?.find(filename)?;
+        if entry.is_link() {
+            Self::open_with_links(&entry.target(dirname, links)?, links)
+        } else {
+            Some(entry)
+        }
+    }
+
+    // Return the path targeted by a symbolic link located in the given dir,
+    // or None if too many links have been followed to avoid loops.
+    pub fn target(&self, dirname: &str, links: &mut usize) -> Option<String> {
+        *links += 1;
+        if *links > MAX_LINKS {
+            return None;
+        }
+        let target = File::from(self.clone()).read_to_string();
+        if target.is_empty() {
+            None
+        } else {
+            Some(path::join(dirname, &target))
+        }
+    }
+
+    // Create an entry with the default metadata of the short format
     pub fn new(dir: Dir, kind: FileType, addr: u32, size: u32, time: u64, name: &str) -> Self {
         let name = String::from(name);
-        Self { dir, kind, addr, size, time, name }
+        let format = EntryFormat::Short;
+        let uid = 0;
+        let mode = default_mode(kind);
+        let (ctime, mtime, atime) = (time, time, time);
+        let is_shared = false;
+        Self { dir, addr, format, is_shared, kind, size, uid, mode, ctime, mtime, atime, name }
     }
 
-    pub fn empty_len() -> usize {
-        1 + 4 + 4 + 8 + 1
+    // Parse an entry from the given buffer, or return None if it is not
+    // valid or if it is too short.
+    pub fn parse(dir: Dir, format: EntryFormat, buf: &[u8]) -> Option<Self> {
+        let n = format.empty_len();
+        if buf.len() < n {
+            return None;
+        }
+        let kind = match buf[0] {
+            0 => FileType::Dir,
+            1 => FileType::File,
+            2 => FileType::Device,
+            3 => FileType::Link,
+            _ => return None,
+        };
+        let addr = read_u32(buf, 1);
+        let size = read_u32(buf, 5);
+        let name_len = buf[n - 1] as usize;
+        if name_len == 0 || buf.len() < n + name_len {
+            return None;
+        }
+        let name = String::from_utf8_lossy(&buf[n..(n + name_len)]);
+        let mut entry = Self::new(dir, kind, addr, size, 0, &name);
+        entry.format = format;
+        match format {
+            EntryFormat::Short => {
+                let time = read_u64(buf, 9);
+                entry.ctime = time;
+                entry.mtime = time;
+                entry.atime = time;
+            }
+            EntryFormat::Long => {
+                let mode = read_u16(buf, 11);
+                entry.uid = read_u16(buf, 9);
+                entry.mode = mode & !MODE_SHARED;
+                entry.is_shared = mode & MODE_SHARED != 0;
+                entry.ctime = read_u64(buf, 13);
+                entry.mtime = read_u64(buf, 21);
+                entry.atime = read_u64(buf, 29);
+            }
+        }
+        if entry.is_shared && entry.addr != 0 {
+            if let Some((size, mtime)) = IndexBlock::read_root(entry.addr).metadata() {
+                entry.size = size;
+                entry.mtime = mtime;
+            }
+        }
+        Some(entry)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        debug_assert!(self.name.len() < 256);
+        let mut res = Vec::new();
+        res.push(self.kind as u8);
+        res.extend_from_slice(&self.addr.to_be_bytes());
+        res.extend_from_slice(&self.size.to_be_bytes());
+        match self.format {
+            EntryFormat::Short => {
+                res.extend_from_slice(&self.mtime.to_be_bytes());
+            }
+            EntryFormat::Long => {
+                let flag = if self.is_shared { MODE_SHARED } else { 0 };
+                res.extend_from_slice(&self.uid.to_be_bytes());
+                res.extend_from_slice(&(self.mode | flag).to_be_bytes());
+                res.extend_from_slice(&self.ctime.to_be_bytes());
+                res.extend_from_slice(&self.mtime.to_be_bytes());
+                res.extend_from_slice(&self.atime.to_be_bytes());
+            }
+        }
+        res.push(self.name.len() as u8);
+        res.extend_from_slice(self.name.as_bytes());
+        res
     }
 
     pub fn len(&self) -> usize {
-        Self::empty_len() + self.name.len()
+        self.format.empty_len() + self.name.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        Self::empty_len() == self.len()
+        self.name.is_empty()
+    }
+
+    pub fn format(&self) -> EntryFormat {
+        self.format
+    }
+
+    pub fn set_format(&mut self, format: EntryFormat) {
+        self.format = format;
     }
 
     pub fn kind(&self) -> FileType {
         self.kind
     }
 
+    // The size and the modification time are shared with the other hard
+    // links of the file
+    pub fn is_shared(&self) -> bool {
+        self.is_shared
+    }
+
+    pub fn set_shared(&mut self, is_shared: bool) {
+        self.is_shared = is_shared;
+    }
+
     pub fn is_dir(&self) -> bool {
         self.kind == FileType::Dir
     }
@@ -59,10 +255,18 @@ impl DirEntry {
         self.kind == FileType::Device
     }
 
+    pub fn is_link(&self) -> bool {
+        self.kind == FileType::Link
+    }
+
     pub fn addr(&self) -> u32 {
         self.addr
     }
 
+    pub fn set_addr(&mut self, addr: u32) {
+        self.addr = addr;
+    }
+
     pub fn dir(&self) -> Dir {
         self.dir.clone()
     }
@@ -75,42 +279,140 @@ impl DirEntry {
         self.size
     }
 
+    pub fn set_size(&mut self, size: u32) {
+        self.size = size;
+    }
+
     pub fn time(&self) -> u64 {
-        self.time
+        self.mtime
+    }
+
+    pub fn uid(&self) -> u16 {
+        self.uid
+    }
+
+    pub fn set_uid(&mut self, uid: u16) {
+        self.uid = uid;
+    }
+
+    pub fn mode(&self) -> u16 {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: u16) {
+        self.mode = mode & 0o7777;
+    }
+
+    pub fn ctime(&self) -> u64 {
+        self.ctime
+    }
+
+    pub fn set_ctime(&mut self, time: u64) {
+        self.ctime = time;
+    }
+
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    pub fn set_mtime(&mut self, time: u64) {
+        self.mtime = time;
+    }
+
+    pub fn atime(&self) -> u64 {
+        self.atime
+    }
+
+    pub fn set_atime(&mut self, time: u64) {
+        self.atime = time;
     }
 
     pub fn info(&self) -> FileInfo {
-        FileInfo { kind: self.kind, name: self.name(), size: self.size(), time: self.time }
+        FileInfo {
+            kind: self.kind,
+            name: self.name(),
+            size: self.size(),
+            uid: self.uid,
+            mode: self.mode,
+            ctime: self.ctime,
+            mtime: self.mtime,
+            atime: self.atime,
+        }
     }
 }
 
+fn read_u16(buf: &[u8], i: usize) -> u16 {
+    u16::from_be_bytes(buf[i..(i + 2)].try_into().unwrap())
+}
+
+fn read_u32(buf: &[u8], i: usize) -> u32 {
+    u32::from_be_bytes(buf[i..(i + 4)].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], i: usize) -> u64 {
+    u64::from_be_bytes(buf[i..(i + 8)].try_into().unwrap())
+}
+
 #[derive(Debug)]
 pub struct FileInfo {
     kind: FileType,
     size: u32,
-    time: u64,
+    uid: u16,
+    mode: u16,
+    ctime: u64,
+    mtime: u64,
+    atime: u64,
     name: String,
 }
 
 impl FileInfo {
     pub fn new() -> Self {
-        Self { kind: FileType::File, name: String::new(), size: 0, time: 0 }
+        let kind = FileType::File;
+        let mode = default_mode(kind);
+        Self { kind, name: String::new(), size: 0, uid: 0, mode, ctime: 0, mtime: 0, atime: 0 }
+    }
+
+    // Create the info of a file found on another filesystem
+    pub fn with(kind: FileType, name: &str, size: u32, mode: u16, time: u64) -> Self {
+        let name = String::from(name);
+        Self { kind, name, size, uid: 0, mode, ctime: time, mtime: time, atime: time }
     }
 
     pub fn root() -> Self {
         let kind = FileType::Dir;
         let name = String::new();
         let size = Dir::root().size() as u32;
-        let time = 0;
-        Self { kind, name, size, time }
+        let mode = default_mode(kind);
+        Self { kind, name, size, uid: 0, mode, ctime: 0, mtime: 0, atime: 0 }
     }
 
     pub fn size(&self) -> u32 {
         self.size
     }
 
+    // Time of the last modification
     pub fn time(&self) -> u64 {
-        self.time
+        self.mtime
+    }
+
+    pub fn uid(&self) -> u16 {
+        self.uid
+    }
+
+    pub fn mode(&self) -> u16 {
+        self.mode
+    }
+
+    pub fn ctime(&self) -> u64 {
+        self.ctime
+    }
+
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    pub fn atime(&self) -> u64 {
+        self.atime
     }
 
     pub fn name(&self) -> String {
@@ -130,33 +432,101 @@ impl FileInfo {
         self.kind == FileType::Device
     }
 
-    // TODO: Use bincode?
+    pub fn is_link(&self) -> bool {
+        self.kind == FileType::Link
+    }
+
+    // Serialize the info into a record starting with the version of the
+    // format and the length of the record, followed by the fields of the
+    // info with the name at the end.
     pub fn as_bytes(&self) -> Vec<u8> {
         debug_assert!(self.name.len() < 256);
+        let len = (INFO_HEADER_LEN + self.name.len()) as u16;
         let mut res = Vec::new();
+        res.push(INFO_VERSION);
+        res.extend_from_slice(&len.to_be_bytes());
         res.push(self.kind as u8);
         res.extend_from_slice(&self.size.to_be_bytes());
-        res.extend_from_slice(&self.time.to_be_bytes());
-        res.push(self.name.len() as u8);
+        res.extend_from_slice(&self.uid.to_be_bytes());
+        res.extend_from_slice(&self.mode.to_be_bytes());
+        res.extend_from_slice(&self.ctime.to_be_bytes());
+        res.extend_from_slice(&self.mtime.to_be_bytes());
+        res.extend_from_slice(&self.atime.to_be_bytes());
         res.extend_from_slice(self.name.as_bytes());
         res
     }
-}
 
-use core::convert::TryInto;
-use core::convert::From;
-impl From<&[u8]> for FileInfo {
-    fn from(buf: &[u8]) -> Self {
-        let kind = match buf[0] { // TODO: Add FileType::from(u8)
+    // Parse a record serialized with `as_bytes`, or return None if its
+    // version is unknown or if it is truncated.
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < INFO_HEADER_LEN || buf[0] != INFO_VERSION {
+            return None;
+        }
+        let len = u16::from_be_bytes(buf[1..3].try_into().unwrap()) as usize;
+        if len < INFO_HEADER_LEN || len > buf.len() {
+            return None;
+        }
+        let kind = match buf[3] {
             0 => FileType::Dir,
             1 => FileType::File,
             2 => FileType::Device,
-            _ => panic!(),
+            3 => FileType::Link,
+            _ => return None,
         };
-        let size = u32::from_be_bytes(buf[1..5].try_into().unwrap());
-        let time = u64::from_be_bytes(buf[5..13].try_into().unwrap());
-        let i = 14 + buf[13] as usize;
-        let name = String::from_utf8_lossy(&buf[14..i]).into();
-        Self { kind, name, size, time }
+        let size = read_u32(buf, 4);
+        let uid = read_u16(buf, 8);
+        let mode = read_u16(buf, 10);
+        let ctime = read_u64(buf, 12);
+        let mtime = read_u64(buf, 20);
+        let atime = read_u64(buf, 28);
+        let name = String::from_utf8_lossy(&buf[INFO_HEADER_LEN..len]).into();
+        Some(Self { kind, name, size, uid, mode, ctime, mtime, atime })
     }
 }
+
+// Serialized file info record:
+// 0 => version
+// 1..3 => length of the record
+// 3 => kind
+// 4..8 => size
+// 8..10 => uid
+// 10..12 => mode
+// 12..20 => ctime
+// 20..28 => mtime
+// 28..36 => atime
+// 36.. => name
+pub const INFO_VERSION: u8 = 1;
+const INFO_HEADER_LEN: usize = 36;
+
+use core::convert::TryInto;
+
+#[test_case]
+fn test_dir_entry_format() {
+    super::mount_mem();
+    super::format_mem();
+    let dir = Dir::root();
+    let mut entry = DirEntry::new(dir.clone(), FileType::File, 42, 1024, 1000, "test");
+    entry.set_uid(1);
+    entry.set_mode(0o600);
+    entry.set_atime(2000);
+
+    // The metadata missing from the short format are replaced by defaults
+    let short = DirEntry::parse(dir.clone(), EntryFormat::Short, &entry.as_bytes()).unwrap();
+    assert_eq!(short.len(), 18 + 4);
+    assert_eq!((short.uid(), short.mode(), short.atime()), (0, 0o644, 1000));
+
+    entry.set_format(EntryFormat::Long);
+    let long = DirEntry::parse(dir, EntryFormat::Long, &entry.as_bytes()).unwrap();
+    assert_eq!(long.len(), 38 + 4);
+    assert_eq!((long.addr(), long.size(), long.name()), (42, 1024, "test".into()));
+    assert_eq!((long.uid(), long.mode()), (1, 0o600));
+    assert_eq!((long.ctime(), long.mtime(), long.atime()), (1000, 1000, 2000));
+
+    // The info of an entry is serialized into a versioned record
+    let buf = long.info().as_bytes();
+    assert_eq!(buf.len(), INFO_HEADER_LEN + 4);
+    let info = FileInfo::parse(&buf).unwrap();
+    assert_eq!((info.name(), info.size(), info.mode()), ("test".into(), 1024, 0o600));
+    assert!(FileInfo::parse(&buf[0..(buf.len() - 1)]).is_none());
+    super::dismount();
+}
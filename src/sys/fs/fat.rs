@@ -0,0 +1,456 @@
+use super::{FileInfo, FileIO, FileType, BLOCK_SIZE};
+use super::block_device::{BlockDevice, BlockDeviceIO};
+use super::mount;
+use super::vfs::{self, FileSystem, Node};
+use crate::sys;
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+// FAT32 filesystem (read-only)
+//
+// The boot sector at the beginning of the disk describes the geometry of the
+// filesystem: the reserved sectors are followed by the copies of the file
+// allocation table and then by the data area divided into clusters. A file
+// or a dir is a chain of clusters where the entry of each cluster in the
+// table is the address of the next one.
+//
+// Dir entry structure:
+// 0..8 => name
+// 8..11 => extension
+// 11 => attributes
+// 12 => case of the name and extension
+// 20..22 => high half of the first cluster
+// 22..24 => modification time
+// 24..26 => modification date
+// 26..28 => low half of the first cluster
+// 28..32 => size
+//
+// A long name is stored in reverse order in the entries preceding the entry
+// of its file, in parts of 13 UTF-16 characters.
+
+const ENTRY_SIZE: usize = 32;
+const END_OF_CHAIN: u32 = 0x0FFF_FFF8;
+
+const ATTR_VOLUME: u8 = 0x08;
+const ATTR_DIR: u8 = 0x10;
+const ATTR_LONG_NAME: u8 = 0x0F;
+
+const LOWERCASE_NAME: u8 = 0x08;
+const LOWERCASE_EXT: u8 = 0x10;
+
+#[derive(Debug, Clone)]
+pub struct Fat32 {
+    dev: Arc<BlockDevice>,
+    sectors_per_cluster: u32,
+    fat_addr: u32,
+    data_addr: u32,
+    root_cluster: u32,
+    cluster_count: u32,
+}
+
+impl Fat32 {
+    // Read the boot sector of a device, which is shared with the mount table
+    // once the filesystem is mounted.
+    pub fn open(dev: Arc<BlockDevice>) -> Option<Self> {
+        let mut buf = [0; BLOCK_SIZE];
+        dev.read(0, &mut buf).ok()?;
+        if buf[510..512] != [0x55, 0xAA] || &buf[82..90] != b"FAT32   " {
+            return None;
+        }
+        if read_u16(&buf, 11) as usize != BLOCK_SIZE {
+            return None; // Unsupported sector size
+        }
+        let sectors_per_cluster = buf[13] as u32;
+        let reserved_sectors = read_u16(&buf, 14) as u32;
+        let fat_count = buf[16] as u32;
+        let sector_count = read_u32(&buf, 32);
+        let fat_size = read_u32(&buf, 36);
+        let root_cluster = read_u32(&buf, 44);
+        if sectors_per_cluster == 0 || fat_count == 0 {
+            return None;
+        }
+        let fat_addr = reserved_sectors;
+        let data_addr = fat_addr + fat_count * fat_size;
+        let cluster_count = sector_count.saturating_sub(data_addr) / sectors_per_cluster;
+        Some(Self { dev, sectors_per_cluster, fat_addr, data_addr, root_cluster, cluster_count })
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster as usize * BLOCK_SIZE
+    }
+
+    fn read_cluster(&self, cluster: u32, buf: &mut [u8]) -> Result<(), ()> {
+        if cluster < 2 || cluster - 2 >= self.cluster_count {
+            return Err(());
+        }
+        let addr = self.data_addr + (cluster - 2) * self.sectors_per_cluster;
+        for (i, sector) in buf.chunks_mut(BLOCK_SIZE).enumerate() {
+            self.dev.read(addr + i as u32, sector)?;
+        }
+        Ok(())
+    }
+
+    // Return the cluster following the given one in its chain
+    fn next_cluster(&self, cluster: u32) -> Option<u32> {
+        let offset = cluster as usize * 4;
+        let addr = self.fat_addr + (offset / BLOCK_SIZE) as u32;
+        let mut buf = [0; BLOCK_SIZE];
+        self.dev.read(addr, &mut buf).ok()?;
+        let next = read_u32(&buf, offset % BLOCK_SIZE) & 0x0FFF_FFFF;
+        if next < 2 || next >= END_OF_CHAIN {
+            None
+        } else {
+            Some(next)
+        }
+    }
+
+    // Return the chain of clusters starting with the given one, stopping
+    // after the number of clusters in the data area to avoid loops.
+    fn clusters(&self, first: u32) -> Vec<u32> {
+        let mut res = Vec::new();
+        let mut cluster = Some(first);
+        while let Some(c) = cluster {
+            if res.len() as u32 >= self.cluster_count {
+                break;
+            }
+            res.push(c);
+            cluster = self.next_cluster(c);
+        }
+        res
+    }
+
+    fn entries(&self, dir: &FatEntry) -> Vec<FatEntry> {
+        let mut res = Vec::new();
+        let mut long_name = Vec::new();
+        let mut buf = vec![0; self.cluster_size()];
+        for cluster in self.clusters(dir.cluster) {
+            if self.read_cluster(cluster, &mut buf).is_err() {
+                break;
+            }
+            for raw in buf.chunks(ENTRY_SIZE) {
+                match raw[0] {
+                    0x00 => return res, // End of dir
+                    0xE5 => { // Deleted entry
+                        long_name.clear();
+                        continue;
+                    }
+                    _ => {}
+                }
+                let attr = raw[11];
+                if attr == ATTR_LONG_NAME {
+                    if raw[0] & 0x40 != 0 { // Last part
+                        long_name.clear();
+                    }
+                    let mut part = long_name_part(raw);
+                    part.extend_from_slice(&long_name);
+                    long_name = part;
+                    continue;
+                }
+                if attr & ATTR_VOLUME == 0 {
+                    let name = if long_name.is_empty() {
+                        short_name(raw)
+                    } else {
+                        String::from_utf16_lossy(&long_name)
+                    };
+                    if name != "." && name != ".." {
+                        res.push(FatEntry::parse(raw, name));
+                    }
+                }
+                long_name.clear();
+            }
+        }
+        res
+    }
+
+    fn root(&self) -> FatEntry {
+        let name = String::new();
+        FatEntry { name, is_dir: true, cluster: self.root_cluster, size: 0, time: 0 }
+    }
+
+    // Find the entry of an absolute path, where the names are compared
+    // without case like on other systems using FAT.
+    fn find(&self, path: &str) -> Option<FatEntry> {
+        let (_, path) = mount::resolve(path)?;
+        let mut entry = self.root();
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            if !entry.is_dir {
+                return None;
+            }
+            entry = self.entries(&entry).into_iter().find(|e| e.name.eq_ignore_ascii_case(name))?;
+        }
+        Some(entry)
+    }
+}
+
+impl FileSystem for Fat32 {
+    fn name(&self) -> &'static str {
+        "fat32"
+    }
+
+    fn lookup(&self, path: &str, kind: FileType) -> Option<Box<dyn Node>> {
+        let entry = self.find(path)?;
+        match kind {
+            FileType::Dir if entry.is_dir => {
+                Some(Box::new(FatDir { fs: self.clone(), entry, entries: None, entry_index: 0 }))
+            }
+            FileType::File if !entry.is_dir => {
+                let cursor = (0, entry.cluster);
+                Some(Box::new(FatFile { fs: self.clone(), entry, offset: 0, cursor }))
+            }
+            _ => None,
+        }
+    }
+
+    fn create(&self, _path: &str, _kind: FileType) -> Option<Box<dyn Node>> {
+        None
+    }
+
+    fn unlink(&self, _path: &str) -> Result<(), ()> {
+        Err(())
+    }
+
+    fn read_dir(&self, path: &str) -> Option<Vec<FileInfo>> {
+        let dir = self.find(path)?;
+        if dir.is_dir {
+            Some(self.entries(&dir).iter().map(|e| e.info()).collect())
+        } else {
+            None
+        }
+    }
+
+    fn stat(&self, path: &str) -> Option<FileInfo> {
+        self.find(path).map(|e| e.info())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FatEntry {
+    name: String,
+    is_dir: bool,
+    cluster: u32,
+    size: u32,
+    time: u64,
+}
+
+impl FatEntry {
+    fn parse(raw: &[u8], name: String) -> Self {
+        let is_dir = raw[11] & ATTR_DIR != 0;
+        let cluster = (read_u16(raw, 20) as u32) << 16 | read_u16(raw, 26) as u32;
+        let size = if is_dir { 0 } else { read_u32(raw, 28) };
+        let time = timestamp(read_u16(raw, 24), read_u16(raw, 22));
+        Self { name, is_dir, cluster, size, time }
+    }
+
+    fn info(&self) -> FileInfo {
+        let kind = if self.is_dir { FileType::Dir } else { FileType::File };
+        let mode = if self.is_dir { 0o555 } else { 0o444 };
+        FileInfo::with(kind, &self.name, self.size, mode, self.time)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FatFile {
+    fs: Fat32,
+    entry: FatEntry,
+    offset: usize,
+    cursor: (usize, u32), // Index and address of the last cluster read
+}
+
+impl FatFile {
+    // Read a file from the given offset, starting the walk of its chain of
+    // clusters from the cursor of the last read when reading forward, and
+    // update the cursor.
+    fn read_from(&mut self, offset: usize, buf: &mut [u8]) -> Result<usize, ()> {
+        let size = self.entry.size as usize;
+        if offset >= size {
+            return Ok(0);
+        }
+        let n = core::cmp::min(buf.len(), size - offset);
+        let cluster_size = self.fs.cluster_size();
+        let first = offset / cluster_size;
+        let (mut index, mut cluster) = if self.cursor.0 <= first {
+            self.cursor
+        } else {
+            (0, self.entry.cluster)
+        };
+        while index < first {
+            match self.fs.next_cluster(cluster) {
+                Some(next) => cluster = next,
+                None => return Ok(0),
+            }
+            index += 1;
+        }
+        let mut data = vec![0; cluster_size];
+        let mut i = 0;
+        loop {
+            self.fs.read_cluster(cluster, &mut data)?;
+            self.cursor = (index, cluster);
+            let j = (offset + i) % cluster_size;
+            let len = core::cmp::min(n - i, cluster_size - j);
+            buf[i..(i + len)].copy_from_slice(&data[j..(j + len)]);
+            i += len;
+            if i == n {
+                break;
+            }
+            match self.fs.next_cluster(cluster) {
+                Some(next) => cluster = next,
+                None => break,
+            }
+            index += 1;
+        }
+        Ok(i)
+    }
+}
+
+impl FileIO for FatFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let bytes = self.read_from(self.offset, buf)?;
+        self.offset += bytes;
+        Ok(bytes)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+}
+
+impl Node for FatFile {
+    fn read_at(&mut self, offset: usize, buf: &mut [u8]) -> Result<usize, ()> {
+        self.read_from(offset, buf)
+    }
+
+    fn box_clone(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FatDir {
+    fs: Fat32,
+    entry: FatEntry,
+    entries: Option<Vec<FatEntry>>, // Read when the first entry is needed
+    entry_index: usize,
+}
+
+impl FatDir {
+    fn entries(&mut self) -> &[FatEntry] {
+        if self.entries.is_none() {
+            self.entries = Some(self.fs.entries(&self.entry));
+        }
+        self.entries.as_deref().unwrap()
+    }
+}
+
+impl FileIO for FatDir {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let mut i = self.entry_index;
+        let entries = self.entries();
+        let res = vfs::read_entries(buf, &mut i, |i| {
+            let info = entries.get(*i).map(|entry| entry.info());
+            *i += 1;
+            Ok(info)
+        });
+        self.entry_index = i;
+        res
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+}
+
+impl Node for FatDir {
+    fn next_entry(&mut self) -> Result<Option<FileInfo>, ()> {
+        let i = self.entry_index;
+        let info = self.entries().get(i).map(|entry| entry.info());
+        if info.is_some() {
+            self.entry_index += 1;
+        }
+        Ok(info)
+    }
+
+    fn box_clone(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+}
+
+fn short_name(raw: &[u8]) -> String {
+    let mut name = String::from_utf8_lossy(&raw[0..8]).trim_end().to_string();
+    let mut ext = String::from_utf8_lossy(&raw[8..11]).trim_end().to_string();
+    if raw[0] == 0x05 { // Escaped 0xE5 character
+        name.replace_range(0..1, "\u{E5}");
+    }
+    if raw[12] & LOWERCASE_NAME != 0 {
+        name = name.to_lowercase();
+    }
+    if raw[12] & LOWERCASE_EXT != 0 {
+        ext = ext.to_lowercase();
+    }
+    if !ext.is_empty() {
+        name.push('.');
+        name.push_str(&ext);
+    }
+    name
+}
+
+fn long_name_part(raw: &[u8]) -> Vec<u16> {
+    let mut res = Vec::new();
+    for i in (1..11).step_by(2).chain((14..26).step_by(2)).chain((28..32).step_by(2)) {
+        match read_u16(raw, i) {
+            0x0000 | 0xFFFF => break,
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+// Convert the date and time of an entry into a Unix timestamp
+fn timestamp(date: u16, time: u16) -> u64 {
+    let year = 1980 + (date >> 9) as u64;
+    let month = ((date >> 5) & 0xF) as u64;
+    let day = (date & 0x1F) as u64;
+    if month < 1 || month > 12 || day < 1 {
+        return 0;
+    }
+    let hour = (time >> 11) as u64;
+    let minute = ((time >> 5) & 0x3F) as u64;
+    let second = 2 * (time & 0x1F) as u64;
+    sys::clock::timestamp(year, month, day, hour, minute, second)
+}
+
+fn read_u16(buf: &[u8], i: usize) -> u16 {
+    u16::from_le_bytes(buf[i..(i + 2)].try_into().unwrap())
+}
+
+fn read_u32(buf: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes(buf[i..(i + 4)].try_into().unwrap())
+}
+
+#[test_case]
+fn test_fat_entry() {
+    let mut raw = [0; ENTRY_SIZE];
+    raw[0..11].copy_from_slice(b"HELLO   TXT");
+    raw[12] = LOWERCASE_NAME | LOWERCASE_EXT;
+    raw[22..24].copy_from_slice(&(12u16 << 11 | 30 << 5 | 5).to_le_bytes()); // 12:30:10
+    raw[24..26].copy_from_slice(&(42u16 << 9 | 6 << 5 | 15).to_le_bytes()); // 2022-06-15
+    raw[26..28].copy_from_slice(&3u16.to_le_bytes());
+    raw[28..32].copy_from_slice(&1024u32.to_le_bytes());
+    let entry = FatEntry::parse(&raw, short_name(&raw));
+    assert_eq!(entry.name, "hello.txt");
+    assert_eq!(entry.cluster, 3);
+    assert_eq!(entry.size, 1024);
+    assert_eq!(entry.time, 1655296210);
+    assert!(!entry.is_dir);
+
+    let mut raw = [0xFF; ENTRY_SIZE];
+    for (i, c) in [1, 3, 5, 7, 9, 14, 16].iter().zip("hello-w".bytes()) {
+        raw[*i..(*i + 2)].copy_from_slice(&(c as u16).to_le_bytes());
+    }
+    raw[18..20].copy_from_slice(&[0, 0]);
+    assert_eq!(String::from_utf16_lossy(&long_name_part(&raw)), "hello-w");
+}
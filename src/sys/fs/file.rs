@@ -1,12 +1,26 @@
 use super::{dirname, filename, realpath, FileIO};
 use super::dir::Dir;
-use super::block::LinkedBlock;
-use super::dir_entry::DirEntry;
+use super::block::{self, Block, LinkedBlock};
+use super::dir_entry::{DirEntry, EntryFormat};
+use super::index_block::{self, IndexBlock};
+use super::journal;
+use super::mount;
+use super::super_block::SuperBlock;
+use super::vfs::Node;
+use super::watch::{self, EventKind};
+use crate::sys;
 
 use alloc::boxed::Box;
+use alloc::format;
 use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
 use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp;
 use core::convert::From;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::lazy_static;
+use spin::Mutex;
 
 pub enum SeekFrom {
     Start(u32),
@@ -14,23 +28,86 @@ pub enum SeekFrom {
     End(i32),
 }
 
-#[derive(Debug, Clone)]
-pub struct File {
+// The parent dir and the name of an open file are shared by its handles, and
+// are updated when the file or one of the dirs above it is renamed. The paths
+// of the hard links of the file are kept with the number of links and the
+// number of renames when they were found.
+#[derive(Debug)]
+struct Location {
     parent: Option<Box<Dir>>,
     name: String,
+    links: Option<(u16, usize, Vec<String>)>,
+}
+
+impl Location {
+    fn path(&self) -> String {
+        match &self.parent {
+            Some(dir) => watch::join(&dir.path(), &self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+lazy_static! {
+    static ref LOCATIONS: Mutex<Vec<Weak<Mutex<Location>>>> = Mutex::new(Vec::new());
+}
+
+static RENAMES: AtomicUsize = AtomicUsize::new(0);
+
+// Move the open files at or below the source path to the destination path
+pub fn relocate(source: &str, dest: &str) {
+    RENAMES.fetch_add(1, Ordering::SeqCst);
+    let mut locations = LOCATIONS.lock();
+    locations.retain(|location| location.strong_count() > 0); // Closed files
+    let moved: Vec<_> = locations.iter().filter_map(Weak::upgrade).filter(|location| {
+        let path = location.lock().path();
+        path == source || path.starts_with(&format!("{}/", source))
+    }).collect();
+    drop(locations); // The dirs are opened without the lock
+
+    for location in moved {
+        let path = format!("{}{}", dest, &location.lock().path()[source.len()..]);
+        if let Some(dir) = Dir::open(dirname(&path)) {
+            let mut location = location.lock();
+            location.parent = Some(Box::new(dir));
+            location.name = filename(&path).into();
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct File {
+    location: Arc<Mutex<Location>>,
     addr: u32,
     size: u32,
     offset: u32,
+    is_accessed: bool,
+    dev: usize,
 }
 
 impl From<DirEntry> for File {
     fn from(entry: DirEntry) -> Self {
-        Self {
+        // The access time will only be updated when it is older than the
+        // modification time to avoid writing the dir on every read, and not
+        // at all for links which are read while resolving paths.
+        let is_accessed = entry.format() == EntryFormat::Short
+            || entry.atime() > entry.mtime()
+            || entry.is_link();
+        let location = Arc::new(Mutex::new(Location {
             parent: Some(Box::new(entry.dir())),
             name: entry.name(),
+            links: None,
+        }));
+        let mut locations = LOCATIONS.lock();
+        locations.retain(|location| location.strong_count() > 0);
+        locations.push(Arc::downgrade(&location));
+        Self {
+            location,
             addr: entry.addr(),
             size: entry.size(),
             offset: 0,
+            is_accessed,
+            dev: entry.dir().dev(),
         }
     }
 }
@@ -49,21 +126,63 @@ impl File {
     }
 
     pub fn open(pathname: &str) -> Option<Self> {
+        if let Some(dir_entry) = DirEntry::open(pathname) {
+            if dir_entry.is_file() {
+                return Some(dir_entry.into());
+            }
+        }
+        None
+    }
+
+    pub fn create_link(pathname: &str) -> Option<Self> {
         let pathname = realpath(pathname);
         let dirname = dirname(&pathname);
         let filename = filename(&pathname);
-        if let Some(dir) = Dir::open(dirname) {
-            if let Some(dir_entry) = dir.find(filename) {
-                if dir_entry.is_file() {
-                    return Some(dir_entry.into());
-                }
+        let mut dir = Dir::open(dirname)?;
+        let _dev = mount::select(dir.dev());
+        if !SuperBlock::read().has_links() {
+            return None;
+        }
+        dir.create_link(filename).map(|dir_entry| dir_entry.into())
+    }
+
+    // Open a symbolic link to read or write its target
+    pub fn open_link(pathname: &str) -> Option<Self> {
+        if let Some(dir_entry) = DirEntry::open_link(pathname) {
+            if dir_entry.is_link() {
+                return Some(dir_entry.into());
             }
         }
         None
     }
 
     pub fn name(&self) -> String {
-        self.name.clone()
+        self.location.lock().name.clone()
+    }
+
+    // Path of the file from the root of the VFS
+    pub fn path(&self) -> String {
+        self.location.lock().path()
+    }
+
+    fn parent(&self) -> (Option<Box<Dir>>, String) {
+        let location = self.location.lock();
+        (location.parent.clone(), location.name.clone())
+    }
+
+    // Return the paths of the hard links of the file, which are only found
+    // again when links have been added or removed or when an entry has been
+    // renamed since the last time.
+    fn link_paths(&self, n: u16) -> Vec<String> {
+        let renames = RENAMES.load(Ordering::SeqCst);
+        if let Some((links, r, paths)) = &self.location.lock().links {
+            if *links == n && *r == renames {
+                return paths.clone();
+            }
+        }
+        let paths = Dir::root().find_links(self.addr);
+        self.location.lock().links = Some((n, renames, paths.clone()));
+        paths
     }
 
     pub fn size(&self) -> usize {
@@ -110,87 +229,242 @@ impl File {
     }
 }
 
-impl FileIO for File {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
-        let buf_len = buf.len();
-        let mut addr = self.addr;
+impl File {
+    fn read_linked(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let mut block = LinkedBlock::read(self.addr);
+        let data_len = block.len();
+
+        // Skip the blocks before the offset
+        for _ in 0..(self.offset as usize / data_len) {
+            match block.next() {
+                Some(next_block) => block = next_block,
+                None => return Ok(0),
+            }
+        }
+
+        let n = cmp::min(buf.len(), self.size().saturating_sub(self.offset as usize));
         let mut bytes = 0; // Number of bytes read
-        let mut pos = 0; // Position in the file
-        loop {
-            let block = LinkedBlock::read(addr);
-            let data = block.data();
-            let data_len = data.len();
-            for i in 0..data_len {
-                if pos == self.offset {
-                    if bytes == buf_len || pos as usize == self.size() {
-                        return Ok(bytes);
-                    }
-                    buf[bytes] = data[i];
-                    bytes += 1;
-                    self.offset += 1;
+        while bytes < n {
+            let i = self.offset as usize % data_len;
+            let j = cmp::min(data_len - i, n - bytes);
+            buf[bytes..(bytes + j)].copy_from_slice(&block.data()[i..(i + j)]);
+            bytes += j;
+            self.offset += j as u32;
+            if bytes < n {
+                match block.next() {
+                    Some(next_block) => block = next_block,
+                    None => break,
                 }
-                pos += 1;
-            }
-            match block.next() {
-                Some(next_block) => addr = next_block.addr(),
-                None => return Ok(bytes),
             }
         }
+        Ok(bytes)
     }
 
-    fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+    fn write_linked(&mut self, buf: &[u8]) -> Result<usize, ()> {
         let buf_len = buf.len();
-        let mut addr = self.addr;
+        if buf_len == 0 {
+            return Ok(0);
+        }
+
+        let mut block = LinkedBlock::read(self.addr);
+        let data_len = block.len();
+
+        // Skip the blocks before the offset
+        for _ in 0..(self.offset as usize / data_len) {
+            block = match block.next() {
+                Some(next_block) => next_block,
+                None => journal::transaction(|| block.alloc_next())?.ok_or(())?,
+            };
+        }
+
         let mut bytes = 0; // Number of bytes written
-        let mut pos = 0; // Position in the file
         while bytes < buf_len {
-            let mut block = LinkedBlock::read(addr);
-            let data = block.data_mut();
-            let data_len = data.len();
-            for i in 0..data_len {
-                if pos == self.offset {
-                    if bytes == buf_len {
-                        break;
+            let i = self.offset as usize % data_len;
+            let j = cmp::min(data_len - i, buf_len - bytes);
+            block.data_mut()[i..(i + j)].copy_from_slice(&buf[bytes..(bytes + j)]);
+            bytes += j;
+            self.offset += j as u32;
+            if bytes < buf_len {
+                block = match block.next() {
+                    Some(next_block) => {
+                        block.write();
+                        next_block
                     }
-                    data[i] = buf[bytes];
-                    bytes += 1;
-                    self.offset += 1;
-                }
-                pos += 1;
+                    None => {
+                        // The allocation of the next block is committed
+                        // with the link from the current block.
+                        journal::transaction(|| block.alloc_next())?.ok_or(())?
+                    }
+                };
+            } else {
+                // TODO: Free the next block(s)
+                block.set_next_addr(0);
+                block.write();
             }
+        }
+        Ok(bytes)
+    }
 
-            addr = match block.next() {
-                Some(next_block) => {
-                    if bytes < buf_len {
-                        next_block.addr()
-                    } else {
-                        // TODO: Free the next block(s)
-                        0
+    fn read_indexed(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let bs = super::BLOCK_SIZE;
+        let n = cmp::min(buf.len(), self.size().saturating_sub(self.offset as usize));
+        let mut bytes = 0; // Number of bytes read
+        while bytes < n {
+            let index = self.offset / bs as u32;
+            let i = self.offset as usize % bs;
+            let mut j = cmp::min(bs - i, n - bytes);
+            match index_block::data_addr(self.addr, index, false) {
+                Some(addr) if j == bs => {
+                    // Read the following data blocks that are contiguous on
+                    // the disk with the same request
+                    let max = (n - bytes) / bs;
+                    let mut k = 1;
+                    while k < max {
+                        let next = index_block::data_addr(self.addr, index + k as u32, false);
+                        if next != Some(addr + k as u32) {
+                            break;
+                        }
+                        k += 1;
                     }
+                    j = k * bs;
+                    block::read_blocks(addr, &mut buf[bytes..(bytes + j)]);
+                }
+                Some(addr) => {
+                    let block = Block::read(addr);
+                    buf[bytes..(bytes + j)].copy_from_slice(&block.data()[i..(i + j)]);
                 }
                 None => {
-                    if bytes < buf_len {
-                        match LinkedBlock::alloc() {
-                            Some(next_block) => next_block.addr(),
-                            None => return Err(()),
-                        }
-                    } else {
-                        0
+                    for b in buf[bytes..(bytes + j)].iter_mut() {
+                        *b = 0;
                     }
                 }
-            };
+            }
+            bytes += j;
+            self.offset += j as u32;
+        }
+        Ok(bytes)
+    }
+
+    fn write_indexed(&mut self, buf: &[u8]) -> Result<usize, ()> {
+        let bs = super::BLOCK_SIZE;
+        let root = self.addr;
+        let buf_len = buf.len();
+        let mut bytes = 0; // Number of bytes written
+        while bytes < buf_len {
+            let index = self.offset / bs as u32;
+            let i = self.offset as usize % bs;
+            let mut j = cmp::min(bs - i, buf_len - bytes);
+            let addr = journal::transaction(|| {
+                index_block::data_addr(root, index, true)
+            })?.ok_or(())?;
+            if j == bs {
+                // Write the following data blocks that are contiguous on the
+                // disk with the same request. The first block allocated out
+                // of the sequence will be used by the next iteration.
+                let max = (buf_len - bytes) / bs;
+                let mut k = 1;
+                while k < max {
+                    let next = journal::transaction(|| {
+                        index_block::data_addr(root, index + k as u32, true)
+                    })?.ok_or(())?;
+                    if next != addr + k as u32 {
+                        break;
+                    }
+                    k += 1;
+                }
+                j = k * bs;
+                block::write_blocks(addr, &buf[bytes..(bytes + j)]);
+            } else {
+                let mut block = Block::read(addr);
+                block.data_mut()[i..(i + j)].copy_from_slice(&buf[bytes..(bytes + j)]);
+                block.write();
+            }
+            bytes += j;
+            self.offset += j as u32;
+        }
 
-            block.set_next_addr(addr);
-            block.write();
+        // Free the blocks after the end of the file
+        let n = (self.offset as usize + bs - 1) / bs;
+        index_block::truncate(root, n as u32)?;
+        Ok(bytes)
+    }
+}
+
+impl FileIO for File {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let _dev = mount::select(self.dev);
+        let bytes = if SuperBlock::read().has_indexed_files() {
+            self.read_indexed(buf)?
+        } else {
+            self.read_linked(buf)?
+        };
+        if bytes > 0 && !self.is_accessed {
+            self.is_accessed = true;
+            if let (Some(dir), name) = self.parent() {
+                let time = sys::clock::realtime() as u64;
+                dir.update_entry_with(&name, |entry| entry.set_atime(time)).ok();
+            }
         }
+        Ok(bytes)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+        let _dev = mount::select(self.dev);
+        let sb = SuperBlock::read();
+        let bytes = if sb.has_indexed_files() {
+            self.write_indexed(buf)?
+        } else {
+            self.write_linked(buf)?
+        };
         self.size = self.offset;
-        if let Some(dir) = self.parent.clone() {
-            dir.update_entry(&self.name, self.size);
+        if let (Some(dir), name) = self.parent() {
+            dir.update_entry(&name, self.size);
+        }
+        // The other entries of a hard linked file read their size and their
+        // modification time from its root block
+        let mut links = 0;
+        if sb.has_indexed_files() {
+            let mut root = IndexBlock::read_root(self.addr);
+            if root.metadata().is_some() {
+                root.set_metadata(self.size, sys::clock::realtime() as u64);
+                root.write();
+                links = root.links();
+            }
+        }
+        if watch::is_active() {
+            let path = self.path();
+            watch::post(EventKind::Write, &path);
+            if links > 0 {
+                for link in self.link_paths(links) {
+                    if link != path {
+                        watch::post(EventKind::Write, &link);
+                    }
+                }
+            }
         }
         Ok(bytes)
     }
 }
 
+impl Node for File {
+    fn read_at(&mut self, offset: usize, buf: &mut [u8]) -> Result<usize, ()> {
+        if offset > self.size() {
+            return Ok(0);
+        }
+        self.seek(SeekFrom::Start(offset as u32))?;
+        self.read(buf)
+    }
+
+    fn write_at(&mut self, offset: usize, buf: &[u8]) -> Result<usize, ()> {
+        self.seek(SeekFrom::Start(offset as u32))?;
+        self.write(buf)
+    }
+
+    fn box_clone(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+}
+
 #[test_case]
 fn test_file_create() {
     super::mount_mem();
@@ -237,6 +511,24 @@ fn test_file_read() {
     super::dismount();
 }
 
+#[test_case]
+fn test_file_read_blocks() {
+    super::mount_mem();
+    super::format_mem();
+    let bs = super::BLOCK_SIZE;
+    let input: Vec<u8> = (0..(5 * bs + 7)).map(|i| (i % 251) as u8).collect();
+    let mut file = File::create("/test").unwrap();
+    assert_eq!(file.write(&input[0..7]), Ok(7));
+    assert_eq!(file.write(&input[7..]), Ok(5 * bs));
+
+    let mut file = File::open("/test").unwrap();
+    let mut output = vec![0; input.len()];
+    assert_eq!(file.read(&mut output[0..3]), Ok(3));
+    assert_eq!(file.read(&mut output[3..]), Ok(input.len() - 3));
+    assert_eq!(input, output);
+    super::dismount();
+}
+
 #[test_case]
 fn test_file_delete() {
     super::mount_mem();
@@ -248,3 +540,71 @@ fn test_file_delete() {
     assert!(File::open("/test").is_none());
     super::dismount();
 }
+
+#[test_case]
+fn test_file_links() {
+    super::mount_mem();
+    super::format_mem();
+    let mut file = File::create("/test").unwrap();
+    file.write(b"Hello").unwrap();
+    assert!(super::Dir::create("/tmp").is_some());
+
+    // Symbolic links to a file and a dir
+    File::create_link("/link").unwrap().write(b"test").unwrap();
+    File::create_link("/tmp/dir").unwrap().write(b"/tmp").unwrap();
+    assert_eq!(File::open("/link").unwrap().read_to_string(), "Hello");
+    assert!(super::Dir::open("/tmp/dir/dir").is_some());
+
+    // Loop of symbolic links
+    File::create_link("/loop").unwrap().write(b"/loop").unwrap();
+    assert!(File::open("/loop").is_none());
+    assert!(super::delete("/loop").is_ok());
+
+    // Hard links share their contents
+    assert!(super::link("/link", "/tmp/hard").is_ok());
+    File::open("/tmp/hard").unwrap().write(b"Hello, World!").unwrap();
+    assert_eq!(File::open("/test").unwrap().read_to_string(), "Hello, World!");
+    assert!(super::delete("/test").is_ok());
+    assert_eq!(File::open("/tmp/hard").unwrap().read_to_string(), "Hello, World!");
+    assert!(File::open("/link").is_none());
+    assert_eq!(super::check(false), Ok(vec::Vec::new()));
+
+    // The size and the modification time are shared by the hard links
+    assert!(super::link("/tmp/hard", "/tmp/other").is_ok());
+    File::open("/tmp/other").unwrap().write(b"Hello").unwrap();
+    let a = super::info("/tmp/hard").unwrap();
+    let b = super::info("/tmp/other").unwrap();
+    assert_eq!((a.size(), a.mtime()), (5, b.mtime()));
+
+    super::dismount();
+}
+
+#[test_case]
+fn test_file_watch() {
+    super::mount_mem();
+    super::format_mem();
+    let mut watcher = super::Watcher::new("/tmp/");
+    assert!(Dir::create("/tmp").is_some());
+    let mut file = File::create("/tmp/a").unwrap();
+    assert!(super::rename("/tmp/a", "/tmp/b").is_ok());
+    assert_eq!(file.path(), "/tmp/b");
+    file.write(b"Hello").unwrap();
+    assert_eq!(File::open("/tmp/b").unwrap().size(), 5);
+
+    // A write through a hard link is sent to the other links
+    assert!(super::link("/tmp/b", "/c").is_ok());
+    let mut link = File::open("/c").unwrap();
+    link.write(b"Hello, World!").unwrap();
+    assert!(super::chmod("/tmp/b", 0o600).is_ok());
+
+    // The paths of the links are found again after a rename
+    assert!(super::rename("/tmp/b", "/tmp/d").is_ok());
+    link.write(b"Hello").unwrap();
+
+    let mut buf = [0; 256];
+    let n = watcher.read(&mut buf).unwrap();
+    let res = "create /tmp\ncreate /tmp/a\nrename /tmp/a /tmp/b\nwrite /tmp/b\n\
+               write /tmp/b\nchange /tmp/b\nrename /tmp/b /tmp/d\nwrite /tmp/d\n";
+    assert_eq!(&buf[0..n], res.as_bytes());
+    super::dismount();
+}
@@ -0,0 +1,373 @@
+use super::bitmap_block::BitmapBlock;
+use super::block::{Block, LinkedBlock};
+use super::dir::Dir;
+use super::dir_entry::{DirEntry, EntryFormat};
+use super::index_block::IndexBlock;
+use super::super_block::SuperBlock;
+use super::BITMAP_SIZE;
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::btree_set::BTreeSet;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use bit_field::BitField;
+use core::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    OrphanedBlock(u32),
+    UnallocatedBlock(u32),
+    DoubleAllocatedBlock(u32),
+    InvalidBlockAddr(u32),
+    Cycle(u32),
+    BadEntry(String, usize),
+    AllocCount(u32, u32),
+    LinkCount(u32, u16, u16),
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::OrphanedBlock(addr) => {
+                write!(f, "Block {:#x} is allocated but not used", addr)
+            }
+            Issue::UnallocatedBlock(addr) => {
+                write!(f, "Block {:#x} is used but not allocated", addr)
+            }
+            Issue::DoubleAllocatedBlock(addr) => {
+                write!(f, "Block {:#x} is used more than once", addr)
+            }
+            Issue::InvalidBlockAddr(addr) => {
+                write!(f, "Block {:#x} is outside of the data area", addr)
+            }
+            Issue::Cycle(addr) => {
+                write!(f, "Block {:#x} is linked in a cycle", addr)
+            }
+            Issue::BadEntry(path, offset) => {
+                write!(f, "Bad entry in '{}' at offset {}", path, offset)
+            }
+            Issue::AllocCount(expected, found) => {
+                write!(f, "Superblock has {} allocated blocks instead of {}", found, expected)
+            }
+            Issue::LinkCount(addr, expected, found) => {
+                write!(f, "Block {:#x} has {} links instead of {}", addr, found, expected)
+            }
+        }
+    }
+}
+
+struct Checker {
+    repair: bool,
+    is_indexed: bool,
+    has_links: bool,
+    format: EntryFormat,
+    root: Dir,
+    data_area: u32,
+    block_count: u32,
+    used: BTreeSet<u32>,
+    links: BTreeMap<u32, u16>,
+    issues: Vec<Issue>,
+}
+
+impl Checker {
+    fn new(repair: bool) -> Self {
+        let sb = SuperBlock::read();
+        Self {
+            repair,
+            is_indexed: sb.has_indexed_files(),
+            has_links: sb.has_links(),
+            format: EntryFormat::current(),
+            root: Dir::root(),
+            data_area: sb.data_area(),
+            block_count: sb.block_count(),
+            used: BTreeSet::new(),
+            links: BTreeMap::new(),
+            issues: Vec::new(),
+        }
+    }
+
+    fn is_valid_addr(&self, addr: u32) -> bool {
+        self.data_area <= addr && addr < self.block_count
+    }
+
+    // Return the issue preventing a block from being linked, if any
+    fn check_link(&self, addr: u32) -> Option<Issue> {
+        if !self.is_valid_addr(addr) {
+            Some(Issue::InvalidBlockAddr(addr))
+        } else if self.used.contains(&addr) {
+            Some(Issue::DoubleAllocatedBlock(addr))
+        } else {
+            None
+        }
+    }
+
+    fn mark_used(&mut self, addr: u32) {
+        self.used.insert(addr);
+        if !BitmapBlock::is_alloc(addr) {
+            self.issues.push(Issue::UnallocatedBlock(addr));
+            if self.repair {
+                BitmapBlock::alloc(addr);
+            }
+        }
+    }
+
+    // Follow a chain of linked blocks, truncating it at the first bad link
+    // when repairing, and return the addresses of its blocks.
+    fn check_chain(&mut self, addr: u32) -> Vec<u32> {
+        let mut chain = Vec::new();
+        let mut visited = BTreeSet::new();
+        let mut block = LinkedBlock::read(addr);
+        loop {
+            chain.push(block.addr());
+            visited.insert(block.addr());
+            self.mark_used(block.addr());
+
+            let next_addr = block.next_addr();
+            if next_addr == 0 {
+                break;
+            }
+            let issue = if visited.contains(&next_addr) {
+                Some(Issue::Cycle(next_addr))
+            } else {
+                self.check_link(next_addr)
+            };
+            if let Some(issue) = issue {
+                self.issues.push(issue);
+                if self.repair {
+                    block.set_next_addr(0);
+                    block.write();
+                }
+                break;
+            }
+            block = LinkedBlock::read(next_addr);
+        }
+        chain
+    }
+
+    // Walk a tree of index blocks from its root, removing the bad links when
+    // repairing.
+    fn check_index(&mut self, addr: u32) {
+        self.mark_used(addr);
+        let mut root = IndexBlock::read_root(addr);
+        let depth = root.depth();
+        if depth > 0 && self.check_index_block(&mut root, depth) {
+            root.write();
+        }
+    }
+
+    // Check the children of an index block at the given level of the tree,
+    // and return true if the block has been modified.
+    fn check_index_block(&mut self, node: &mut IndexBlock, level: u8) -> bool {
+        let mut is_modified = false;
+        for i in 0..node.len() {
+            let addr = node.get(i);
+            if addr == 0 {
+                continue;
+            }
+            if let Some(issue) = self.check_link(addr) {
+                self.issues.push(issue);
+                if self.repair {
+                    node.set(i, 0);
+                    is_modified = true;
+                }
+                continue;
+            }
+            self.mark_used(addr);
+            if level > 1 {
+                let mut child = IndexBlock::read(addr);
+                if self.check_index_block(&mut child, level - 1) {
+                    child.write();
+                }
+            }
+        }
+        is_modified
+    }
+
+    fn check_dir(&mut self, path: &str, addr: u32) {
+        let sep = if path.ends_with('/') { "" } else { "/" };
+        for block_addr in self.check_chain(addr) {
+            let mut block = LinkedBlock::read(block_addr);
+            let n = block.len();
+            let mut i = 0;
+            while i + self.format.empty_len() < n {
+                let data = &block.data()[i..];
+                let mut entry = match DirEntry::parse(self.root.clone(), self.format, data) {
+                    Some(entry) => entry,
+                    None if data.iter().all(|b| *b == 0) => break, // End of entries
+                    None => {
+                        self.issues.push(Issue::BadEntry(path.into(), i));
+                        if self.repair {
+                            // Discard the rest of the block
+                            for b in block.data_mut()[i..].iter_mut() {
+                                *b = 0;
+                            }
+                            block.write();
+                        }
+                        break;
+                    }
+                };
+
+                let entry_addr = entry.addr();
+                if entry_addr != 0 { // Skip deleted entries
+                    let entry_path = format!("{}{}{}", path, sep, entry.name());
+                    if !entry.is_dir() && self.has_links && self.links.contains_key(&entry_addr) {
+                        // Hard link to a file that has already been checked
+                        if let Some(n) = self.links.get_mut(&entry_addr) {
+                            *n = n.saturating_add(1);
+                        }
+                    } else if let Some(issue) = self.check_link(entry_addr) {
+                        self.issues.push(issue);
+                        if self.repair {
+                            // Delete the entry by zeroing its address
+                            entry.set_addr(0);
+                            let bytes = entry.as_bytes();
+                            block.data_mut()[i..(i + bytes.len())].clone_from_slice(&bytes);
+                            block.write();
+                        }
+                    } else if entry.is_dir() {
+                        self.check_dir(&entry_path, entry_addr);
+                    } else if self.is_indexed {
+                        self.links.insert(entry_addr, 1);
+                        self.check_index(entry_addr);
+                    } else {
+                        self.check_chain(entry_addr);
+                    }
+                }
+                i += entry.len();
+            }
+        }
+    }
+
+    // Compare the number of links of each file with the number of entries
+    // found while walking the tree.
+    fn check_links(&mut self) {
+        if !self.has_links {
+            return;
+        }
+        let links: Vec<(u32, u16)> = self.links.iter().map(|(k, v)| (*k, *v)).collect();
+        for (addr, n) in links {
+            let mut root = IndexBlock::read_root(addr);
+            if root.links() != n {
+                self.issues.push(Issue::LinkCount(addr, n, root.links()));
+                if self.repair {
+                    root.set_links(n);
+                    root.write();
+                }
+            }
+        }
+    }
+
+    // Compare the bitmap with the blocks found while walking the tree
+    fn check_bitmap(&mut self) {
+        let mut sb = SuperBlock::read();
+        if !sb.has_full_bitmap() {
+            return; // The bits of older versions are shared by many blocks
+        }
+        let mut alloc_count = 0;
+        let mut free_count = 0;
+        for i in sb.bitmap_area()..sb.data_area() {
+            let mut block = Block::read(i);
+            let mut modified = false;
+            let offset = self.data_area + (i - sb.bitmap_area()) * BITMAP_SIZE as u32;
+            let bitmap = block.data_mut();
+            for j in 0..bitmap.len() {
+                for k in 0..8 {
+                    if !bitmap[j].get_bit(k) {
+                        continue;
+                    }
+                    let addr = offset + (j * 8 + k) as u32;
+                    if addr < self.block_count && !self.used.contains(&addr) {
+                        self.issues.push(Issue::OrphanedBlock(addr));
+                        if self.repair {
+                            bitmap[j].set_bit(k, false);
+                            modified = true;
+                            free_count += 1;
+                        }
+                    }
+                    alloc_count += 1;
+                }
+            }
+            if modified {
+                block.write();
+            }
+        }
+
+        // The blocks freed while repairing are still counted in the
+        // superblock before being removed from it.
+        if sb.alloc_count != alloc_count {
+            self.issues.push(Issue::AllocCount(alloc_count, sb.alloc_count));
+        }
+        if self.repair && sb.alloc_count != alloc_count - free_count {
+            sb.alloc_count = alloc_count - free_count;
+            sb.write();
+        }
+    }
+}
+
+// Walk the tree of dirs from the root, following every chain of blocks and
+// tree of index blocks, and compare the blocks found with the allocation
+// bitmap and the superblock.
+pub fn check(repair: bool) -> Result<Vec<Issue>, ()> {
+    let (_dev, _) = super::mount::select_path("/").ok_or(())?;
+    let mut checker = Checker::new(repair);
+    let root = checker.data_area;
+    checker.check_dir("/", root);
+    checker.check_links();
+    checker.check_bitmap();
+    Ok(checker.issues)
+}
+
+// Return the blocks found while walking the tree of dirs of the selected
+// device, without modifying it.
+pub fn used_blocks() -> BTreeSet<u32> {
+    let mut checker = Checker::new(false);
+    let root = checker.data_area;
+    checker.check_dir("/", root);
+    checker.used
+}
+
+#[test_case]
+fn test_check() {
+    use super::FileIO;
+    use super::index_block;
+    use alloc::vec;
+    super::mount_mem();
+    super::format_mem();
+    assert!(super::File::create("/test").is_some());
+    assert!(super::Dir::create("/tmp").is_some());
+    assert_eq!(check(false), Ok(Vec::new()));
+
+    // Allocate a block without linking it
+    let addr = BitmapBlock::next_free_addr().unwrap();
+    BitmapBlock::alloc(addr);
+    assert_eq!(check(false), Ok(vec![Issue::OrphanedBlock(addr)]));
+    assert_eq!(check(true), Ok(vec![Issue::OrphanedBlock(addr)]));
+    assert_eq!(check(false), Ok(Vec::new()));
+
+    // Free a block that is still used by a file
+    let mut file = super::File::open("/test").unwrap();
+    assert_eq!(file.write(b"Hello"), Ok(5));
+    let addr = index_block::data_addr(file.addr(), 0, false).unwrap();
+    BitmapBlock::free(addr);
+    assert_eq!(check(false), Ok(vec![Issue::UnallocatedBlock(addr)]));
+    assert_eq!(check(true), Ok(vec![Issue::UnallocatedBlock(addr)]));
+    assert_eq!(check(false), Ok(Vec::new()));
+
+    // Link the same block into two files
+    let mut other = super::File::create("/tmp/test").unwrap();
+    assert_eq!(other.write(b"World"), Ok(5));
+    let other_addr = index_block::data_addr(other.addr(), 0, false).unwrap();
+    let mut root = IndexBlock::read_root(other.addr());
+    root.set(0, addr);
+    root.write();
+    let issues = vec![
+        Issue::DoubleAllocatedBlock(addr),
+        Issue::OrphanedBlock(other_addr),
+    ];
+    assert_eq!(check(false), Ok(issues.clone()));
+    assert_eq!(check(true), Ok(issues));
+    assert_eq!(check(false), Ok(Vec::new()));
+    assert_eq!(super::File::open("/test").unwrap().read_to_string(), "Hello");
+    super::dismount();
+}
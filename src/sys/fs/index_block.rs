@@ -0,0 +1,372 @@
+use super::bitmap_block::BitmapBlock;
+use super::block::Block;
+use super::journal;
+
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+// Since version 3 of MFS the contents of a file is stored in a tree of index
+// blocks where the leaves are data blocks. The root of the tree is the block
+// referenced by the dir entry of the file, and it starts with a header giving
+// the depth of the tree. The others index blocks contain only addresses.
+//
+// Root block structure:
+// 0 => depth of the tree
+// 1 => flags (since version 6)
+// 2..4 => number of hard links (since version 5)
+// 4..512 => addresses of children blocks
+//
+// Since version 6 the root block of a hard linked file has a larger header
+// with the size and the modification time shared by all of its entries:
+// 4..8 => size
+// 8..16 => modification time
+// 16..512 => addresses of children blocks
+//
+// Index block structure:
+// 0..512 => addresses of children blocks
+
+// Maximum number of blocks freed in a transaction, which will also modify up
+// to the same number of bitmap blocks, a few index blocks, and the superblock.
+const FREE_BATCH: usize = 16;
+
+const ROOT_HEADER_SIZE: usize = 4;
+const SHARED_HEADER_SIZE: usize = 16;
+const FLAG_SHARED: u8 = 1;
+const ADDR_SIZE: usize = 4;
+const MAX_DEPTH: u8 = 4;
+
+pub struct IndexBlock {
+    block: Block,
+    offset: usize,
+}
+
+impl IndexBlock {
+    pub fn read_root(addr: u32) -> Self {
+        let block = Block::read(addr);
+        let offset = if block.data()[1] & FLAG_SHARED != 0 {
+            SHARED_HEADER_SIZE
+        } else {
+            ROOT_HEADER_SIZE
+        };
+        Self { block, offset }
+    }
+
+    pub fn read(addr: u32) -> Self {
+        Self { block: Block::read(addr), offset: 0 }
+    }
+
+    fn alloc() -> Option<Self> {
+        Block::alloc().map(|block| Self { block, offset: 0 })
+    }
+
+    pub fn write(&self) {
+        self.block.write()
+    }
+
+    pub fn addr(&self) -> u32 {
+        self.block.addr()
+    }
+
+    // NOTE: Only the root block has a depth
+    pub fn depth(&self) -> u8 {
+        self.block.data()[0]
+    }
+
+    fn set_depth(&mut self, depth: u8) {
+        self.block.data_mut()[0] = depth;
+    }
+
+    // NOTE: Only the root block has a number of links, and it is zero for
+    // the files created before version 5.
+    pub fn links(&self) -> u16 {
+        let n = u16::from_be_bytes(self.block.data()[2..4].try_into().unwrap());
+        core::cmp::max(n, 1)
+    }
+
+    pub fn set_links(&mut self, n: u16) {
+        self.block.data_mut()[2..4].clone_from_slice(&n.to_be_bytes());
+    }
+
+    // NOTE: Only the root block of a hard linked file has a size and a
+    // modification time.
+    pub fn metadata(&self) -> Option<(u32, u64)> {
+        if self.offset != SHARED_HEADER_SIZE {
+            return None;
+        }
+        let data = self.block.data();
+        let size = u32::from_be_bytes(data[4..8].try_into().unwrap());
+        let mtime = u64::from_be_bytes(data[8..16].try_into().unwrap());
+        Some((size, mtime))
+    }
+
+    pub fn set_metadata(&mut self, size: u32, mtime: u64) {
+        debug_assert_eq!(self.offset, SHARED_HEADER_SIZE);
+        let data = self.block.data_mut();
+        data[4..8].clone_from_slice(&size.to_be_bytes());
+        data[8..16].clone_from_slice(&mtime.to_be_bytes());
+    }
+
+    // Enlarge the header of the root block to store the metadata shared by
+    // the hard links of a file, after growing the tree if its last addresses
+    // are used. Return None if the tree could not grow.
+    pub fn share_metadata(&mut self, size: u32, mtime: u64) -> Option<()> {
+        if self.offset == SHARED_HEADER_SIZE {
+            self.set_metadata(size, mtime);
+            return Some(());
+        }
+        let n = (super::BLOCK_SIZE - SHARED_HEADER_SIZE) / ADDR_SIZE;
+        if (n..self.len()).any(|i| self.get(i) != 0) {
+            let depth = self.depth();
+            if depth == MAX_DEPTH {
+                return None;
+            }
+            let mut node = IndexBlock::alloc()?;
+            for i in 0..self.len() {
+                node.set(i, self.get(i));
+                self.set(i, 0);
+            }
+            node.write();
+            self.set(0, node.addr());
+            self.set_depth(depth + 1);
+        }
+        let addrs: Vec<u32> = (0..n).map(|i| self.get(i)).collect();
+        self.block.data_mut()[1] |= FLAG_SHARED;
+        self.offset = SHARED_HEADER_SIZE;
+        for (i, addr) in addrs.into_iter().enumerate() {
+            self.set(i, addr);
+        }
+        self.set_metadata(size, mtime);
+        Some(())
+    }
+
+    // Number of addresses in the block
+    pub fn len(&self) -> usize {
+        (super::BLOCK_SIZE - self.offset) / ADDR_SIZE
+    }
+
+    pub fn get(&self, i: usize) -> u32 {
+        let j = self.offset + i * ADDR_SIZE;
+        u32::from_be_bytes(self.block.data()[j..(j + ADDR_SIZE)].try_into().unwrap())
+    }
+
+    pub fn set(&mut self, i: usize, addr: u32) {
+        let j = self.offset + i * ADDR_SIZE;
+        self.block.data_mut()[j..(j + ADDR_SIZE)].clone_from_slice(&addr.to_be_bytes());
+    }
+}
+
+// Number of data blocks under each child of an index block at the given
+// level of the tree, the children of a block at level 1 being data blocks.
+fn span(level: u8) -> u64 {
+    let n = (super::BLOCK_SIZE / ADDR_SIZE) as u64;
+    n.pow(level as u32 - 1)
+}
+
+// Number of data blocks addressable by the tree of a root block
+fn capacity(root: &IndexBlock) -> u64 {
+    let depth = root.depth();
+    if depth == 0 {
+        0
+    } else {
+        root.len() as u64 * span(depth)
+    }
+}
+
+// Return the address of the data block at the given index of a file, and
+// allocate the missing blocks on the way if requested.
+pub fn data_addr(root_addr: u32, index: u32, alloc: bool) -> Option<u32> {
+    let mut i = index as u64;
+    let mut node = IndexBlock::read_root(root_addr);
+    if i >= capacity(&node) {
+        if !alloc {
+            return None;
+        }
+        grow(&mut node, i)?;
+    }
+    let mut level = node.depth();
+    loop {
+        let n = span(level);
+        let j = (i / n) as usize;
+        i %= n;
+        let mut addr = node.get(j);
+        if addr == 0 {
+            if !alloc {
+                return None;
+            }
+            addr = Block::alloc()?.addr();
+            node.set(j, addr);
+            node.write();
+        }
+        if level == 1 {
+            return Some(addr);
+        }
+        node = IndexBlock::read(addr);
+        level -= 1;
+    }
+}
+
+// Increase the depth of the tree until the index fits in it by moving the
+// children of the root block into a new index block.
+fn grow(root: &mut IndexBlock, index: u64) -> Option<()> {
+    while index >= capacity(root) {
+        let depth = root.depth();
+        if depth == MAX_DEPTH {
+            return None;
+        }
+        if depth > 0 {
+            let mut node = IndexBlock::alloc()?;
+            for i in 0..root.len() {
+                node.set(i, root.get(i));
+                root.set(i, 0);
+            }
+            node.write();
+            root.set(0, node.addr());
+        }
+        root.set_depth(depth + 1);
+        root.write();
+    }
+    Some(())
+}
+
+// Write the tree of index blocks of a file with the given data blocks and the
+// index blocks returned by the allocator, which is used to write a new copy
+// of a file without allocating its blocks in the bitmap.
+pub fn build<F>(root_addr: u32, addrs: &[u32], alloc: &mut F) -> Option<()> where F: FnMut() -> Option<u32> {
+    let mut root = IndexBlock { block: Block::new(root_addr), offset: ROOT_HEADER_SIZE };
+    while (addrs.len() as u64) > capacity(&root) {
+        let depth = root.depth();
+        if depth == MAX_DEPTH {
+            return None;
+        }
+        root.set_depth(depth + 1);
+    }
+    let depth = root.depth();
+    if depth > 0 {
+        build_from(&mut root, depth, addrs, alloc)?;
+    }
+    root.write();
+    Some(())
+}
+
+fn build_from<F>(node: &mut IndexBlock, level: u8, addrs: &[u32], alloc: &mut F) -> Option<()> where F: FnMut() -> Option<u32> {
+    for (i, chunk) in addrs.chunks(span(level) as usize).enumerate() {
+        if level == 1 {
+            node.set(i, chunk[0]);
+        } else {
+            let mut child = IndexBlock { block: Block::new(alloc()?), offset: 0 };
+            build_from(&mut child, level - 1, chunk, alloc)?;
+            child.write();
+            node.set(i, child.addr());
+        }
+    }
+    Some(())
+}
+
+// Free the data blocks of a file starting at the given index, from the end
+// of the file and in multiple transactions to fit in the journal.
+pub fn truncate(root_addr: u32, index: u32) -> Result<(), ()> {
+    loop {
+        let is_done = journal::transaction(|| {
+            let mut root = IndexBlock::read_root(root_addr);
+            let depth = root.depth();
+            if depth == 0 {
+                return true;
+            }
+            let mut budget = FREE_BATCH;
+            let (is_modified, is_done) = free_from(&mut root, depth, 0, index as u64, &mut budget);
+            if is_modified {
+                root.write();
+            }
+            is_done
+        })?;
+        if is_done {
+            return Ok(());
+        }
+    }
+}
+
+// Free all the blocks of a file including its root block
+pub fn free(root_addr: u32) -> Result<(), ()> {
+    truncate(root_addr, 0)?;
+    journal::transaction(|| BitmapBlock::free(root_addr))
+}
+
+// Free the children of an index block at the given level of the tree starting
+// at the given index, from the last one and until the budget of blocks freed
+// is spent. Return true if the index block has been modified, and true if all
+// the blocks after the index have been freed.
+fn free_from(node: &mut IndexBlock, level: u8, first: u64, index: u64, budget: &mut usize) -> (bool, bool) {
+    let n = span(level);
+    let mut is_modified = false;
+    for i in (0..node.len()).rev() {
+        let addr = node.get(i);
+        if addr == 0 {
+            continue;
+        }
+        let start = first + (i as u64) * n;
+        if start + n <= index {
+            break;
+        }
+        if *budget == 0 {
+            return (is_modified, false);
+        }
+        if level > 1 {
+            let mut child = IndexBlock::read(addr);
+            let (is_child_modified, is_done) = free_from(&mut child, level - 1, start, index, budget);
+            if is_done && start >= index && *budget > 0 {
+                // The index block is empty
+                BitmapBlock::free(addr);
+                node.set(i, 0);
+                is_modified = true;
+                *budget -= 1;
+                continue;
+            }
+            if is_child_modified {
+                child.write();
+            }
+            if !is_done || start >= index {
+                return (is_modified, false);
+            }
+        } else {
+            BitmapBlock::free(addr);
+            node.set(i, 0);
+            is_modified = true;
+            *budget -= 1;
+        }
+    }
+    (is_modified, true)
+}
+
+#[test_case]
+fn test_index_block() {
+    use super::{File, FileIO, SeekFrom};
+    use alloc::vec;
+
+    super::mount_mem();
+    super::format_mem();
+
+    // Write enough data to grow the tree of the file to a depth of 2
+    let n = 200 * super::BLOCK_SIZE + 42;
+    let input: vec::Vec<u8> = (0..n).map(|i| i as u8).collect();
+    let mut file = File::create("/test").unwrap();
+    assert_eq!(file.write(&input), Ok(n));
+    assert_eq!(IndexBlock::read_root(file.addr()).depth(), 2);
+
+    let mut file = File::open("/test").unwrap();
+    let mut output = vec![0; n];
+    assert_eq!(file.read(&mut output), Ok(n));
+    assert_eq!(input, output);
+
+    // Read from the middle of the file
+    let mut output = vec![0; 10];
+    assert!(file.seek(SeekFrom::Start(150 * super::BLOCK_SIZE as u32 + 5)).is_ok());
+    assert_eq!(file.read(&mut output), Ok(10));
+    assert_eq!(output[..], input[(150 * super::BLOCK_SIZE + 5)..(150 * super::BLOCK_SIZE + 15)]);
+
+    // Truncate the file and check that its blocks have been freed
+    let mut file = File::open("/test").unwrap();
+    assert_eq!(file.write(b"Hello"), Ok(5));
+    assert_eq!(super::check(false), Ok(vec::Vec::new()));
+
+    super::dismount();
+}
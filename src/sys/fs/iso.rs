@@ -0,0 +1,367 @@
+use super::{FileInfo, FileIO, FileType};
+use super::block_device::{BlockDevice, BlockDeviceIO};
+use super::mount;
+use super::vfs::{self, FileSystem, Node};
+use crate::sys;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+// ISO 9660 filesystem (read-only)
+//
+// The first 16 sectors of a disc are unused and followed by a list of volume
+// descriptors ending with a terminator. The primary volume descriptor holds
+// the dir record of the root dir, and a supplementary volume descriptor with
+// a Joliet escape sequence holds the root of another tree of dir records
+// where the names are in UCS-2. The content of a file or a dir is stored in
+// a single extent of consecutive sectors.
+//
+// Dir record structure:
+// 0 => length of the record
+// 2..6 => first sector of the extent
+// 10..14 => size of the extent
+// 18..25 => recording date and time
+// 25 => flags
+// 32 => length of the name
+// 33.. => name
+//
+// The numbers are stored in both byte orders and only the little-endian half
+// is read. A dir record doesn't cross a sector boundary, so the end of each
+// sector of a dir can be padded with zeros.
+
+const SECTOR_SIZE: usize = 2048;
+const DESCRIPTORS_ADDR: u32 = 16;
+const MAX_DESCRIPTORS: u32 = 64;
+
+const TYPE_PRIMARY: u8 = 1;
+const TYPE_SUPPLEMENTARY: u8 = 2;
+const TYPE_TERMINATOR: u8 = 255;
+
+const FLAG_DIR: u8 = 0x02;
+
+#[derive(Debug, Clone)]
+pub struct Iso9660 {
+    dev: Arc<BlockDevice>,
+    root: IsoEntry,
+    joliet: bool,
+}
+
+impl Iso9660 {
+    // Read the volume descriptors of a device, which is shared with the mount
+    // table once the filesystem is mounted.
+    pub fn open(dev: Arc<BlockDevice>) -> Option<Self> {
+        if dev.block_size() != SECTOR_SIZE {
+            return None;
+        }
+        let mut buf = vec![0; SECTOR_SIZE];
+        let mut root = None;
+        let mut joliet = false;
+        for addr in DESCRIPTORS_ADDR..(DESCRIPTORS_ADDR + MAX_DESCRIPTORS) {
+            dev.read(addr, &mut buf).ok()?;
+            if &buf[1..6] != b"CD001" {
+                return None;
+            }
+            match buf[0] {
+                TYPE_PRIMARY if root.is_none() => {
+                    root = IsoEntry::parse(&buf[156..190], false);
+                }
+                TYPE_SUPPLEMENTARY if is_joliet(&buf[88..91]) => {
+                    root = IsoEntry::parse(&buf[156..190], true);
+                    joliet = root.is_some();
+                }
+                TYPE_TERMINATOR => break,
+                _ => {}
+            }
+        }
+        let mut root = root?;
+        root.name = String::new();
+        Some(Self { dev, root, joliet })
+    }
+
+    fn entries(&self, dir: &IsoEntry) -> Vec<IsoEntry> {
+        let mut res = Vec::new();
+        let size = dir.size as usize;
+        let mut buf = vec![0; ((size + SECTOR_SIZE - 1) / SECTOR_SIZE) * SECTOR_SIZE];
+        if self.dev.read_blocks(dir.addr, &mut buf).is_err() {
+            return res;
+        }
+        let mut i = 0;
+        while i < size {
+            let len = buf[i] as usize;
+            if len == 0 { // Padding until the next sector
+                i = (i / SECTOR_SIZE + 1) * SECTOR_SIZE;
+                continue;
+            }
+            let raw = &buf[i..(i + len).min(size)];
+            let is_special = raw.len() > 33 && raw[32] == 1 && raw[33] <= 1; // "." and ".."
+            if !is_special {
+                if let Some(entry) = IsoEntry::parse(raw, self.joliet) {
+                    res.push(entry);
+                }
+            }
+            i += len;
+        }
+        res
+    }
+
+    // Find the entry of an absolute path, where the names are compared
+    // without case because they are uppercase on discs without Joliet names.
+    fn find(&self, path: &str) -> Option<IsoEntry> {
+        let (_, path) = mount::resolve(path)?;
+        let mut entry = self.root.clone();
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            if !entry.is_dir {
+                return None;
+            }
+            entry = self.entries(&entry).into_iter().find(|e| e.name.eq_ignore_ascii_case(name))?;
+        }
+        Some(entry)
+    }
+
+    fn read_at(&self, entry: &IsoEntry, offset: usize, buf: &mut [u8]) -> Result<usize, ()> {
+        let size = entry.size as usize;
+        if offset >= size || buf.is_empty() {
+            return Ok(0);
+        }
+        let n = core::cmp::min(buf.len(), size - offset);
+        let first = offset / SECTOR_SIZE;
+        let last = (offset + n - 1) / SECTOR_SIZE;
+        let mut data = vec![0; (last - first + 1) * SECTOR_SIZE];
+        self.dev.read_blocks(entry.addr + first as u32, &mut data)?;
+        let i = offset % SECTOR_SIZE;
+        buf[0..n].copy_from_slice(&data[i..(i + n)]);
+        Ok(n)
+    }
+}
+
+impl FileSystem for Iso9660 {
+    fn name(&self) -> &'static str {
+        "iso9660"
+    }
+
+    fn lookup(&self, path: &str, kind: FileType) -> Option<Box<dyn Node>> {
+        let entry = self.find(path)?;
+        match kind {
+            FileType::Dir if entry.is_dir => {
+                Some(Box::new(IsoDir { fs: self.clone(), entry, entries: None, entry_index: 0 }))
+            }
+            FileType::File if !entry.is_dir => {
+                Some(Box::new(IsoFile { fs: self.clone(), entry, offset: 0 }))
+            }
+            _ => None,
+        }
+    }
+
+    fn create(&self, _path: &str, _kind: FileType) -> Option<Box<dyn Node>> {
+        None
+    }
+
+    fn unlink(&self, _path: &str) -> Result<(), ()> {
+        Err(())
+    }
+
+    fn read_dir(&self, path: &str) -> Option<Vec<FileInfo>> {
+        let dir = self.find(path)?;
+        if dir.is_dir {
+            Some(self.entries(&dir).iter().map(|e| e.info()).collect())
+        } else {
+            None
+        }
+    }
+
+    fn stat(&self, path: &str) -> Option<FileInfo> {
+        self.find(path).map(|e| e.info())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IsoEntry {
+    name: String,
+    is_dir: bool,
+    addr: u32,
+    size: u32,
+    time: u64,
+}
+
+impl IsoEntry {
+    fn parse(raw: &[u8], joliet: bool) -> Option<Self> {
+        let len = *raw.first()? as usize;
+        if len < 34 || raw.len() < len {
+            return None;
+        }
+        let name_len = raw[32] as usize;
+        if 33 + name_len > len {
+            return None;
+        }
+        let addr = read_u32(raw, 2);
+        let size = read_u32(raw, 10);
+        let is_dir = raw[25] & FLAG_DIR != 0;
+        let time = timestamp(&raw[18..25]);
+        let name = parse_name(&raw[33..(33 + name_len)], joliet);
+        Some(Self { name, is_dir, addr, size, time })
+    }
+
+    fn info(&self) -> FileInfo {
+        let kind = if self.is_dir { FileType::Dir } else { FileType::File };
+        let mode = if self.is_dir { 0o555 } else { 0o444 };
+        let size = if self.is_dir { 0 } else { self.size };
+        FileInfo::with(kind, &self.name, size, mode, self.time)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IsoFile {
+    fs: Iso9660,
+    entry: IsoEntry,
+    offset: usize,
+}
+
+impl FileIO for IsoFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let bytes = self.fs.read_at(&self.entry, self.offset, buf)?;
+        self.offset += bytes;
+        Ok(bytes)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+}
+
+impl Node for IsoFile {
+    fn read_at(&mut self, offset: usize, buf: &mut [u8]) -> Result<usize, ()> {
+        self.fs.read_at(&self.entry, offset, buf)
+    }
+
+    fn box_clone(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IsoDir {
+    fs: Iso9660,
+    entry: IsoEntry,
+    entries: Option<Vec<IsoEntry>>, // Read when the first entry is needed
+    entry_index: usize,
+}
+
+impl IsoDir {
+    fn entries(&mut self) -> &[IsoEntry] {
+        if self.entries.is_none() {
+            self.entries = Some(self.fs.entries(&self.entry));
+        }
+        self.entries.as_deref().unwrap()
+    }
+}
+
+impl FileIO for IsoDir {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let mut i = self.entry_index;
+        let entries = self.entries();
+        let res = vfs::read_entries(buf, &mut i, |i| {
+            let info = entries.get(*i).map(|entry| entry.info());
+            *i += 1;
+            Ok(info)
+        });
+        self.entry_index = i;
+        res
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+}
+
+impl Node for IsoDir {
+    fn next_entry(&mut self) -> Result<Option<FileInfo>, ()> {
+        let i = self.entry_index;
+        let info = self.entries().get(i).map(|entry| entry.info());
+        if info.is_some() {
+            self.entry_index += 1;
+        }
+        Ok(info)
+    }
+
+    fn box_clone(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+}
+
+// The escape sequences of the UCS-2 levels 1, 2 and 3 of Joliet
+fn is_joliet(escape: &[u8]) -> bool {
+    matches!(escape, b"%/@" | b"%/C" | b"%/E")
+}
+
+// Remove the version number of a file name and the trailing dot of a name
+// without extension, and show the uppercase names of a disc without Joliet
+// names in lowercase.
+fn parse_name(raw: &[u8], joliet: bool) -> String {
+    let mut name = if joliet {
+        let chars: Vec<u16> = raw.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+        String::from_utf16_lossy(&chars)
+    } else {
+        String::from_utf8_lossy(raw).to_lowercase()
+    };
+    if let Some(i) = name.find(';') {
+        name.truncate(i);
+    }
+    if name.ends_with('.') {
+        name.pop();
+    }
+    name
+}
+
+// Convert the recording date and time of a record into a Unix timestamp
+fn timestamp(raw: &[u8]) -> u64 {
+    let year = 1900 + raw[0] as u64;
+    let month = raw[1] as u64;
+    let day = raw[2] as u64;
+    if year < 1970 || month < 1 || month > 12 || day < 1 {
+        return 0;
+    }
+    let hour = raw[3] as u64;
+    let minute = raw[4] as u64;
+    let second = raw[5] as u64;
+    let offset = (raw[6] as i8) as i64 * 15 * 60; // GMT offset in 15 min intervals
+    let time = sys::clock::timestamp(year, month, day, hour, minute, second) as i64;
+    (time - offset).max(0) as u64
+}
+
+fn read_u32(buf: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes(buf[i..(i + 4)].try_into().unwrap())
+}
+
+#[test_case]
+fn test_iso_entry() {
+    let name = b"HELLO.TXT;1";
+    let mut raw = [0; 33 + 11];
+    raw[0] = raw.len() as u8;
+    raw[2..6].copy_from_slice(&20u32.to_le_bytes());
+    raw[10..14].copy_from_slice(&1024u32.to_le_bytes());
+    raw[18..25].copy_from_slice(&[122, 6, 15, 12, 30, 10, 0]); // 2022-06-15 12:30:10
+    raw[32] = name.len() as u8;
+    raw[33..].copy_from_slice(name);
+    let entry = IsoEntry::parse(&raw, false).unwrap();
+    assert_eq!(entry.name, "hello.txt");
+    assert_eq!(entry.addr, 20);
+    assert_eq!(entry.size, 1024);
+    assert_eq!(entry.time, 1655296210);
+    assert!(!entry.is_dir);
+
+    raw[25] = FLAG_DIR;
+    raw[24] = 8; // GMT+2
+    assert!(IsoEntry::parse(&raw, false).unwrap().is_dir);
+    assert_eq!(IsoEntry::parse(&raw, false).unwrap().time, 1655296210 - 7200);
+    assert!(IsoEntry::parse(&raw[0..40], false).is_none());
+
+    let name: Vec<u8> = "Été.md".encode_utf16().flat_map(|c| c.to_be_bytes()).collect();
+    assert_eq!(parse_name(&name, true), "Été.md");
+    assert_eq!(parse_name(b"README.;1", false), "readme");
+    assert!(is_joliet(b"%/E"));
+    assert!(!is_joliet(b"%/F"));
+}
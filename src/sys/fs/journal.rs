@@ -0,0 +1,238 @@
+use super::block_device::BlockDeviceIO;
+use super::mount;
+use super::super_block::{journal_addr, SuperBlock};
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+// The journal area is reserved after the superblock since version 2 of MFS.
+// Its first block is a header with the addresses of the blocks of the last
+// committed transaction, followed by a copy of those blocks.
+//
+// Header structure:
+// 0..4 => number of blocks in the transaction (0 if empty)
+// 4..n => address of each block
+pub const JOURNAL_SIZE: u32 = 64;
+
+const MAX_BLOCKS: usize = (JOURNAL_SIZE - 1) as usize;
+
+type Buffer = [u8; super::BLOCK_SIZE];
+
+// A transaction is made on the device selected when it begins, which must
+// stay selected until it is committed.
+struct Transaction {
+    depth: usize,
+    dev: usize,
+    is_enabled: bool,
+    blocks: BTreeMap<u32, Buffer>,
+}
+
+lazy_static! {
+    static ref TRANSACTION: Mutex<Transaction> = Mutex::new(Transaction {
+        depth: 0,
+        dev: 0,
+        is_enabled: false,
+        blocks: BTreeMap::new(),
+    });
+}
+
+// Start a transaction, or a nested transaction that will be committed with
+// the outermost one. Block writes are kept in memory until then.
+pub fn begin() {
+    let is_enabled = SuperBlock::read().has_journal();
+    let mut tx = TRANSACTION.lock();
+    if tx.depth == 0 {
+        tx.dev = mount::current();
+        tx.is_enabled = is_enabled;
+    }
+    tx.depth += 1;
+}
+
+// Write the blocks of the transaction to the journal, then to their final
+// location, and finally clear the journal. A transaction that doesn't fit in
+// the journal is discarded without writing any of its blocks.
+pub fn commit() -> Result<(), ()> {
+    let (dev, blocks) = {
+        let mut tx = TRANSACTION.lock();
+        debug_assert!(tx.depth > 0);
+        tx.depth -= 1;
+        if tx.depth > 0 || tx.blocks.is_empty() {
+            return Ok(());
+        }
+        (tx.dev, core::mem::take(&mut tx.blocks))
+    };
+    if blocks.len() > MAX_BLOCKS {
+        debug!("MFS: transaction of {} blocks discarded", blocks.len());
+        return Err(());
+    }
+    let _dev = mount::select(dev);
+    flush(&blocks);
+    Ok(())
+}
+
+// Run a function in a transaction, and return an error if it could not be
+// committed.
+pub fn transaction<T, F: FnOnce() -> T>(f: F) -> Result<T, ()> {
+    begin();
+    let res = f();
+    commit()?;
+    Ok(res)
+}
+
+// Check if a transaction has begun and not yet been committed
+pub fn is_active() -> bool {
+    TRANSACTION.lock().depth > 0
+}
+
+// Return the pending version of a block in the current transaction
+pub fn read(addr: u32) -> Option<Buffer> {
+    let tx = TRANSACTION.lock();
+    if tx.depth > 0 && tx.dev == mount::current() {
+        tx.blocks.get(&addr).copied()
+    } else {
+        None
+    }
+}
+
+// Keep a block write in the current transaction, or return false if the
+// block must be written directly to the device.
+pub fn write(addr: u32, buf: &[u8]) -> bool {
+    let mut tx = TRANSACTION.lock();
+    if tx.depth == 0 || !tx.is_enabled {
+        return false;
+    }
+    assert_eq!(tx.dev, mount::current(), "MFS: write outside of the device of the transaction");
+    let mut block = [0; super::BLOCK_SIZE];
+    block.copy_from_slice(buf);
+    tx.blocks.insert(addr, block);
+    true
+}
+
+// Apply the last committed transaction if it was interrupted
+pub fn replay() {
+    if !SuperBlock::read().has_journal() {
+        return;
+    }
+    let mut header = [0; super::BLOCK_SIZE];
+    read_block(journal_addr(), &mut header);
+    let n = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+    if n == 0 {
+        return;
+    }
+    if n <= MAX_BLOCKS {
+        let mut buf = [0; super::BLOCK_SIZE];
+        for i in 0..n {
+            let j = 4 + i * 4;
+            let addr = u32::from_be_bytes(header[j..(j + 4)].try_into().unwrap());
+            read_block(journal_addr() + 1 + i as u32, &mut buf);
+            write_block(addr, &buf);
+        }
+        log!("MFS Journal replayed {} blocks\n", n);
+    }
+    clear();
+}
+
+pub fn clear() {
+    write_block(journal_addr(), &[0; super::BLOCK_SIZE]);
+}
+
+fn flush(blocks: &BTreeMap<u32, Buffer>) {
+    // Write the blocks to the journal before committing them with the header
+    let mut header = [0; super::BLOCK_SIZE];
+    header[0..4].clone_from_slice(&(blocks.len() as u32).to_be_bytes());
+    let mut copies = Vec::with_capacity(blocks.len() * super::BLOCK_SIZE);
+    for (i, (addr, buf)) in blocks.iter().enumerate() {
+        let j = 4 + i * 4;
+        header[j..(j + 4)].clone_from_slice(&addr.to_be_bytes());
+        copies.extend_from_slice(buf);
+    }
+    if !copies.is_empty() {
+        write_blocks(journal_addr() + 1, &copies);
+    }
+    write_block(journal_addr(), &header);
+
+    for (addr, buf) in blocks.iter() {
+        write_block(*addr, buf);
+    }
+    clear();
+}
+
+fn read_block(addr: u32, buf: &mut [u8]) {
+    if let Some(Err(())) = mount::with_device(|dev| dev.read(addr, buf)) {
+        debug!("MFS: could not read block {:#x}", addr);
+    }
+}
+
+fn write_block(addr: u32, buf: &[u8]) {
+    if let Some(Err(())) = mount::with_device(|dev| dev.write(addr, buf)) {
+        debug!("MFS: could not write block {:#x}", addr);
+    }
+}
+
+fn write_blocks(addr: u32, buf: &[u8]) {
+    if let Some(Err(())) = mount::with_device(|dev| dev.write_blocks(addr, buf)) {
+        debug!("MFS: could not write blocks from {:#x}", addr);
+    }
+}
+
+#[test_case]
+fn test_journal_replay() {
+    super::mount_mem();
+    super::format_mem();
+
+    // Simulate a crash after committing a transaction to the journal
+    let addr = SuperBlock::read().data_area() + 1;
+    let mut header = [0; super::BLOCK_SIZE];
+    header[0..4].clone_from_slice(&1u32.to_be_bytes());
+    header[4..8].clone_from_slice(&addr.to_be_bytes());
+    write_block(journal_addr() + 1, &[0xFF; super::BLOCK_SIZE]);
+    write_block(journal_addr(), &header);
+
+    let mut buf = [0; super::BLOCK_SIZE];
+    read_block(addr, &mut buf);
+    assert_eq!(buf[0], 0);
+
+    replay();
+    read_block(addr, &mut buf);
+    assert_eq!(buf[0], 0xFF);
+    read_block(journal_addr(), &mut buf);
+    assert!(buf[0..4].iter().all(|b| *b == 0));
+
+    super::dismount();
+}
+
+#[test_case]
+fn test_journal_transaction() {
+    super::mount_mem();
+    super::format_mem();
+
+    let addr = SuperBlock::read().data_area() + 1;
+    begin();
+    assert!(write(addr, &[0xFF; super::BLOCK_SIZE]));
+    assert_eq!(read(addr).map(|buf| buf[0]), Some(0xFF));
+    let mut buf = [0; super::BLOCK_SIZE];
+    read_block(addr, &mut buf);
+    assert_eq!(buf[0], 0);
+    assert_eq!(commit(), Ok(()));
+
+    assert_eq!(read(addr), None);
+    read_block(addr, &mut buf);
+    assert_eq!(buf[0], 0xFF);
+
+    // A transaction larger than the journal is not partially committed
+    let res = transaction(|| {
+        for i in 0..(MAX_BLOCKS as u32 + 1) {
+            assert!(write(addr + i, &[0xAA; super::BLOCK_SIZE]));
+        }
+    });
+    assert_eq!(res, Err(()));
+    read_block(addr, &mut buf);
+    assert_eq!(buf[0], 0xFF);
+    read_block(journal_addr(), &mut buf);
+    assert!(buf[0..4].iter().all(|b| *b == 0));
+
+    super::dismount();
+}
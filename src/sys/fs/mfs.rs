@@ -0,0 +1,95 @@
+use super::{realpath, Device, Dir, File, FileInfo, FileType};
+use super::dir_entry::DirEntry;
+use super::mount;
+use super::vfs::{FileSystem, Node};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+// MOROS FileSystem
+//
+// The dirs and files of MFS already resolve absolute paths through the mount
+// table to select their device, so its implementation of the VFS is only a
+// dispatch on the kind of node.
+#[derive(Debug, Clone, Copy)]
+pub struct Mfs;
+
+impl FileSystem for Mfs {
+    fn name(&self) -> &'static str {
+        "mfs"
+    }
+
+    fn is_mfs(&self) -> bool {
+        true
+    }
+
+    fn lookup(&self, path: &str, kind: FileType) -> Option<Box<dyn Node>> {
+        match kind {
+            FileType::Dir => Dir::open(path).map(boxed),
+            FileType::File => File::open(path).map(boxed),
+            FileType::Device => Device::open(path).map(boxed),
+            FileType::Link => File::open_link(path).map(boxed),
+        }
+    }
+
+    fn create(&self, path: &str, kind: FileType) -> Option<Box<dyn Node>> {
+        match kind {
+            FileType::Dir => Dir::create(path).map(boxed),
+            FileType::File => File::create(path).map(boxed),
+            FileType::Device => Device::create(path).map(boxed),
+            FileType::Link => File::create_link(path).map(boxed),
+        }
+    }
+
+    fn unlink(&self, path: &str) -> Result<(), ()> {
+        if let Some(entry) = DirEntry::open_link(path) {
+            if entry.is_file() || entry.is_link() {
+                return File::delete(path);
+            } else if entry.is_dir() {
+                return Dir::delete(path);
+            }
+        }
+        Err(())
+    }
+
+    fn read_dir(&self, path: &str) -> Option<Vec<FileInfo>> {
+        Dir::open(path).map(|dir| dir.entries().map(|entry| entry.info()).collect())
+    }
+
+    fn stat(&self, path: &str) -> Option<FileInfo> {
+        let path = realpath(path);
+        if mount::is_mount_point(&path) {
+            let (_dev, _) = mount::select_path(&path)?;
+            return Some(FileInfo::root());
+        }
+        DirEntry::open(&path).map(|entry| entry.info())
+    }
+}
+
+fn boxed<T: Node + 'static>(node: T) -> Box<dyn Node> {
+    Box::new(node)
+}
+
+#[test_case]
+fn test_mfs() {
+    super::mount_mem();
+    super::format_mem();
+    let fs = Mfs;
+    assert!(fs.create("/test", FileType::File).is_some());
+    assert!(fs.create("/tmp", FileType::Dir).is_some());
+    assert!(fs.lookup("/test", FileType::Dir).is_none());
+
+    let mut file = fs.lookup("/test", FileType::File).unwrap();
+    assert_eq!(file.write_at(0, b"hello, world"), Ok(12));
+    assert_eq!(file.write_at(7, b"mfs!!"), Ok(5));
+    let mut buf = [0; 5];
+    assert_eq!(file.read_at(7, &mut buf), Ok(5));
+    assert_eq!(&buf, b"mfs!!");
+    assert_eq!(fs.stat("/test").map(|info| info.size()), Some(12));
+
+    let names: Vec<_> = fs.read_dir("/").unwrap().iter().map(|info| info.name()).collect();
+    assert_eq!(names, ["test", "tmp"]);
+    assert!(fs.unlink("/test").is_ok());
+    assert!(fs.stat("/test").is_none());
+    super::dismount();
+}
@@ -0,0 +1,328 @@
+use super::bitmap_block::{self, BitmapBlock};
+use super::block::{Block, LinkedBlock};
+use super::dir::Dir;
+use super::dir_entry::{DirEntry, EntryFormat};
+use super::fsck;
+use super::index_block;
+use super::journal;
+use super::super_block::SuperBlock;
+
+use alloc::collections::btree_set::BTreeSet;
+use alloc::vec::Vec;
+
+// The versions of MFS changing the layout of the dirs or of the files are
+// migrated by writing a new copy of the tree of dirs into the blocks that are
+// not used by the current tree, without relying on its bitmap. The copy is
+// committed by writing the first block of the new root dir with the version
+// of the superblock, so an interrupted migration leaves the disk in its
+// previous version and can be restarted from the beginning.
+struct Stager {
+    used: BTreeSet<u32>,
+    next: u32,
+    block_count: u32,
+    format: EntryFormat,
+    is_indexed: bool,
+    copy_files: bool,
+}
+
+impl Stager {
+    // Return a block that is neither used by the current tree nor already
+    // staged, after the root dir of the new version.
+    fn alloc(&mut self) -> Option<u32> {
+        while self.next < self.block_count {
+            let addr = self.next;
+            self.next += 1;
+            if self.used.insert(addr) {
+                return Some(addr);
+            }
+        }
+        None
+    }
+
+    // Copy the entries of a dir and of its subdirs in the new format, with
+    // the first block of the dir at the given address, and return this
+    // block before writing it with the size of the dir.
+    fn copy_dir(&mut self, dir: Dir, addr: u32) -> Result<(LinkedBlock, u32), ()> {
+        let mut entries: Vec<DirEntry> = dir.entries().collect();
+        for entry in entries.iter_mut() {
+            if entry.is_dir() {
+                let dir_addr = self.alloc().ok_or(())?;
+                let (block, size) = self.copy_dir(Dir::from(entry.clone()), dir_addr)?;
+                block.write();
+                entry.set_addr(dir_addr);
+                entry.set_size(size);
+            } else if self.copy_files {
+                let file_addr = self.alloc().ok_or(())?;
+                self.copy_file(entry, file_addr)?;
+                entry.set_addr(file_addr);
+            }
+            entry.set_format(self.format);
+        }
+        let size = entries.iter().map(|e| e.len()).sum::<usize>() as u32;
+        Ok((self.write_entries(addr, &entries)?, size))
+    }
+
+    // Write the entries in a new chain of blocks, except its first block
+    fn write_entries(&mut self, addr: u32, entries: &[DirEntry]) -> Result<LinkedBlock, ()> {
+        let mut first = None;
+        let mut block = LinkedBlock::new(addr);
+        let mut i = 0;
+        for entry in entries {
+            let bytes = entry.as_bytes();
+            if i + bytes.len() > block.len() {
+                let next_addr = self.alloc().ok_or(())?;
+                block.set_next_addr(next_addr);
+                let prev = core::mem::replace(&mut block, LinkedBlock::new(next_addr));
+                if prev.addr() == addr {
+                    first = Some(prev);
+                } else {
+                    prev.write();
+                }
+                i = 0;
+            }
+            block.data_mut()[i..(i + bytes.len())].clone_from_slice(&bytes);
+            i += bytes.len();
+        }
+        match first {
+            Some(first) => {
+                block.write();
+                Ok(first)
+            }
+            None => Ok(block),
+        }
+    }
+
+    // Copy the contents of a file into new data blocks indexed by a tree
+    // whose root block is at the given address.
+    fn copy_file(&mut self, entry: &DirEntry, addr: u32) -> Result<(), ()> {
+        let bs = super::BLOCK_SIZE;
+        let size = entry.size() as usize;
+        let mut data = Vec::with_capacity(size + bs);
+        if self.is_indexed {
+            for i in 0..((size + bs - 1) / bs) {
+                match index_block::data_addr(entry.addr(), i as u32, false) {
+                    Some(data_addr) => data.extend_from_slice(Block::read(data_addr).data()),
+                    None => data.resize(data.len() + bs, 0),
+                }
+            }
+        } else {
+            let mut block = LinkedBlock::read(entry.addr());
+            loop {
+                data.extend_from_slice(block.data());
+                if data.len() >= size {
+                    break;
+                }
+                match block.next() {
+                    Some(next_block) => block = next_block,
+                    None => break,
+                }
+            }
+        }
+        data.resize(size, 0);
+
+        let mut addrs = Vec::with_capacity((size + bs - 1) / bs);
+        for chunk in data.chunks(bs) {
+            let data_addr = self.alloc().ok_or(())?;
+            let mut block = Block::new(data_addr);
+            block.data_mut()[0..chunk.len()].clone_from_slice(chunk);
+            block.write();
+            addrs.push(data_addr);
+        }
+        index_block::build(addr, &addrs, &mut || self.alloc()).ok_or(())
+    }
+}
+
+// Copy the tree of dirs of the selected device in the layout of the given
+// version and commit it.
+pub fn copy_tree(version: u8) -> Result<(), ()> {
+    let sb = SuperBlock::read();
+    let mut new_sb = SuperBlock::read();
+    new_sb.set_version(version);
+    let root_addr = new_sb.data_area();
+    let mut stager = Stager {
+        used: fsck::used_blocks(),
+        next: root_addr + 1,
+        block_count: sb.block_count(),
+        format: if new_sb.has_long_entries() { EntryFormat::Long } else { EntryFormat::Short },
+        is_indexed: sb.has_indexed_files(),
+        copy_files: sb.has_indexed_files() != new_sb.has_indexed_files(),
+    };
+
+    // The data area starts after the journal since version 2, and the new
+    // root dir could be at the address of a block of the current tree.
+    let is_used = sb.data_area() != root_addr && stager.used.contains(&root_addr);
+    if is_used && !relocate(Dir::root(), root_addr, &mut stager)? {
+        return Err(());
+    }
+
+    let (root, _) = stager.copy_dir(Dir::root(), root_addr)?;
+    journal::transaction(|| {
+        root.write();
+        new_sb.write();
+    })?;
+    if !sb.has_journal() {
+        // The header of the new journal was the first block of the old
+        // bitmap, whose first bit is always set for the root dir, making it
+        // too large to be replayed if this write is interrupted.
+        journal::clear();
+    }
+
+    // The bitmap of the new tree is rebuilt again at the end of the
+    // migration if this step is interrupted.
+    new_sb.alloc_count = bitmap_block::rebuild(&new_sb, &fsck::used_blocks());
+    new_sb.write();
+    Ok(())
+}
+
+// Move a block of the current tree of linked blocks to a staged block,
+// updating the reference to it with a single write so that the tree stays
+// valid in its current version.
+fn relocate(dir: Dir, addr: u32, stager: &mut Stager) -> Result<bool, ()> {
+    for entry in dir.entries() {
+        if entry.addr() == addr {
+            let new_addr = move_block(addr, stager)?;
+            dir.update_entry_with(&entry.name(), |e| e.set_addr(new_addr))?;
+            return Ok(true);
+        }
+        let is_found = if entry.is_dir() {
+            relocate(Dir::from(entry.clone()), addr, stager)?
+        } else {
+            relocate_next(entry.addr(), addr, stager)?
+        };
+        if is_found {
+            return Ok(true);
+        }
+    }
+    relocate_next(dir.addr(), addr, stager)
+}
+
+// Move the block following another block of a chain if it is the given one
+fn relocate_next(first_addr: u32, addr: u32, stager: &mut Stager) -> Result<bool, ()> {
+    let mut block = LinkedBlock::read(first_addr);
+    loop {
+        if block.next_addr() == addr {
+            let new_addr = move_block(addr, stager)?;
+            block.set_next_addr(new_addr);
+            block.write();
+            return Ok(true);
+        }
+        match block.next() {
+            Some(next_block) => block = next_block,
+            None => return Ok(false),
+        }
+    }
+}
+
+// Copy a block to a staged block allocated in the current bitmap
+fn move_block(addr: u32, stager: &mut Stager) -> Result<u32, ()> {
+    let new_addr = stager.alloc().ok_or(())?;
+    let mut block = Block::new(new_addr);
+    block.data_mut().clone_from_slice(Block::read(addr).data());
+    block.write();
+    BitmapBlock::alloc(new_addr);
+    Ok(new_addr)
+}
+
+#[cfg(test)]
+fn write_files(n: usize) {
+    use super::{File, FileIO};
+    assert!(Dir::create("/tmp").is_some());
+    for i in 0..n {
+        let mut file = File::create(&alloc::format!("/tmp/file-{}", i)).unwrap();
+        let buf: Vec<u8> = (0..(i * 100)).map(|j| (i + j) as u8).collect();
+        assert_eq!(file.write(&buf), Ok(buf.len()));
+    }
+}
+
+#[cfg(test)]
+fn check_files(n: usize) {
+    use super::{File, FileIO};
+    assert_eq!(Dir::open("/tmp").unwrap().entries().count(), n);
+    for i in 0..n {
+        let mut file = File::open(&alloc::format!("/tmp/file-{}", i)).unwrap();
+        let mut buf = alloc::vec![0; i * 100 + 1];
+        assert_eq!(file.read(&mut buf), Ok(i * 100));
+        assert!(buf[..(i * 100)].iter().enumerate().all(|(j, b)| *b == (i + j) as u8));
+    }
+    assert_eq!(super::check(false), Ok(Vec::new()));
+}
+
+#[test_case]
+fn test_migrate_v1() {
+    super::mount_mem();
+    super::format_mem();
+
+    // Create a filesystem without journal with a root dir at the beginning
+    // of the journal area of the next versions
+    let mut sb = SuperBlock::read();
+    sb.set_version(1);
+    sb.alloc_count = 0;
+    sb.write();
+    let root = Dir::root();
+    Block::new(root.addr()).write();
+    BitmapBlock::alloc(root.addr());
+    write_files(30);
+
+    // The root dir of the next versions is used by a file
+    let mut sb = SuperBlock::read();
+    sb.set_version(3);
+    assert!(fsck::used_blocks().contains(&sb.data_area()));
+
+    assert!(super::migrate().is_ok());
+    assert_eq!(SuperBlock::read().version(), super::VERSION);
+    check_files(30);
+    super::dismount();
+}
+
+#[test_case]
+fn test_migrate_v2() {
+    super::mount_mem();
+    super::format_mem();
+
+    // Create linked files in the short format of version 2
+    let mut sb = SuperBlock::read();
+    sb.set_version(2);
+    sb.write();
+    write_files(30);
+
+    assert!(super::migrate().is_ok());
+    assert_eq!(SuperBlock::read().version(), super::VERSION);
+    assert!(super::chmod("/tmp/file-0", 0o600).is_ok());
+    check_files(30);
+    super::dismount();
+}
+
+#[test_case]
+fn test_migrate_v3() {
+    super::mount_mem();
+    super::format_mem();
+
+    // Create a few entries in the short format of version 3
+    let mut sb = SuperBlock::read();
+    sb.set_version(3);
+    sb.write();
+    write_files(30);
+    assert!(super::chmod("/tmp/file-0", 0o600).is_err());
+
+    // A migration that cannot be committed leaves the disk unchanged
+    let mut stager = Stager {
+        used: fsck::used_blocks(),
+        next: sb.data_area() + 1,
+        block_count: sb.data_area() + 4,
+        format: EntryFormat::Long,
+        is_indexed: true,
+        copy_files: false,
+    };
+    assert!(stager.copy_dir(Dir::root(), sb.data_area()).is_err());
+    assert_eq!(SuperBlock::read().version(), 3);
+    check_files(30);
+
+    assert!(super::migrate().is_ok());
+    assert_eq!(EntryFormat::current(), EntryFormat::Long);
+    assert!(super::chmod("/tmp/file-0", 0o600).is_ok());
+    assert!(super::chown("/tmp/file-0", 1000).is_ok());
+    let info = super::info("/tmp/file-0").unwrap();
+    assert_eq!((info.mode(), info.uid()), (0o600, 1000));
+    check_files(30);
+    super::dismount();
+}
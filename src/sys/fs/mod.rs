@@ -4,27 +4,54 @@ mod block_device;
 mod device;
 mod dir;
 mod dir_entry;
+mod fat;
 mod file;
+mod fsck;
+mod index_block;
+mod iso;
+mod journal;
+mod mfs;
+mod migration;
+mod mount;
+mod partition;
 mod read_dir;
+mod snapshot;
 mod super_block;
+mod vfs;
+mod watch;
 
 use crate::sys;
 
 pub use bitmap_block::BITMAP_SIZE;
-pub use device::{Device, DeviceType};
+pub use device::{device_type, ControlRequest, Device, DeviceType};
 pub use dir::Dir;
 pub use dir_entry::FileInfo;
 pub use file::{File, SeekFrom};
-pub use block_device::{format_ata, format_mem, is_mounted, mount_ata, mount_mem, dismount};
+pub use fat::Fat32;
+pub use fsck::{check, Issue};
+pub use iso::Iso9660;
+pub use mfs::Mfs;
+pub use partition::Partition;
+pub use vfs::{FileSystem, Node};
+pub use watch::{EventKind, Watcher};
+pub use block_device::{AhciBlockDevice, AtaBlockDevice, BlockDevice, BlockDeviceIO, PartitionBlockDevice, VirtioBlockDevice};
+pub use snapshot::{Error as SnapshotError, Header as SnapshotHeader};
+pub use block_device::{disks, format_disk, format_mem, is_mounted, mem_size, mount_device, mount_mem, mount_mem_disk, open_device, partitions, dismount};
+pub use block_device::{blocks_count, humanized_size, read_chunks, write_chunks};
 pub use crate::api::fs::{dirname, filename, realpath, FileIO};
 pub use crate::sys::ata::BLOCK_SIZE;
 
-use dir_entry::DirEntry;
+use dir_entry::{DirEntry, EntryFormat};
 use super_block::SuperBlock;
 
+use alloc::boxed::Box;
+use alloc::format;
 use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 
-pub const VERSION: u8 = 1;
+pub const VERSION: u8 = 6;
 
 #[derive(Clone, Copy)]
 #[repr(u8)]
@@ -34,6 +61,8 @@ pub enum OpenFlag {
     Create = 4,
     Dir    = 8,
     Device = 16,
+    Link   = 32,
+    Watch  = 64,
 }
 
 impl OpenFlag {
@@ -43,46 +72,194 @@ impl OpenFlag {
 }
 
 pub fn open(path: &str, flags: usize) -> Option<Resource> {
-    if OpenFlag::Dir.is_set(flags) {
-        let res = Dir::open(path);
-        if res.is_none() && OpenFlag::Create.is_set(flags) {
-            Dir::create(path)
-        } else {
-            res
-        }.map(Resource::Dir)
+    let path = realpath(path);
+    if OpenFlag::Watch.is_set(flags) {
+        // A path can be watched before its creation if its parent exists
+        info(&path).or_else(|| info(dirname(&path)))?;
+        return Some(Resource::new(Watcher::new(&path)));
+    }
+    let fs = mount::filesystem(&path)?;
+    let kind = if OpenFlag::Dir.is_set(flags) {
+        FileType::Dir
+    } else if OpenFlag::Link.is_set(flags) {
+        FileType::Link // The target of a symbolic link is stored as its contents
     } else if OpenFlag::Device.is_set(flags) {
-        let res = Device::open(path);
-        if res.is_none() && OpenFlag::Create.is_set(flags) {
-            Device::create(path)
-        } else {
-            res
-        }.map(Resource::Device)
+        FileType::Device
     } else {
-        let res = File::open(path);
-        if res.is_none() && OpenFlag::Create.is_set(flags) {
-            File::create(path)
-        } else {
-            res
-        }.map(Resource::File)
-    }
+        FileType::File
+    };
+    let res = fs.lookup(&path, kind);
+    if res.is_none() && OpenFlag::Create.is_set(flags) {
+        fs.create(&path, kind)
+    } else {
+        res
+    }.map(|node| Resource { node })
 }
 
 pub fn delete(path: &str) -> Result<(), ()> {
-    if let Some(info) = info(path) {
-        if info.is_file() {
-            return File::delete(path);
-        } else if info.is_dir() {
-            return Dir::delete(path);
-        }
+    let path = realpath(path);
+    if mount::is_mount_point(&path) {
+        return Err(());
+    }
+    // A symbolic link is deleted instead of its target
+    mount::filesystem(&path).ok_or(())?.unlink(&path)
+}
+
+pub fn read_dir(path: &str) -> Option<Vec<FileInfo>> {
+    let path = realpath(path);
+    mount::filesystem(&path)?.read_dir(&path)
+}
+
+// Create a hard link sharing the contents of a file
+pub fn link(source: &str, dest: &str) -> Result<(), ()> {
+    let entry = DirEntry::open(source).ok_or(())?;
+    let _dev = mount::select(entry.dir().dev());
+    if entry.is_dir() || !SuperBlock::read().has_shared_metadata() {
+        return Err(());
     }
-    Err(())
+    let pathname = realpath(dest);
+    let dirname = dirname(&pathname);
+    let filename = filename(&pathname);
+    let mut dir = Dir::open(dirname).ok_or(())?;
+    dir.create_hard_link(filename, &entry).map(|_| ()).ok_or(())
+}
+
+// Move a file or a dir to another path on the same MFS device
+pub fn rename(source: &str, dest: &str) -> Result<(), ()> {
+    let source = realpath(source);
+    let dest = realpath(dest);
+    if mount::is_mount_point(&source) || dest.starts_with(&format!("{}/", source)) {
+        return Err(());
+    }
+    let mut source_dir = Dir::open(dirname(&source)).ok_or(())?;
+    let mut dest_dir = Dir::open(dirname(&dest)).ok_or(())?;
+    source_dir.move_entry(filename(&source), &mut dest_dir, filename(&dest))
 }
 
 pub fn info(pathname: &str) -> Option<FileInfo> {
+    let pathname = realpath(pathname);
+    mount::filesystem(&pathname)?.stat(&pathname)
+}
+
+pub fn chmod(pathname: &str, mode: u16) -> Result<(), ()> {
+    update_metadata(pathname, true, |entry| entry.set_mode(mode))
+}
+
+pub fn chown(pathname: &str, uid: u16) -> Result<(), ()> {
+    update_metadata(pathname, true, |entry| entry.set_uid(uid))
+}
+
+// Set the access and modification times of a file
+pub fn touch(pathname: &str, time: u64) -> Result<(), ()> {
+    update_metadata(pathname, false, |entry| {
+        entry.set_atime(time);
+        entry.set_mtime(time);
+    })
+}
+
+fn update_metadata<F>(pathname: &str, is_long: bool, f: F) -> Result<(), ()> where F: FnOnce(&mut DirEntry) {
+    let pathname = realpath(pathname);
+    let dirname = dirname(&pathname);
+    let filename = filename(&pathname);
+    let dir = Dir::open(dirname).ok_or(())?;
+    let _dev = mount::select(dir.dev());
+    if filename.is_empty() || (is_long && EntryFormat::current() != EntryFormat::Long) {
+        return Err(()); // The metadata cannot be stored
+    }
+    dir.change_entry_with(filename, f)
+}
+
+// Migrate a filesystem to the current version. Versions 1 and 2 stored the
+// files in linked blocks, version 1 had no journal, and version 3 had short
+// dir entries, so a new copy of the tree of dirs is written and committed at
+// once for those versions. Version 5 only added links and version 6 rebuilds
+// the bitmap to use all of its bits and moves the metadata of hard links to
+// their root blocks. Each step can be restarted if it is interrupted.
+pub fn migrate() -> Result<(), ()> {
+    let (_dev, _) = mount::select_path("/").ok_or(())?;
+    if SuperBlock::read().version() < 3 {
+        migration::copy_tree(3)?;
+    }
+    if SuperBlock::read().version() == 3 {
+        migration::copy_tree(4)?;
+    }
+    let mut sb = SuperBlock::read();
+    if sb.version() == 4 {
+        sb.set_version(5);
+        sb.write();
+    }
+    if sb.version() == 5 {
+        // The bitmap is rebuilt from the blocks found in the tree of dirs
+        // before the version is changed.
+        sb.set_version(6);
+        sb.alloc_count = bitmap_block::rebuild(&sb, &fsck::used_blocks());
+        sb.write();
+    }
+    if sb.version() == VERSION {
+        // The hard links are migrated again in case of an interruption
+        Dir::root().migrate_links()
+    } else {
+        Err(())
+    }
+}
+
+// Mount a device on an existing dir, where the device is either "mem" for a
+// new RAM disk, or the path of a disk or of a partition with an MFS, FAT32 or
+// ISO 9660 filesystem.
+pub fn mount(dev: &str, pathname: &str) -> Result<(), ()> {
+    let pathname = realpath(pathname);
+    if pathname == "/" || Dir::open(&pathname).is_none() {
+        return Err(());
+    }
+    let fields: Vec<_> = dev.split('/').collect();
+    match fields[..] {
+        ["mem"] => {
+            mount_mem_disk(&pathname, mem_size())?;
+        }
+        _ => {
+            let blk = Arc::new(open_device(dev).ok_or(())?);
+            if SuperBlock::check(&blk) {
+                let _dev = mount::select(mount::mount(&pathname, blk, Arc::new(Mfs)));
+                journal::replay();
+            } else if let Some(fat) = Fat32::open(blk.clone()) {
+                mount::mount(&pathname, blk, Arc::new(fat));
+            } else if let Some(iso) = Iso9660::open(blk.clone()) {
+                mount::mount(&pathname, blk, Arc::new(iso));
+            } else {
+                return Err(());
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn umount(pathname: &str) -> Result<(), ()> {
+    let pathname = realpath(pathname);
     if pathname == "/" {
-        return Some(FileInfo::root());
+        return Err(());
     }
-    DirEntry::open(pathname).map(|e| e.info())
+    mount::umount(&pathname)
+}
+
+// Return the path, the device name and the filesystem name of each mount
+pub fn mounts() -> Vec<(String, String, String)> {
+    mount::list()
+}
+
+pub fn is_disk_mounted(name: &str) -> bool {
+    mount::is_disk_mounted(name)
+}
+
+pub fn snapshot<T: BlockDeviceIO>(dev: &T, out: &mut dyn FileIO, progress: impl FnMut(u32, u32)) -> Result<(), SnapshotError> {
+    snapshot::dump(dev, out, progress)
+}
+
+pub fn verify_snapshot(input: &mut dyn FileIO, progress: impl FnMut(u32, u32)) -> Result<SnapshotHeader, SnapshotError> {
+    snapshot::verify(input, progress)
+}
+
+pub fn restore_snapshot<T: BlockDeviceIO>(dev: &mut T, input: &mut dyn FileIO, progress: impl FnMut(u32, u32)) -> Result<SnapshotHeader, SnapshotError> {
+    snapshot::restore(dev, input, progress)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -90,53 +267,73 @@ pub enum FileType {
     Dir = 0,
     File = 1,
     Device = 2,
+    Link = 3,
 }
 
+// A resource is a node opened by a filesystem and stored in a file handle
 #[derive(Debug, Clone)]
-pub enum Resource {
-    Dir(Dir),
-    File(File),
-    Device(Device),
+pub struct Resource {
+    node: Box<dyn Node>,
+}
+
+impl Resource {
+    pub fn new<T: Node + 'static>(node: T) -> Self {
+        Self { node: Box::new(node) }
+    }
+
+    pub fn read_at(&mut self, offset: usize, buf: &mut [u8]) -> Result<usize, ()> {
+        self.node.read_at(offset, buf)
+    }
+
+    pub fn write_at(&mut self, offset: usize, buf: &[u8]) -> Result<usize, ()> {
+        self.node.write_at(offset, buf)
+    }
+
+    pub fn next_entry(&mut self) -> Result<Option<FileInfo>, ()> {
+        self.node.next_entry()
+    }
+
+    pub fn control(&mut self, request: ControlRequest, buf: &mut [u8]) -> Result<usize, ()> {
+        self.node.control(request, buf)
+    }
 }
 
 impl FileIO for Resource {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
-        match self {
-            Resource::Dir(io) => io.read(buf),
-            Resource::File(io) => io.read(buf),
-            Resource::Device(io) => io.read(buf),
-        }
+        self.node.read(buf)
     }
 
     fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
-        match self {
-            Resource::Dir(io) => io.write(buf),
-            Resource::File(io) => io.write(buf),
-            Resource::Device(io) => io.write(buf),
-        }
+        self.node.write(buf)
     }
 }
 
+// Expand a leading "~" into the home dir of the user and return the
+// normalized absolute path
 pub fn canonicalize(path: &str) -> Result<String, ()> {
     match sys::process::env("HOME") {
-        Some(home) => {
-            if path.starts_with('~') {
-                Ok(path.replace('~', &home))
-            } else {
-                Ok(path.to_string())
-            }
+        Some(home) if path == "~" || path.starts_with("~/") => {
+            Ok(realpath(&format!("{}{}", home, &path[1..])))
         },
-        None => {
-            Ok(path.to_string())
+        _ => {
+            Ok(realpath(path))
         }
     }
 }
 
+// Return the version of the MFS mounted to '/'
+pub fn disk_version() -> Option<u8> {
+    let (_dev, _) = mount::select_path("/")?;
+    Some(SuperBlock::read().version())
+}
+
 pub fn disk_size() -> usize {
+    let _dev = mount::select_path("/");
     (SuperBlock::read().block_count as usize) * BLOCK_SIZE
 }
 
 pub fn disk_used() -> usize {
+    let _dev = mount::select_path("/");
     (SuperBlock::read().alloc_count as usize) * BLOCK_SIZE
 }
 
@@ -145,12 +342,19 @@ pub fn disk_free() -> usize {
 }
 
 pub fn init() {
-    for bus in 0..2 {
-        for dsk in 0..2 {
-            if SuperBlock::check_ata(bus, dsk) {
-                log!("MFS Superblock found in ATA {}:{}\n", bus, dsk);
-                mount_ata(bus, dsk);
-                return;
+    for disk in disks() {
+        let mut paths = vec![disk.clone()];
+        if let Some(dev) = open_device(&disk) {
+            paths.extend(partitions(&dev).iter().map(|(n, _)| format!("{}/{}", disk, n)));
+        }
+        for path in paths {
+            if let Some(dev) = open_device(&path) {
+                if SuperBlock::check(&dev) {
+                    log!("MFS Superblock found in {}\n", path);
+                    mount_device(dev);
+                    journal::replay();
+                    return;
+                }
             }
         }
     }
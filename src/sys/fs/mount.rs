@@ -0,0 +1,195 @@
+use super::block_device::BlockDevice;
+use super::vfs::FileSystem;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+// The mount table maps the paths where filesystems are mounted to their block
+// devices and to the implementation of the filesystem found on them. Blocks
+// are read from and written to the device selected during an operation, or
+// to the root device otherwise, and the dirs and files opened on a device
+// keep its id to select it again before accessing their blocks. The device
+// of a mount is shared with the read-only filesystems reading it directly.
+pub struct Mount {
+    id: usize,
+    path: String,
+    dev: Arc<BlockDevice>,
+    fs: Arc<dyn FileSystem>,
+}
+
+lazy_static! {
+    static ref MOUNTS: Mutex<Vec<Mount>> = Mutex::new(Vec::new());
+}
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+// Mount a device at the given path, replacing the device already mounted
+// there, and return its id. The other mounts are removed when a new root is
+// mounted because their mount points were on the previous root.
+pub fn mount<D: Into<Arc<BlockDevice>>>(path: &str, dev: D, fs: Arc<dyn FileSystem>) -> usize {
+    let dev = dev.into();
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    let mut mounts = MOUNTS.lock();
+    mounts.retain(|m| m.path != path && path != "/");
+    mounts.push(Mount { id, path: path.into(), dev, fs });
+    id
+}
+
+pub fn umount(path: &str) -> Result<(), ()> {
+    let mut mounts = MOUNTS.lock();
+    let n = mounts.len();
+    mounts.retain(|m| m.path != path);
+    if mounts.len() == n {
+        return Err(());
+    }
+    Ok(())
+}
+
+pub fn umount_all() {
+    MOUNTS.lock().clear();
+    CURRENT.store(0, Ordering::SeqCst);
+}
+
+// A device stays selected until its selection is dropped, which restores the
+// previous one.
+#[must_use]
+pub struct Selection {
+    prev: usize,
+}
+
+impl Drop for Selection {
+    fn drop(&mut self) {
+        CURRENT.store(self.prev, Ordering::SeqCst);
+    }
+}
+
+pub fn select(id: usize) -> Selection {
+    let prev = CURRENT.swap(id, Ordering::SeqCst);
+    Selection { prev }
+}
+
+// Return the id of the selected device, or of the root device when none is
+// selected.
+pub fn current() -> usize {
+    match CURRENT.load(Ordering::SeqCst) {
+        0 => MOUNTS.lock().iter().find(|m| m.path == "/").map_or(0, |m| m.id),
+        id => id,
+    }
+}
+
+// Return the path where the device is mounted
+pub fn path(id: usize) -> Option<String> {
+    MOUNTS.lock().iter().find(|m| m.id == id).map(|m| m.path.clone())
+}
+
+// Return the id of the device mounted at the longest prefix of the given
+// absolute path, and the rest of the path inside this device.
+pub fn resolve(path: &str) -> Option<(usize, String)> {
+    let mounts = MOUNTS.lock();
+    find(&mounts, path).map(|mount| {
+        let rest = path[mount.path.len()..].trim_start_matches('/');
+        (mount.id, format!("/{}", rest))
+    })
+}
+
+// Return the filesystem mounted at the longest prefix of the given path
+pub fn filesystem(path: &str) -> Option<Arc<dyn FileSystem>> {
+    let mounts = MOUNTS.lock();
+    find(&mounts, path).map(|mount| mount.fs.clone())
+}
+
+// Select the device of the given path and return its selection with the rest
+// of the path, or None if this device does not have an MFS filesystem.
+pub fn select_path(path: &str) -> Option<(Selection, String)> {
+    let mounts = MOUNTS.lock();
+    let mount = find(&mounts, path)?;
+    if !mount.fs.is_mfs() {
+        return None;
+    }
+    let rest = path[mount.path.len()..].trim_start_matches('/');
+    Some((select(mount.id), format!("/{}", rest)))
+}
+
+pub fn is_mount_point(path: &str) -> bool {
+    let path = if path.len() > 1 { path.trim_end_matches('/') } else { path };
+    MOUNTS.lock().iter().any(|m| m.path == path)
+}
+
+// Call a function with the selected device, unless it is shared with the
+// filesystem mounted on it
+pub fn with_device<T, F>(f: F) -> Option<T> where F: FnOnce(&mut BlockDevice) -> T {
+    let id = current();
+    let mut mounts = MOUNTS.lock();
+    mounts.iter_mut().find(|m| m.id == id).and_then(|m| Arc::get_mut(&mut m.dev)).map(f)
+}
+
+// Return the path, the name of the device, and the name of the filesystem
+// of each mount
+pub fn list() -> Vec<(String, String, String)> {
+    MOUNTS.lock().iter().map(|m| (m.path.clone(), m.dev.name(), m.fs.name().into())).collect()
+}
+
+// Return true if the device, a partition of the device, or the disk holding
+// the device is mounted
+pub fn is_disk_mounted(name: &str) -> bool {
+    MOUNTS.lock().iter().any(|m| {
+        let dev = m.dev.name();
+        is_prefix(&dev, name) || is_prefix(name, &dev)
+    })
+}
+
+fn find<'a>(mounts: &'a [Mount], path: &str) -> Option<&'a Mount> {
+    let mut res: Option<&Mount> = None;
+    for mount in mounts {
+        if is_prefix(&mount.path, path) {
+            if res.map_or(true, |m| m.path.len() < mount.path.len()) {
+                res = Some(mount);
+            }
+        }
+    }
+    res
+}
+
+fn is_prefix(prefix: &str, path: &str) -> bool {
+    if prefix == "/" {
+        path.starts_with('/')
+    } else if let Some(rest) = path.strip_prefix(prefix) {
+        rest.is_empty() || rest.starts_with('/')
+    } else {
+        false
+    }
+}
+
+#[test_case]
+fn test_mount_resolve() {
+    use super::block_device::MemBlockDevice;
+    use super::mfs::Mfs;
+    let a = mount("/", BlockDevice::Mem(MemBlockDevice::new(0)), Arc::new(Mfs));
+    let b = mount("/tmp", BlockDevice::Mem(MemBlockDevice::new(0)), Arc::new(Mfs));
+    assert_eq!(resolve("/"), Some((a, "/".into())));
+    assert_eq!(resolve("/usr/alice"), Some((a, "/usr/alice".into())));
+    assert_eq!(resolve("/tmpfile"), Some((a, "/tmpfile".into())));
+    assert_eq!(resolve("/tmp"), Some((b, "/".into())));
+    assert_eq!(resolve("/tmp/test"), Some((b, "/test".into())));
+    assert!(is_mount_point("/tmp/"));
+    assert!(umount("/tmp").is_ok());
+    assert!(umount("/tmp").is_err());
+    assert_eq!(resolve("/tmp/test"), Some((a, "/tmp/test".into())));
+    let b = mount("/tmp", BlockDevice::Mem(MemBlockDevice::new(0)), Arc::new(Mfs));
+    assert_eq!(current(), a);
+    {
+        let _dev = select(b);
+        assert_eq!(current(), b);
+    }
+    assert_eq!(current(), a);
+    let c = mount("/", BlockDevice::Mem(MemBlockDevice::new(0)), Arc::new(Mfs));
+    assert_eq!(resolve("/tmp/test"), Some((c, "/tmp/test".into())));
+    umount_all();
+    assert_eq!(resolve("/"), None);
+}
@@ -0,0 +1,159 @@
+use super::block_device::BlockDeviceIO;
+use super::BLOCK_SIZE;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+// Partition tables
+//
+// The MBR in the first block of a disk has 4 entries of 16 bytes at offset
+// 446 followed by the 0x55AA signature:
+// 4 => type
+// 8..12 => first block
+// 12..16 => number of blocks
+//
+// A disk with a GPT has a single protective entry of type 0xEE in its MBR,
+// and a header in its second block giving the address, the number, and the
+// size of the entries of the table:
+// 0..8 => "EFI PART"
+// 72..80 => first block of the entries
+// 80..84 => number of entries
+// 84..88 => size of an entry
+//
+// GPT entry structure:
+// 0..16 => type GUID (zero for an unused entry)
+// 32..40 => first block
+// 40..48 => last block (inclusive)
+
+const MBR_ENTRIES_OFFSET: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+const TYPE_PROTECTIVE: u8 = 0xEE;
+const TYPE_EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+
+// Maximum number of GPT entries read from the table
+const MAX_GPT_ENTRIES: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Partition {
+    pub start: u64,
+    pub len: u64,
+}
+
+// Return the partitions of a disk indexed by their number minus one, with
+// None for the unused entries. Logical partitions inside an extended
+// partition of an MBR are not supported.
+pub fn read<T: BlockDeviceIO>(dev: &T) -> Vec<Option<Partition>> {
+    if dev.block_size() != BLOCK_SIZE {
+        return Vec::new();
+    }
+    let mut buf = [0; BLOCK_SIZE];
+    if dev.read(0, &mut buf).is_err() {
+        return Vec::new();
+    }
+    match parse_mbr(&buf) {
+        Some(partitions) if is_protective(&buf) => read_gpt(dev).unwrap_or(partitions),
+        Some(partitions) => partitions,
+        None => Vec::new(),
+    }
+}
+
+fn read_gpt<T: BlockDeviceIO>(dev: &T) -> Option<Vec<Option<Partition>>> {
+    let mut buf = [0; BLOCK_SIZE];
+    dev.read(1, &mut buf).ok()?;
+    let (addr, count, size) = parse_gpt_header(&buf)?;
+    let len = count.checked_mul(size).filter(|&len| len <= MAX_GPT_ENTRIES * BLOCK_SIZE)?;
+    let n = (len + BLOCK_SIZE - 1) / BLOCK_SIZE;
+    let mut entries = vec![0; n * BLOCK_SIZE];
+    dev.read_blocks(addr.try_into().ok()?, &mut entries).ok()?;
+    Some(entries.chunks(size).take(count).map(parse_gpt_entry).collect())
+}
+
+fn parse_mbr(buf: &[u8]) -> Option<Vec<Option<Partition>>> {
+    if buf[510..512] != [0x55, 0xAA] {
+        return None;
+    }
+    let partitions = (0..4).map(|i| {
+        let entry = &buf[MBR_ENTRIES_OFFSET + i * MBR_ENTRY_SIZE..];
+        let start = read_u32(entry, 8) as u64;
+        let len = read_u32(entry, 12) as u64;
+        if entry[4] == 0 || TYPE_EXTENDED.contains(&entry[4]) || len == 0 {
+            None
+        } else {
+            Some(Partition { start, len })
+        }
+    }).collect();
+    Some(partitions)
+}
+
+fn is_protective(buf: &[u8]) -> bool {
+    buf[MBR_ENTRIES_OFFSET + 4] == TYPE_PROTECTIVE
+}
+
+fn parse_gpt_header(buf: &[u8]) -> Option<(u64, usize, usize)> {
+    if &buf[0..8] != b"EFI PART" {
+        return None;
+    }
+    let addr = read_u64(buf, 72);
+    let count = (read_u32(buf, 80) as usize).min(MAX_GPT_ENTRIES);
+    let size = read_u32(buf, 84) as usize;
+    if size < 128 || size > BLOCK_SIZE || !size.is_power_of_two() {
+        return None;
+    }
+    Some((addr, count, size))
+}
+
+fn parse_gpt_entry(buf: &[u8]) -> Option<Partition> {
+    if buf[0..16].iter().all(|&b| b == 0) {
+        return None;
+    }
+    let start = read_u64(buf, 32);
+    let end = read_u64(buf, 40);
+    if end < start {
+        return None;
+    }
+    Some(Partition { start, len: end - start + 1 })
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+#[test_case]
+fn test_partition_tables() {
+    let mut mbr = [0; BLOCK_SIZE];
+    assert_eq!(parse_mbr(&mbr), None);
+    mbr[510] = 0x55;
+    mbr[511] = 0xAA;
+    mbr[446 + 4] = 0x0C; // FAT32 with LBA
+    mbr[446 + 8..446 + 12].copy_from_slice(&2048u32.to_le_bytes());
+    mbr[446 + 12..446 + 16].copy_from_slice(&4096u32.to_le_bytes());
+    mbr[478 + 4] = 0x05; // Extended
+    mbr[478 + 12..478 + 16].copy_from_slice(&1u32.to_le_bytes());
+    let partition = Partition { start: 2048, len: 4096 };
+    assert_eq!(parse_mbr(&mbr), Some(vec![Some(partition), None, None, None]));
+    assert!(!is_protective(&mbr));
+
+    let mut header = [0; BLOCK_SIZE];
+    assert_eq!(parse_gpt_header(&header), None);
+    header[0..8].copy_from_slice(b"EFI PART");
+    header[72..80].copy_from_slice(&2u64.to_le_bytes());
+    header[80..84].copy_from_slice(&256u32.to_le_bytes());
+    header[84..88].copy_from_slice(&128u32.to_le_bytes());
+    assert_eq!(parse_gpt_header(&header), Some((2, MAX_GPT_ENTRIES, 128)));
+    header[84..88].copy_from_slice(&(1u32 << 31).to_le_bytes());
+    assert_eq!(parse_gpt_header(&header), None);
+    header[84..88].copy_from_slice(&1024u32.to_le_bytes());
+    assert_eq!(parse_gpt_header(&header), None);
+
+    let mut entry = [0; 128];
+    assert_eq!(parse_gpt_entry(&entry), None);
+    entry[0] = 0xAF;
+    entry[32..40].copy_from_slice(&2048u64.to_le_bytes());
+    entry[40..48].copy_from_slice(&6143u64.to_le_bytes());
+    assert_eq!(parse_gpt_entry(&entry), Some(partition));
+}
@@ -1,11 +1,9 @@
-use super::dir_entry::DirEntry;
+use super::dir_entry::{DirEntry, EntryFormat};
 use super::block::LinkedBlock;
 use super::dir::Dir;
-use super::FileType;
+use super::mount;
 
-use alloc::string::String;
 use core::convert::From;
-use core::convert::TryInto;
 
 pub struct ReadDir {
     // TODO: make those fields private
@@ -13,6 +11,22 @@ pub struct ReadDir {
     pub block: LinkedBlock,
     pub block_offset: usize,
     block_index: usize,
+    format: EntryFormat,
+}
+
+// Position of an entry in the chain of blocks of a dir
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor {
+    addr: u32,
+    offset: usize,
+    index: usize,
+}
+
+impl Cursor {
+    // Position of the first entry of a dir
+    pub fn start(dir: &Dir) -> Self {
+        Self { addr: dir.addr(), offset: 0, index: 0 }
+    }
 }
 
 impl From<Dir> for ReadDir {
@@ -22,23 +36,28 @@ impl From<Dir> for ReadDir {
             block: LinkedBlock::read(dir.addr()),
             block_offset: 0,
             block_index: 0,
+            format: EntryFormat::current(),
         }
     }
 }
 
-macro_rules! read_uint_fn {
-    ($name:ident, $type:ident) => {
-        fn $name(&mut self) -> $type {
-            let data = self.block.data();
-            let a = self.block_offset;
-            let b = a + core::mem::size_of::<$type>();
-            self.block_offset = b;
-            $type::from_be_bytes(data[a..b].try_into().unwrap())
+impl ReadDir {
+    // Resume reading the entries of a dir at a cursor
+    pub fn resume(dir: Dir, cursor: Cursor) -> Self {
+        Self {
+            dir,
+            block: LinkedBlock::read(cursor.addr),
+            block_offset: cursor.offset,
+            block_index: cursor.index,
+            format: EntryFormat::current(),
         }
-    };
-}
+    }
+
+    /// Position of the next entry
+    pub fn cursor(&self) -> Cursor {
+        Cursor { addr: self.block.addr(), offset: self.block_offset, index: self.block_index }
+    }
 
-impl ReadDir {
     /// Total number of bytes read
     pub fn offset(&self) -> usize {
         self.block_index * self.block.len() + self.block_offset
@@ -54,16 +73,9 @@ impl ReadDir {
         self.block.addr()
     }
 
-    read_uint_fn!(read_u8, u8);
-    read_uint_fn!(read_u32, u32);
-    read_uint_fn!(read_u64, u64);
-
-    fn read_utf8_lossy(&mut self, len: usize) -> String {
-        let data = self.block.data();
-        let a = self.block_offset;
-        let b = a + len;
-        self.block_offset = b;
-        String::from_utf8_lossy(&data[a..b]).into()
+    /// Format of the entries
+    pub fn format(&self) -> EntryFormat {
+        self.format
     }
 }
 
@@ -71,45 +83,29 @@ impl Iterator for ReadDir {
     type Item = DirEntry;
 
     fn next(&mut self) -> Option<DirEntry> {
+        let _dev = mount::select(self.dir.dev());
         loop {
             loop {
-                let offset = self.block_offset; // Backup cursor position
+                let offset = self.block_offset;
 
                 // Switch to next block if no space left for another entry
-                if offset >= self.block.len() - DirEntry::empty_len() {
+                if offset >= self.block.len() - self.format.empty_len() {
                     break;
                 }
 
-                let entry_kind = match self.read_u8() {
-                    0 => FileType::Dir,
-                    1 => FileType::File,
-                    2 => FileType::Device,
-                    _ => {
-                        self.block_offset = offset; // Rewind the cursor
-                        break;
-                    },
+                let data = &self.block.data()[offset..];
+                let entry = match DirEntry::parse(self.dir.clone(), self.format, data) {
+                    Some(entry) => entry,
+                    None => break,
                 };
-
-                let entry_addr = self.read_u32();
-                let entry_size = self.read_u32();
-                let entry_time = self.read_u64();
-
-                let n = self.read_u8() as usize;
-                if n == 0 || n >= self.block.len() - self.block_offset {
-                    self.block_offset = offset; // Rewind the cursor
-                    break;
-                }
-
-                // The rest of the entry is the filename string
-                let entry_name = self.read_utf8_lossy(n);
+                self.block_offset += entry.len();
 
                 // Skip deleted entries
-                if entry_addr == 0 {
+                if entry.addr() == 0 {
                     continue;
                 }
 
-                let dir = self.dir.clone();
-                return Some(DirEntry::new(dir, entry_kind, entry_addr, entry_size, entry_time, &entry_name));
+                return Some(entry);
             }
 
             match self.block.next() {
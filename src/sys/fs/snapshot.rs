@@ -0,0 +1,217 @@
+use super::FileIO;
+use super::block_device::BlockDeviceIO;
+
+use alloc::vec;
+use core::convert::{TryFrom, TryInto};
+use sha2::{Digest, Sha256};
+
+// A snapshot is a copy of every block of a device, made of a header followed
+// by ranges of blocks, where each range is followed by its SHA-256 checksum.
+//
+// Header structure:
+// 0..8 => signature
+// 8..12 => block size
+// 12..16 => block count
+// 16..20 => number of blocks in a range
+// 20..32 => reserved
+const SIGNATURE: &[u8; 8] = b"MOROSDSK";
+const HEADER_SIZE: usize = 32;
+const CHECKSUM_SIZE: usize = 32;
+const RANGE_LEN: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Read,
+    Write,
+    Header,
+    Checksum(u32), // Address of the first block of the range
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub block_size: u32,
+    pub block_count: u32,
+    pub range_len: u32,
+}
+
+impl Header {
+    fn parse(buf: &[u8]) -> Result<Self, Error> {
+        if &buf[0..8] != SIGNATURE {
+            return Err(Error::Header);
+        }
+        let block_size = u32::from_be_bytes(buf[8..12].try_into().unwrap());
+        let block_count = u32::from_be_bytes(buf[12..16].try_into().unwrap());
+        let range_len = u32::from_be_bytes(buf[16..20].try_into().unwrap());
+        if block_size == 0 || range_len == 0 {
+            return Err(Error::Header);
+        }
+        Ok(Self { block_size, block_count, range_len })
+    }
+
+    fn as_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut buf = [0; HEADER_SIZE];
+        buf[0..8].copy_from_slice(SIGNATURE);
+        buf[8..12].copy_from_slice(&self.block_size.to_be_bytes());
+        buf[12..16].copy_from_slice(&self.block_count.to_be_bytes());
+        buf[16..20].copy_from_slice(&self.range_len.to_be_bytes());
+        buf
+    }
+
+    // Number of blocks of the range starting at the given address
+    fn range_at(&self, addr: u32) -> u32 {
+        core::cmp::min(self.range_len, self.block_count - addr)
+    }
+}
+
+// Write a snapshot of a device, calling the progress function with the number
+// of blocks copied and the total number of blocks after each range.
+pub fn dump<T, P>(dev: &T, out: &mut dyn FileIO, mut progress: P) -> Result<(), Error>
+where T: BlockDeviceIO, P: FnMut(u32, u32) {
+    let header = Header {
+        block_size: dev.block_size() as u32,
+        block_count: u32::try_from(dev.block_count()).map_err(|_| Error::Header)?,
+        range_len: RANGE_LEN,
+    };
+    write_all(out, &header.as_bytes())?;
+    let bs = header.block_size as usize;
+    let mut buf = vec![0; header.range_len as usize * bs];
+    let mut addr = 0;
+    while addr < header.block_count {
+        let n = header.range_at(addr);
+        let data = &mut buf[0..(n as usize * bs)];
+        dev.read_blocks(addr, data).map_err(|_| Error::Read)?;
+        write_all(out, data)?;
+        write_all(out, &Sha256::digest(data))?;
+        addr += n;
+        progress(addr, header.block_count);
+    }
+    Ok(())
+}
+
+// Check every checksum of a snapshot and return its header
+pub fn verify<P>(input: &mut dyn FileIO, progress: P) -> Result<Header, Error>
+where P: FnMut(u32, u32) {
+    read_ranges(input, progress, |_, _| Ok(()))
+}
+
+// Write a snapshot back to a device, stopping at the first range with a
+// checksum mismatch before writing it.
+pub fn restore<T, P>(dev: &mut T, input: &mut dyn FileIO, progress: P) -> Result<Header, Error>
+where T: BlockDeviceIO, P: FnMut(u32, u32) {
+    let block_size = dev.block_size();
+    let block_count = u32::try_from(dev.block_count()).unwrap_or(u32::MAX);
+    read_ranges(input, progress, |header, range| {
+        match range {
+            None => {
+                if header.block_size as usize != block_size || header.block_count > block_count {
+                    return Err(Error::Header);
+                }
+            }
+            Some((addr, data)) => {
+                dev.write_blocks(addr, data).map_err(|_| Error::Write)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+fn read_ranges<P, F>(input: &mut dyn FileIO, mut progress: P, mut f: F) -> Result<Header, Error>
+where P: FnMut(u32, u32), F: FnMut(&Header, Option<(u32, &[u8])>) -> Result<(), Error> {
+    let mut buf = [0; HEADER_SIZE];
+    read_exact(input, &mut buf)?;
+    let header = Header::parse(&buf)?;
+    f(&header, None)?;
+    let bs = header.block_size as usize;
+    let mut buf = vec![0; header.range_len as usize * bs];
+    let mut checksum = [0; CHECKSUM_SIZE];
+    let mut addr = 0;
+    while addr < header.block_count {
+        let n = header.range_at(addr);
+        let data = &mut buf[0..(n as usize * bs)];
+        read_exact(input, data)?;
+        read_exact(input, &mut checksum)?;
+        if Sha256::digest(data).as_slice() != checksum {
+            return Err(Error::Checksum(addr));
+        }
+        f(&header, Some((addr, data)))?;
+        addr += n;
+        progress(addr, header.block_count);
+    }
+    Ok(header)
+}
+
+fn read_exact(input: &mut dyn FileIO, buf: &mut [u8]) -> Result<(), Error> {
+    let mut i = 0;
+    while i < buf.len() {
+        match input.read(&mut buf[i..]) {
+            Ok(0) | Err(()) => return Err(Error::Read),
+            Ok(n) => i += n,
+        }
+    }
+    Ok(())
+}
+
+fn write_all(out: &mut dyn FileIO, buf: &[u8]) -> Result<(), Error> {
+    let mut i = 0;
+    while i < buf.len() {
+        match out.write(&buf[i..]) {
+            Ok(0) | Err(()) => return Err(Error::Write),
+            Ok(n) => i += n,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+use alloc::vec::Vec;
+
+#[cfg(test)]
+struct Buffer {
+    data: Vec<u8>,
+    offset: usize,
+}
+
+#[cfg(test)]
+impl FileIO for Buffer {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let n = core::cmp::min(buf.len(), self.data.len() - self.offset);
+        buf[0..n].copy_from_slice(&self.data[self.offset..(self.offset + n)]);
+        self.offset += n;
+        Ok(n)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+#[test_case]
+fn test_snapshot() {
+    use super::block_device::MemBlockDevice;
+    use super::BLOCK_SIZE;
+
+    let n = RANGE_LEN + 6;
+    let mut src = MemBlockDevice::new(n as usize);
+    src.write(1, &[1; BLOCK_SIZE]).unwrap();
+    src.write(n - 1, &[2; BLOCK_SIZE]).unwrap();
+
+    let mut buf = Buffer { data: Vec::new(), offset: 0 };
+    assert_eq!(dump(&src, &mut buf, |_, _| {}), Ok(()));
+    let size = HEADER_SIZE + n as usize * BLOCK_SIZE + 2 * CHECKSUM_SIZE;
+    assert_eq!(buf.data.len(), size);
+
+    let mut dst = MemBlockDevice::new(n as usize);
+    assert!(restore(&mut dst, &mut buf, |_, _| {}).is_ok());
+    let mut block = [0; BLOCK_SIZE];
+    dst.read(n - 1, &mut block).unwrap();
+    assert_eq!(block, [2; BLOCK_SIZE]);
+
+    buf.offset = 0;
+    buf.data[HEADER_SIZE + BLOCK_SIZE] = 0;
+    assert_eq!(verify(&mut buf, |_, _| {}), Err(Error::Checksum(0)));
+
+    let mut small = MemBlockDevice::new(n as usize - 1);
+    buf.offset = 0;
+    assert_eq!(restore(&mut small, &mut buf, |_, _| {}).err(), Some(Error::Header));
+}
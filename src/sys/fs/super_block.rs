@@ -1,14 +1,17 @@
-use crate::sys;
 use crate::KERNEL_SIZE;
 use super::block::Block;
-use super::block_device::BlockDeviceIO;
-use core::convert::TryInto;
+use super::block_device::{BlockDevice, BlockDeviceIO};
+use super::journal::JOURNAL_SIZE;
+use core::convert::{TryFrom, TryInto};
 
-const SUPERBLOCK_ADDR: u32 = (KERNEL_SIZE / super::BLOCK_SIZE) as u32;
+// Address of the superblock after the bootloader and the kernel at the
+// beginning of a whole disk
+pub const SUPERBLOCK_ADDR: u32 = (KERNEL_SIZE / super::BLOCK_SIZE) as u32;
 const SIGNATURE: &[u8; 8] = b"MOROS FS";
 
 #[derive(Debug)]
 pub struct SuperBlock {
+    addr: u32,
     signature: &'static[u8; 8],
     version: u8,
     block_size: u32,
@@ -17,34 +20,45 @@ pub struct SuperBlock {
 }
 
 impl SuperBlock {
-    pub fn check_ata(bus: u8, dsk: u8) -> bool {
+    pub fn check(dev: &BlockDevice) -> bool {
         let mut buf = [0u8; super::BLOCK_SIZE];
-        if sys::ata::read(bus, dsk, SUPERBLOCK_ADDR, &mut buf).is_err() {
+        if dev.read(dev.superblock_addr(), &mut buf).is_err() {
             return false;
         }
-        &buf[0..8] == SIGNATURE
+        &buf[0..8] == SIGNATURE && buf[8] <= super::VERSION
     }
 
+    // Return None if the selected device is too small for a filesystem, or
+    // too large for its 32 bits block addresses.
     pub fn new() -> Option<Self> {
-        if let Some(ref dev) = *super::block_device::BLOCK_DEVICE.lock() {
-            Some(Self {
-                signature: SIGNATURE,
-                version: super::VERSION,
-                block_size: dev.block_size() as u32,
-                block_count: dev.block_count() as u32,
-                alloc_count: 0,
-            })
-        } else {
-            None
-        }
+        super::mount::with_device(|dev| Self::for_device(dev)).flatten()
+    }
+
+    pub fn for_device(dev: &BlockDevice) -> Option<Self> {
+        u32::try_from(dev.block_count()).ok().map(|block_count| Self {
+            addr: dev.superblock_addr(),
+            signature: SIGNATURE,
+            version: super::VERSION,
+            block_size: dev.block_size() as u32,
+            block_count,
+            alloc_count: 0,
+        }).filter(|sb| sb.block_count > sb.bitmap_area() + 1 && sb.data_area() < sb.block_count)
+    }
+
+    // Number of blocks before the data area with a single bitmap block on a
+    // whole disk
+    pub fn min_block_count() -> usize {
+        (SUPERBLOCK_ADDR + 2 + JOURNAL_SIZE + 1) as usize
     }
 
     // NOTE: FS must be mounted
     pub fn read() -> Self {
-        let block = Block::read(SUPERBLOCK_ADDR);
+        let addr = superblock_addr();
+        let block = Block::read(addr);
         let data = block.data();
         debug_assert_eq!(&data[0..8], SIGNATURE);
         Self {
+            addr,
             signature: SIGNATURE,
             version: data[8],
             block_size: 2 << (8 + data[9] as u32),
@@ -54,7 +68,7 @@ impl SuperBlock {
     }
 
     pub fn write(&self) {
-        let mut block = Block::new(SUPERBLOCK_ADDR);
+        let mut block = Block::new(self.addr);
         let data = block.data_mut();
 
         data[0..8].clone_from_slice(self.signature);
@@ -70,6 +84,45 @@ impl SuperBlock {
         block.write();
     }
 
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    // The journal area was added in version 2
+    pub fn has_journal(&self) -> bool {
+        self.version >= 2
+    }
+
+    // The files are stored in trees of index blocks since version 3
+    pub fn has_indexed_files(&self) -> bool {
+        self.version >= 3
+    }
+
+    // The dir entries have a long format with more metadata since version 4
+    pub fn has_long_entries(&self) -> bool {
+        self.version >= 4
+    }
+
+    // The links were added in version 5
+    pub fn has_links(&self) -> bool {
+        self.version >= 5
+    }
+
+    // All the bits of a bitmap block are used since version 6
+    pub fn has_full_bitmap(&self) -> bool {
+        self.version >= 6
+    }
+
+    // The hard links of a file share its size and modification time since
+    // version 6
+    pub fn has_shared_metadata(&self) -> bool {
+        self.version >= 6
+    }
+
+    pub fn set_version(&mut self, version: u8) {
+        self.version = version;
+    }
+
     pub fn block_size(&self) -> u32 {
         self.block_size
     }
@@ -79,18 +132,36 @@ impl SuperBlock {
     }
 
     pub fn bitmap_area(&self) -> u32 {
-        SUPERBLOCK_ADDR + 2
+        if self.has_journal() {
+            self.addr + 2 + JOURNAL_SIZE
+        } else {
+            self.addr + 2
+        }
     }
 
+    // The size of the bitmap is computed with 64 bits to not overflow on
+    // disks of more than 2^20 blocks, and the data area is then below the
+    // block count.
     pub fn data_area(&self) -> u32 {
-        let bs = super::BITMAP_SIZE as u32;
-        let total = self.block_count;
-        let offset = self.bitmap_area();
-        let rest = (total - offset) * bs / (bs + 1);
-        self.bitmap_area() + rest / bs
+        let bs = super::BITMAP_SIZE as u64;
+        let total = self.block_count as u64;
+        let offset = self.bitmap_area() as u64;
+        let rest = total.saturating_sub(offset) * bs / (bs + 1);
+        (offset + rest / bs) as u32
     }
 }
 
+// The superblock of the selected device is at the beginning of a partition,
+// or after the kernel on a whole disk
+fn superblock_addr() -> u32 {
+    super::mount::with_device(|dev| dev.superblock_addr()).unwrap_or(SUPERBLOCK_ADDR)
+}
+
+// The journal follows the superblock and a reserved block
+pub fn journal_addr() -> u32 {
+    superblock_addr() + 2
+}
+
 pub fn inc_alloc_count() {
     let mut sb = SuperBlock::read();
     sb.alloc_count += 1;
@@ -102,3 +173,22 @@ pub fn dec_alloc_count() {
     sb.alloc_count -= 1;
     sb.write();
 }
+
+#[test_case]
+fn test_data_area() {
+    let bs = super::BITMAP_SIZE as u32;
+    for &block_count in &[1 << 16, (1 << 20) + 1, 1 << 22, u32::MAX] {
+        let sb = SuperBlock {
+            addr: SUPERBLOCK_ADDR,
+            signature: SIGNATURE,
+            version: super::VERSION,
+            block_size: super::BLOCK_SIZE as u32,
+            block_count,
+            alloc_count: 0,
+        };
+        // One bitmap block for every BITMAP_SIZE data blocks
+        let n = (block_count - sb.bitmap_area()) / (bs + 1);
+        assert_eq!(sb.data_area(), sb.bitmap_area() + n);
+        assert!(sb.data_area() < block_count);
+    }
+}
@@ -0,0 +1,88 @@
+use super::{ControlRequest, FileInfo, FileIO, FileType};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+// The virtual filesystem layer is made of two traits: a filesystem mounted in
+// the mount table resolves the absolute paths below its mount point into
+// nodes, and each node is an open file, dir or device that can be stored in
+// a file handle of a process.
+pub trait FileSystem: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // The dirs and files of MFS are also used directly by the kernel
+    fn is_mfs(&self) -> bool {
+        false
+    }
+
+    // Open the node of the given kind found at a path
+    fn lookup(&self, path: &str, kind: FileType) -> Option<Box<dyn Node>>;
+
+    // Create a node of the given kind at a path
+    fn create(&self, path: &str, kind: FileType) -> Option<Box<dyn Node>>;
+
+    // Remove the entry of a path without following it if it is a link
+    fn unlink(&self, path: &str) -> Result<(), ()>;
+
+    fn read_dir(&self, path: &str) -> Option<Vec<FileInfo>>;
+
+    fn stat(&self, path: &str) -> Option<FileInfo>;
+}
+
+pub trait Node: FileIO + Debug + Send + Sync {
+    fn read_at(&mut self, _offset: usize, _buf: &mut [u8]) -> Result<usize, ()> {
+        Err(())
+    }
+
+    fn write_at(&mut self, _offset: usize, _buf: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+
+    // Return the info of the next entry of a dir, or None after the last one
+    fn next_entry(&mut self) -> Result<Option<FileInfo>, ()> {
+        Err(())
+    }
+
+    // Send a typed request to a device, using the buffer for its argument and
+    // its result, and return the length of the result
+    fn control(&mut self, _request: ControlRequest, _buf: &mut [u8]) -> Result<usize, ()> {
+        Err(())
+    }
+
+    fn box_clone(&self) -> Box<dyn Node>;
+}
+
+impl Clone for Box<dyn Node> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+// Serialize the info of the next entries of a dir into the buffer, with a
+// function returning the entry at a cursor and moving it to the next one.
+// The cursor is only moved past the entries that fit in the buffer, and an
+// error is returned if the buffer is too small for the first one.
+pub fn read_entries<C, F>(buf: &mut [u8], cursor: &mut C, mut next_entry: F) -> Result<usize, ()>
+where C: Copy, F: FnMut(&mut C) -> Result<Option<FileInfo>, ()> {
+    let mut i = 0;
+    loop {
+        let mut next = *cursor;
+        let info = match next_entry(&mut next)? {
+            Some(info) => info,
+            None => break,
+        };
+        let bytes = info.as_bytes();
+        let j = i + bytes.len();
+        if j > buf.len() {
+            if i == 0 {
+                return Err(());
+            }
+            break;
+        }
+        buf[i..j].copy_from_slice(&bytes);
+        *cursor = next;
+        i = j;
+    }
+    Ok(i)
+}
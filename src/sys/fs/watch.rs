@@ -0,0 +1,178 @@
+use super::{dirname, FileIO};
+use super::vfs::Node;
+
+use alloc::borrow::ToOwned;
+use alloc::boxed::Box;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+// Number of events kept for a watcher that is not read
+const QUEUE_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Create,
+    Write,
+    Delete,
+    Rename,
+    Change, // Metadata
+}
+
+impl EventKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Create => "create",
+            EventKind::Write  => "write",
+            EventKind::Delete => "delete",
+            EventKind::Rename => "rename",
+            EventKind::Change => "change",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    kind: EventKind,
+    path: String,
+    dest: Option<String>, // New path of a renamed entry
+}
+
+impl Event {
+    fn line(&self) -> String {
+        match &self.dest {
+            Some(dest) => format!("{} {} {}\n", self.kind.as_str(), self.path, dest),
+            None => format!("{} {}\n", self.kind.as_str(), self.path),
+        }
+    }
+}
+
+type Queue = Mutex<VecDeque<Event>>;
+
+struct Watch {
+    path: String,
+    queue: Weak<Queue>,
+}
+
+lazy_static! {
+    static ref WATCHES: Mutex<Vec<Watch>> = Mutex::new(Vec::new());
+}
+
+// A watcher receives the events of a path and of the entries of a dir at this
+// path, and is read by a process as lines of text like "write /ini/boot.sh"
+// through a file handle. Reading returns nothing when no event is queued.
+#[derive(Debug, Clone)]
+pub struct Watcher {
+    queue: Arc<Queue>,
+}
+
+impl Watcher {
+    pub fn new(path: &str) -> Self {
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let path = if path.len() > 1 { path.trim_end_matches('/') } else { path };
+        let path = path.to_owned();
+        WATCHES.lock().push(Watch { path, queue: Arc::downgrade(&queue) });
+        Self { queue }
+    }
+}
+
+impl FileIO for Watcher {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let mut queue = self.queue.lock();
+        let mut bytes = 0;
+        while let Some(event) = queue.front() {
+            let line = event.line();
+            let n = line.len();
+            if bytes + n > buf.len() {
+                if bytes == 0 {
+                    return Err(()); // The buffer is too small for one event
+                }
+                break;
+            }
+            buf[bytes..(bytes + n)].copy_from_slice(line.as_bytes());
+            bytes += n;
+            queue.pop_front();
+        }
+        Ok(bytes)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+}
+
+impl Node for Watcher {
+    fn box_clone(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+}
+
+// Return true if a watcher could receive events
+pub fn is_active() -> bool {
+    !WATCHES.lock().is_empty()
+}
+
+// Join the path of a dir with the name of one of its entries
+pub fn join(dirname: &str, filename: &str) -> String {
+    if dirname.ends_with('/') {
+        format!("{}{}", dirname, filename)
+    } else {
+        format!("{}/{}", dirname, filename)
+    }
+}
+
+fn is_watched(watch: &str, path: &str) -> bool {
+    path == watch || dirname(path) == watch
+}
+
+fn send(event: Event) {
+    let mut watches = WATCHES.lock();
+    watches.retain(|watch| watch.queue.strong_count() > 0); // Closed watchers
+    for watch in watches.iter() {
+        let matched = is_watched(&watch.path, &event.path)
+            || event.dest.as_ref().map_or(false, |dest| is_watched(&watch.path, dest));
+        if matched {
+            if let Some(queue) = watch.queue.upgrade() {
+                let mut queue = queue.lock();
+                if queue.len() == QUEUE_SIZE {
+                    queue.pop_front();
+                }
+                queue.push_back(event.clone());
+            }
+        }
+    }
+}
+
+pub fn post(kind: EventKind, path: &str) {
+    send(Event { kind, path: path.to_owned(), dest: None });
+}
+
+pub fn post_rename(path: &str, dest: &str) {
+    send(Event { kind: EventKind::Rename, path: path.to_owned(), dest: Some(dest.to_owned()) });
+}
+
+#[test_case]
+fn test_watcher() {
+    let mut watcher = Watcher::new("/tmp/");
+    post(EventKind::Create, "/tmp/a.txt");
+    post(EventKind::Write, "/ini/boot.sh");
+    post(EventKind::Write, "/tmp/a.txt");
+    post_rename("/tmp/a.txt", "/ini/b.txt");
+
+    let mut buf = [0; 64];
+    let n = watcher.read(&mut buf).unwrap();
+    let res = "create /tmp/a.txt\nwrite /tmp/a.txt\nrename /tmp/a.txt /ini/b.txt\n";
+    assert_eq!(&buf[0..n], res.as_bytes());
+    assert_eq!(watcher.read(&mut buf), Ok(0));
+
+    assert_eq!(join("/", "tmp"), "/tmp");
+    assert_eq!(join("/tmp", "a.txt"), "/tmp/a.txt");
+
+    drop(watcher);
+    post(EventKind::Delete, "/tmp/b.txt");
+    assert!(WATCHES.lock().iter().all(|watch| watch.path != "/tmp"));
+}
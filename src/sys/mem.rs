@@ -1,14 +1,19 @@
 use crate::sys;
+use alloc::alloc::{alloc_zeroed, Layout};
 use bootloader::bootinfo::{BootInfo, MemoryMap, MemoryRegionType};
 use core::sync::atomic::{AtomicU64, Ordering};
 use x86_64::instructions::interrupts;
-use x86_64::structures::paging::{FrameAllocator, OffsetPageTable, PageTable, PhysFrame, Size4KiB, Translate};
+use x86_64::structures::paging::{FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame, Size4KiB, Translate};
 use x86_64::{PhysAddr, VirtAddr};
 
+// Virtual memory where the registers of the devices are mapped
+const MMIO_START: u64 = 0x5555_5555_0000;
+
 // NOTE: mutable but changed only once during initialization
 pub static mut PHYS_MEM_OFFSET: u64 = 0;
 pub static mut MEMORY_MAP: Option<&MemoryMap> = None;
 pub static MEMORY_SIZE: AtomicU64 = AtomicU64::new(0);
+static MMIO_END: AtomicU64 = AtomicU64::new(MMIO_START);
 
 pub fn init(boot_info: &'static BootInfo) {
     interrupts::without_interrupts(|| {
@@ -45,6 +50,29 @@ pub fn virt_to_phys(addr: VirtAddr) -> Option<PhysAddr> {
     mapper.translate_addr(addr)
 }
 
+// Map the memory mapped registers of a device to their own pages with the
+// cache disabled, because the mapping of the physical memory is cacheable.
+pub fn map_mmio(addr: PhysAddr, size: u64) -> Option<VirtAddr> {
+    if size == 0 {
+        return None;
+    }
+    let start = PhysFrame::<Size4KiB>::containing_address(addr);
+    let end = PhysFrame::<Size4KiB>::containing_address(addr + (size - 1));
+    let len = end.start_address() - start.start_address() + 4096;
+    let virt = MMIO_END.fetch_add(len, Ordering::SeqCst);
+
+    let mut mapper = unsafe { mapper(VirtAddr::new(PHYS_MEM_OFFSET)) };
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE
+              | PageTableFlags::NO_CACHE | PageTableFlags::WRITE_THROUGH;
+    for (i, frame) in PhysFrame::range_inclusive(start, end).enumerate() {
+        let page = Page::containing_address(VirtAddr::new(virt + 4096 * i as u64));
+        unsafe {
+            mapper.map_to(page, frame, flags, &mut HeapFrameAllocator).ok()?.flush();
+        }
+    }
+    Some(VirtAddr::new(virt + addr.as_u64() % 4096))
+}
+
 pub unsafe fn mapper(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
     let level_4_table = active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(level_4_table, physical_memory_offset)
@@ -81,6 +109,21 @@ impl BootInfoFrameAllocator {
     }
 }
 
+// The page tables of the MMIO mappings are allocated on the heap, which is
+// backed by 4 KB pages where an aligned allocation is a whole frame.
+struct HeapFrameAllocator;
+
+unsafe impl FrameAllocator<Size4KiB> for HeapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        let layout = Layout::from_size_align(4096, 4096).ok()?;
+        let ptr = unsafe { alloc_zeroed(layout) };
+        if ptr.is_null() {
+            return None;
+        }
+        virt_to_phys(VirtAddr::from_ptr(ptr)).map(PhysFrame::containing_address)
+    }
+}
+
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
         // FIXME: creating an iterator for each allocation is very slow if
@@ -31,6 +31,7 @@ macro_rules! log {
 }
 
 pub mod acpi;
+pub mod ahci;
 pub mod allocator;
 pub mod ata;
 pub mod clock;
@@ -42,6 +43,7 @@ pub mod gdt;
 pub mod idt;
 pub mod keyboard;
 pub mod mem;
+pub mod mouse;
 pub mod net;
 pub mod pci;
 pub mod pic;
@@ -51,3 +53,4 @@ pub mod serial;
 pub mod syscall;
 pub mod time;
 pub mod vga;
+pub mod virtio;
@@ -0,0 +1,248 @@
+use crate::sys;
+use crate::sys::fs::FileIO;
+use alloc::collections::vec_deque::VecDeque;
+use bit_field::BitField;
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+// PS/2 mouse on the auxiliary port of the keyboard controller
+//
+// Each movement is sent in a packet of 3 bytes:
+// 0 => flags (buttons, signs, overflows)
+// 1 => x movement
+// 2 => y movement (upward)
+//
+// Event structure read from `/dev/mouse`:
+// 0 => buttons (left, right, middle)
+// 1..3 => x movement (i16 BE)
+// 3..5 => y movement (i16 BE, downward)
+// 5 => column of the cursor
+// 6 => row of the cursor
+
+const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64; // Also command port
+
+const EVENTS_SIZE: usize = 256;
+
+// Pixels of movement per cell of the cursor in text mode
+const CELL_WIDTH: i32 = 8;
+const CELL_HEIGHT: i32 = 16;
+
+lazy_static! {
+    static ref MOUSE: Mutex<Mouse> = Mutex::new(Mouse::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseEvent {
+    pub buttons: u8,
+    pub dx: i16,
+    pub dy: i16,
+    pub x: u8,
+    pub y: u8,
+}
+
+impl MouseEvent {
+    pub const fn size() -> usize {
+        7
+    }
+
+    fn write_to(&self, buf: &mut [u8]) {
+        buf[0] = self.buttons;
+        buf[1..3].copy_from_slice(&self.dx.to_be_bytes());
+        buf[3..5].copy_from_slice(&self.dy.to_be_bytes());
+        buf[5] = self.x;
+        buf[6] = self.y;
+    }
+}
+
+struct Mouse {
+    packet: [u8; 3],
+    packet_len: usize,
+    x: i32, // Position in pixels
+    y: i32,
+    events: VecDeque<MouseEvent>,
+    cursor: bool, // Draw the cursor on the VGA text console
+}
+
+impl Mouse {
+    fn new() -> Self {
+        Self {
+            packet: [0; 3],
+            packet_len: 0,
+            x: 0,
+            y: 0,
+            events: VecDeque::new(),
+            cursor: false,
+        }
+    }
+
+    // Return an event when the byte completes a packet
+    fn add_byte(&mut self, byte: u8) -> Option<MouseEvent> {
+        // The first byte of a packet always has its bit 3 set, which is
+        // used to get back in sync after a lost byte.
+        if self.packet_len == 0 && !byte.get_bit(3) {
+            return None;
+        }
+        self.packet[self.packet_len] = byte;
+        self.packet_len += 1;
+        if self.packet_len < 3 {
+            return None;
+        }
+        self.packet_len = 0;
+        let (buttons, dx, dy) = decode(&self.packet)?;
+        let w = CELL_WIDTH * sys::vga::cols() as i32;
+        let h = CELL_HEIGHT * sys::vga::rows() as i32;
+        self.x = (self.x + dx as i32).clamp(0, w - 1);
+        self.y = (self.y + dy as i32).clamp(0, h - 1);
+        let x = (self.x / CELL_WIDTH) as u8;
+        let y = (self.y / CELL_HEIGHT) as u8;
+        let event = MouseEvent { buttons, dx, dy, x, y };
+        if self.events.len() == EVENTS_SIZE {
+            self.events.pop_front(); // Drop the oldest event
+        }
+        self.events.push_back(event);
+        Some(event)
+    }
+}
+
+// Return the buttons and the movement of a packet, with the y movement
+// pointing downward like the rows of the screen, or None if it overflowed.
+fn decode(packet: &[u8; 3]) -> Option<(u8, i16, i16)> {
+    let flags = packet[0];
+    if flags.get_bit(6) || flags.get_bit(7) {
+        return None;
+    }
+    let buttons = flags & 0b111;
+    let dx = packet[1] as i16 - if flags.get_bit(4) { 0x100 } else { 0 };
+    let dy = packet[2] as i16 - if flags.get_bit(5) { 0x100 } else { 0 };
+    Some((buttons, dx, -dy))
+}
+
+// A mouse opened from `/dev/mouse`
+#[derive(Debug, Clone)]
+pub struct MouseDevice;
+
+impl MouseDevice {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl FileIO for MouseDevice {
+    // Read the events received since the last read without blocking
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        interrupts::without_interrupts(|| {
+            let mut mouse = MOUSE.lock();
+            let n = (buf.len() / MouseEvent::size()).min(mouse.events.len());
+            for (i, event) in mouse.events.drain(0..n).enumerate() {
+                event.write_to(&mut buf[i * MouseEvent::size()..]);
+            }
+            Ok(n * MouseEvent::size())
+        })
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+}
+
+// Show or hide the cursor cell of the mouse on the VGA text console
+pub fn set_cursor(enabled: bool) {
+    let pos = interrupts::without_interrupts(|| {
+        let mut mouse = MOUSE.lock();
+        mouse.cursor = enabled;
+        ((mouse.x / CELL_WIDTH) as usize, (mouse.y / CELL_HEIGHT) as usize)
+    });
+    sys::vga::set_mouse_cursor(if enabled { Some(pos) } else { None });
+}
+
+fn wait_input() {
+    let mut status: Port<u8> = Port::new(STATUS_PORT);
+    for _ in 0..100_000 {
+        if unsafe { !status.read().get_bit(1) } {
+            return;
+        }
+    }
+}
+
+fn wait_output() {
+    let mut status: Port<u8> = Port::new(STATUS_PORT);
+    for _ in 0..100_000 {
+        if unsafe { status.read().get_bit(0) } {
+            return;
+        }
+    }
+}
+
+fn write_command(cmd: u8) {
+    wait_input();
+    unsafe { Port::new(STATUS_PORT).write(cmd) }
+}
+
+fn write_data(data: u8) {
+    wait_input();
+    unsafe { Port::new(DATA_PORT).write(data) }
+}
+
+fn read_data() -> u8 {
+    wait_output();
+    unsafe { Port::new(DATA_PORT).read() }
+}
+
+// Send a command to the mouse and read its acknowledgement
+fn send(cmd: u8) -> bool {
+    write_command(0xD4); // Write to the auxiliary device
+    write_data(cmd);
+    read_data() == 0xFA
+}
+
+pub fn init() {
+    interrupts::without_interrupts(|| {
+        write_command(0xA8); // Enable the auxiliary device
+
+        // Enable IRQ 12 and the clock of the auxiliary device
+        write_command(0x20);
+        let mut config = read_data();
+        config.set_bit(1, true);
+        config.set_bit(5, false);
+        write_command(0x60);
+        write_data(config);
+
+        if send(0xF6) && send(0xF4) { // Set defaults and enable reporting
+            sys::idt::set_irq_handler(12, interrupt_handler);
+            log!("MOUSE PS/2 enabled\n");
+        }
+    });
+}
+
+fn interrupt_handler() {
+    let byte = unsafe { Port::<u8>::new(DATA_PORT).read() };
+    let mut mouse = MOUSE.lock();
+    if let Some(event) = mouse.add_byte(byte) {
+        let cursor = mouse.cursor;
+        drop(mouse); // The lock is released before drawing the cursor
+        if cursor {
+            sys::vga::set_mouse_cursor(Some((event.x as usize, event.y as usize)));
+        }
+    }
+}
+
+#[test_case]
+fn test_mouse_packet() {
+    assert_eq!(decode(&[0b0000_1001, 3, 2]), Some((1, 3, -2)));
+    assert_eq!(decode(&[0b0011_1010, 0xFE, 0xFF]), Some((2, -2, 1)));
+    assert_eq!(decode(&[0b0100_1000, 0, 0]), None);
+
+    let mut mouse = Mouse::new();
+    assert_eq!(mouse.add_byte(0), None); // Out of sync
+    assert_eq!(mouse.add_byte(0b0000_1001), None);
+    assert_eq!(mouse.add_byte(20), None);
+    let event = MouseEvent { buttons: 1, dx: 20, dy: 0, x: 2, y: 0 };
+    assert_eq!(mouse.add_byte(0), Some(event));
+
+    let mut buf = [0; MouseEvent::size()];
+    event.write_to(&mut buf);
+    assert_eq!(buf, [1, 0, 20, 0, 0, 2, 0]);
+}
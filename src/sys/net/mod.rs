@@ -1,3 +1,5 @@
+use crate::sys::fs::{ControlRequest, FileIO};
+
 use alloc::sync::Arc;
 use core::sync::atomic::{AtomicU64, Ordering};
 use lazy_static::lazy_static;
@@ -6,6 +8,8 @@ use spin::Mutex;
 // TODO: Support dyn EthernetInterface
 pub type EthernetInterface<T> = smoltcp::iface::EthernetInterface<'static, T>;
 
+pub mod tcp;
+
 #[cfg(feature = "rtl8139")]
 pub mod rtl8139;
 
@@ -32,6 +36,41 @@ pub fn init() {
     pcnet::init();
 }
 
+// The network device file is only used to configure the interface
+#[derive(Debug, Clone)]
+pub struct Net;
+
+impl Net {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn control(&mut self, request: ControlRequest, buf: &mut [u8]) -> Result<usize, ()> {
+        match request {
+            ControlRequest::SetNetDebug => {
+                let debug = *buf.first().ok_or(())?;
+                if let Some(ref mut iface) = *IFACE.lock() {
+                    iface.device_mut().debug_mode = debug != 0;
+                    Ok(1)
+                } else {
+                    Err(())
+                }
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+impl FileIO for Net {
+    fn read(&mut self, _buf: &mut [u8]) -> Result<usize, ()> {
+        Err(())
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+}
+
 struct InnerStats {
     rx_bytes_count: AtomicU64,
     tx_bytes_count: AtomicU64,
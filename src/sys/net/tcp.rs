@@ -0,0 +1,117 @@
+use crate::sys;
+use crate::sys::fs::FileIO;
+
+use alloc::vec;
+use core::time::Duration;
+use smoltcp::socket::{SocketHandle, SocketSet, TcpSocket, TcpSocketBuffer};
+use smoltcp::time::Instant;
+use smoltcp::wire::IpAddress;
+
+const TIMEOUT: f64 = 5.0;
+
+// A blocking TCP connection giving up after a few seconds without progress
+// or when the user presses Ctrl-C.
+pub struct TcpStream {
+    sockets: SocketSet<'static>,
+    handle: SocketHandle,
+}
+
+impl TcpStream {
+    pub fn connect(addr: IpAddress, port: u16) -> Result<Self, ()> {
+        let tcp_rx_buffer = TcpSocketBuffer::new(vec![0; 4096]);
+        let tcp_tx_buffer = TcpSocketBuffer::new(vec![0; 4096]);
+        let tcp_socket = TcpSocket::new(tcp_rx_buffer, tcp_tx_buffer);
+        let mut sockets = SocketSet::new(vec![]);
+        let handle = sockets.add(tcp_socket);
+        let mut stream = Self { sockets, handle };
+
+        let local_port = 49152 + sys::random::get_u16() % 16384;
+        {
+            let mut socket = stream.sockets.get::<TcpSocket>(handle);
+            socket.connect((addr, port), local_port).map_err(|_| ())?;
+        }
+        stream.wait(|socket| {
+            if socket.may_send() {
+                Some(Ok(()))
+            } else if !socket.is_active() {
+                Some(Err(()))
+            } else {
+                None
+            }
+        })?;
+        Ok(stream)
+    }
+
+    pub fn close(&mut self) {
+        self.sockets.get::<TcpSocket>(self.handle).close();
+        self.wait(|socket| {
+            if socket.is_active() { None } else { Some(Ok(())) }
+        }).ok();
+    }
+
+    // Poll the interface until the function returns something
+    fn wait<T, F>(&mut self, mut f: F) -> Result<T, ()>
+    where F: FnMut(&mut TcpSocket) -> Option<Result<T, ()>> {
+        let mut iface = sys::net::IFACE.lock();
+        let iface = iface.as_mut().ok_or(())?;
+        let started = sys::clock::realtime();
+        loop {
+            if sys::clock::realtime() - started > TIMEOUT {
+                return Err(());
+            }
+            if sys::console::end_of_text() {
+                return Err(());
+            }
+            let timestamp = Instant::from_millis((sys::clock::realtime() * 1000.0) as i64);
+            match iface.poll(&mut self.sockets, timestamp) {
+                Err(smoltcp::Error::Unrecognized) => {}
+                Err(e) => {
+                    debug!("Network Error: {}", e);
+                }
+                Ok(_) => {}
+            }
+            {
+                let mut socket = self.sockets.get::<TcpSocket>(self.handle);
+                if let Some(res) = f(&mut socket) {
+                    return res;
+                }
+            }
+            if let Some(wait_duration) = iface.poll_delay(&self.sockets, timestamp) {
+                let wait_duration: Duration = wait_duration.into();
+                sys::time::sleep(wait_duration.as_secs_f64());
+            }
+        }
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl FileIO for TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        self.wait(|socket| {
+            if socket.can_recv() {
+                Some(socket.recv_slice(buf).map_err(|_| ()))
+            } else if !socket.may_recv() {
+                Some(Ok(0)) // End of stream
+            } else {
+                None
+            }
+        })
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+        self.wait(|socket| {
+            if socket.can_send() {
+                Some(socket.send_slice(buf).map_err(|_| ()))
+            } else if !socket.may_send() {
+                Some(Err(()))
+            } else {
+                None
+            }
+        })
+    }
+}
@@ -77,6 +77,44 @@ impl DeviceConfig {
         data.set_bit(2, true);
         register.write(data);
     }
+
+    pub fn read_register(&self, offset: u8) -> u32 {
+        ConfigRegister::new(self.bus, self.device, self.function, offset).read()
+    }
+
+    // Return the offsets of the capabilities with the given id in the
+    // configuration space
+    pub fn capabilities(&self, id: u8) -> Vec<u8> {
+        let mut res = Vec::new();
+        if !self.status.get_bit(4) { // Capabilities list
+            return res;
+        }
+        let mut offset = self.read_register(0x34) as u8 & 0xFC;
+        while offset != 0 && res.len() < 48 { // Bound a malformed list
+            let data = self.read_register(offset);
+            if data.get_bits(0..8) as u8 == id {
+                res.push(offset);
+            }
+            offset = data.get_bits(8..16) as u8 & 0xFC;
+        }
+        res
+    }
+
+    // Return the physical address of a memory BAR, which is made of two
+    // registers for 64-bit BARs
+    pub fn memory_address(&self, bar: usize) -> Option<u64> {
+        let data = *self.base_addresses.get(bar)?;
+        if data.get_bit(0) { // I/O space
+            return None;
+        }
+        let low = (data & 0xFFFF_FFF0) as u64;
+        if data.get_bits(1..3) == 2 {
+            let high = *self.base_addresses.get(bar + 1)? as u64;
+            Some(low | high << 32)
+        } else {
+            Some(low)
+        }
+    }
 }
 
 lazy_static! {
@@ -92,6 +130,21 @@ pub fn find_device(vendor_id: u16, device_id: u16) -> Option<DeviceConfig> {
     None
 }
 
+pub fn find_devices(vendor_id: u16, device_id: u16) -> Vec<DeviceConfig> {
+    PCI_DEVICES.lock().iter().filter(|device| {
+        device.vendor_id == vendor_id && device.device_id == device_id
+    }).copied().collect()
+}
+
+pub fn find_class(class: u8, subclass: u8) -> Option<DeviceConfig> {
+    for &device in PCI_DEVICES.lock().iter() {
+        if device.class == class && device.subclass == subclass {
+            return Some(device);
+        }
+    }
+    None
+}
+
 fn check_bus(bus: u8) {
     for device in 0..32 {
         check_device(bus, device);
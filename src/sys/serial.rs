@@ -1,29 +1,53 @@
 use crate::sys;
+use crate::sys::fs::FileIO;
+use alloc::collections::vec_deque::VecDeque;
 use core::fmt;
 use core::fmt::Write;
 use lazy_static::lazy_static;
 use spin::Mutex;
 use uart_16550::SerialPort;
 use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+const INPUT_SIZE: usize = 1024;
 
 lazy_static! {
-    pub static ref SERIAL: Mutex<Serial> = Mutex::new(Serial::new(0x3F8));
+    // COM1 and COM2
+    pub static ref SERIALS: [Mutex<Serial>; 2] = [
+        Mutex::new(Serial::new(0x3F8)),
+        Mutex::new(Serial::new(0x2F8)),
+    ];
 }
 
 pub struct Serial {
     pub port: SerialPort,
+    line_status: Port<u8>,
+    input: VecDeque<u8>,
 }
 
 impl Serial {
     fn new(addr: u16) -> Self {
         let mut port = unsafe { SerialPort::new(addr) };
-        port.init();
-        Self { port }
+        port.init(); // Also enable receive interrupts
+        let line_status = Port::new(addr + 5);
+        let input = VecDeque::new();
+        Self { port, line_status, input }
     }
 
     pub fn write_byte(&mut self, byte: u8) {
         self.port.send(byte);
     }
+
+    fn is_data_ready(&mut self) -> bool {
+        unsafe { self.line_status.read() & 1 == 1 }
+    }
+
+    fn push_input(&mut self, byte: u8) {
+        if self.input.len() == INPUT_SIZE {
+            self.input.pop_front(); // Drop the oldest byte
+        }
+        self.input.push_back(byte);
+    }
 }
 
 impl fmt::Write for Serial {
@@ -35,23 +59,104 @@ impl fmt::Write for Serial {
     }
 }
 
+// A serial port opened from `/dev/serial/<n>`
+#[derive(Debug, Clone)]
+pub struct SerialDevice {
+    n: usize,
+}
+
+impl SerialDevice {
+    pub fn new(n: usize) -> Self {
+        Self { n }
+    }
+}
+
+impl FileIO for SerialDevice {
+    // Read the bytes received since the last read without blocking
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let serial = SERIALS.get(self.n).ok_or(())?;
+        interrupts::without_interrupts(|| {
+            let mut serial = serial.lock();
+            let n = buf.len().min(serial.input.len());
+            for (i, byte) in serial.input.drain(0..n).enumerate() {
+                buf[i] = byte;
+            }
+            Ok(n)
+        })
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+        let serial = SERIALS.get(self.n).ok_or(())?;
+        interrupts::without_interrupts(|| {
+            let mut serial = serial.lock();
+            for &byte in buf {
+                serial.write_byte(byte);
+            }
+            Ok(buf.len())
+        })
+    }
+}
+
 #[doc(hidden)]
 pub fn print_fmt(args: fmt::Arguments) {
     interrupts::without_interrupts(|| {
-        SERIAL.lock().write_fmt(args).expect("Could not print to serial");
+        SERIALS[0].lock().write_fmt(args).expect("Could not print to serial");
     })
 }
 
 pub fn init() {
-    sys::idt::set_irq_handler(4, interrupt_handler);
+    sys::idt::set_irq_handler(4, com1_interrupt_handler);
+    sys::idt::set_irq_handler(3, com2_interrupt_handler);
 }
 
-fn interrupt_handler() {
-    let b = SERIAL.lock().port.receive();
-    let c = match b as char {
-        '\r' => '\n',
-        '\x7F' => '\x08', // Delete => Backspace
-        c => c,
-    };
-    sys::console::key_handle(c);
+// The input of COM1 also goes to the console when it is the console backend
+fn com1_interrupt_handler() {
+    if cfg!(feature = "serial") {
+        // The lock is released before echoing the keys back to the console
+        let b = {
+            let mut serial = SERIALS[0].lock();
+            let b = serial.port.receive();
+            serial.push_input(b);
+            b
+        };
+        let c = match b as char {
+            '\r' => '\n',
+            '\x7F' => '\x08', // Delete => Backspace
+            c => c,
+        };
+        sys::console::key_handle(c);
+    } else {
+        receive(&SERIALS[0]);
+    }
+}
+
+fn com2_interrupt_handler() {
+    receive(&SERIALS[1]);
+}
+
+fn receive(serial: &Mutex<Serial>) {
+    let mut serial = serial.lock();
+    while serial.is_data_ready() {
+        let b = serial.port.receive();
+        serial.push_input(b);
+    }
+}
+
+#[test_case]
+fn test_serial_device() {
+    interrupts::without_interrupts(|| {
+        let mut serial = SERIALS[1].lock();
+        serial.input.clear();
+        for &b in b"hello" {
+            serial.push_input(b);
+        }
+    });
+    let mut dev = SerialDevice::new(1);
+    let mut buf = [0; 4];
+    assert_eq!(dev.read(&mut buf), Ok(4));
+    assert_eq!(&buf, b"hell");
+    assert_eq!(dev.read(&mut buf), Ok(1));
+    assert_eq!(buf[0], b'o');
+    assert_eq!(dev.read(&mut buf), Ok(0));
+    assert!(SerialDevice::new(2).read(&mut buf).is_err());
 }
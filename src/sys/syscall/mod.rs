@@ -8,7 +8,7 @@ use crate::sys::fs::FileInfo;
  * Dispatching system calls
  */
 
-pub fn dispatcher(n: usize, arg1: usize, arg2: usize, arg3: usize) -> usize {
+pub fn dispatcher(n: usize, arg1: usize, arg2: usize, arg3: usize, arg4: usize) -> usize {
     match n {
         number::EXIT => {
             service::exit(arg1)
@@ -29,6 +29,60 @@ pub fn dispatcher(n: usize, arg1: usize, arg2: usize, arg3: usize) -> usize {
             let path = unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr, len)) };
             service::delete(path) as usize
         }
+        number::CHMOD => {
+            let ptr = sys::process::ptr_from_addr(arg1 as u64);
+            let len = arg2;
+            let path = unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr, len)) };
+            let mode = arg3 as u16;
+            service::chmod(path, mode) as usize
+        }
+        number::CHOWN => {
+            let ptr = sys::process::ptr_from_addr(arg1 as u64);
+            let len = arg2;
+            let path = unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr, len)) };
+            let uid = arg3 as u16;
+            service::chown(path, uid) as usize
+        }
+        number::TOUCH => {
+            let ptr = sys::process::ptr_from_addr(arg1 as u64);
+            let len = arg2;
+            let path = unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr, len)) };
+            let time = arg3 as u64;
+            service::touch(path, time) as usize
+        }
+        number::LINK => {
+            let ptr = sys::process::ptr_from_addr(arg1 as u64);
+            let len = arg2;
+            let source = unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr, len)) };
+            let ptr = sys::process::ptr_from_addr(arg3 as u64);
+            let len = arg4;
+            let dest = unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr, len)) };
+            service::link(source, dest) as usize
+        }
+        number::MOUNT => {
+            let ptr = sys::process::ptr_from_addr(arg1 as u64);
+            let len = arg2;
+            let dev = unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr, len)) };
+            let ptr = sys::process::ptr_from_addr(arg3 as u64);
+            let len = arg4;
+            let path = unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr, len)) };
+            service::mount(dev, path) as usize
+        }
+        number::UMOUNT => {
+            let ptr = sys::process::ptr_from_addr(arg1 as u64);
+            let len = arg2;
+            let path = unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr, len)) };
+            service::umount(path) as usize
+        }
+        number::RENAME => {
+            let ptr = sys::process::ptr_from_addr(arg1 as u64);
+            let len = arg2;
+            let source = unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr, len)) };
+            let ptr = sys::process::ptr_from_addr(arg3 as u64);
+            let len = arg4;
+            let dest = unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr, len)) };
+            service::rename(source, dest) as usize
+        }
         number::INFO => {
             let ptr = sys::process::ptr_from_addr(arg1 as u64);
             let len = arg2;
@@ -50,6 +104,21 @@ pub fn dispatcher(n: usize, arg1: usize, arg2: usize, arg3: usize) -> usize {
             let buf = unsafe { core::slice::from_raw_parts_mut(ptr, len) };
             service::read(handle, buf) as usize
         }
+        number::READ_DIR => {
+            let handle = arg1;
+            let ptr = sys::process::ptr_from_addr(arg2 as u64);
+            let len = arg3;
+            let buf = unsafe { core::slice::from_raw_parts_mut(ptr, len) };
+            service::read_dir(handle, buf) as usize
+        }
+        number::CONTROL => {
+            let handle = arg1;
+            let request = arg2;
+            let ptr = sys::process::ptr_from_addr(arg3 as u64);
+            let len = arg4;
+            let buf = unsafe { core::slice::from_raw_parts_mut(ptr, len) };
+            service::control(handle, request, buf) as usize
+        }
         number::WRITE => {
             let handle = arg1;
             let ptr = sys::process::ptr_from_addr(arg2 as u64);
@@ -127,6 +196,17 @@ pub unsafe fn syscall3(n: usize, arg1: usize, arg2: usize, arg3: usize) -> usize
     res
 }
 
+#[doc(hidden)]
+pub unsafe fn syscall4(n: usize, arg1: usize, arg2: usize, arg3: usize, arg4: usize) -> usize {
+    let res: usize;
+    asm!(
+        "int 0x80", in("rax") n,
+        in("rdi") arg1, in("rsi") arg2, in("rdx") arg3, in("rcx") arg4,
+        lateout("rax") res
+    );
+    res
+}
+
 #[macro_export]
 macro_rules! syscall {
     ($n:expr) => (
@@ -141,4 +221,7 @@ macro_rules! syscall {
     ($n:expr, $a1:expr, $a2:expr, $a3:expr) => (
         $crate::sys::syscall::syscall3(
             $n as usize, $a1 as usize, $a2 as usize, $a3 as usize));
+    ($n:expr, $a1:expr, $a2:expr, $a3:expr, $a4:expr) => (
+        $crate::sys::syscall::syscall4(
+            $n as usize, $a1 as usize, $a2 as usize, $a3 as usize, $a4 as usize));
 }
@@ -10,3 +10,12 @@ pub const SLEEP:    usize = 0x9;
 pub const UPTIME:   usize = 0xA;
 pub const REALTIME: usize = 0xB;
 pub const DELETE:   usize = 0xC;
+pub const CHMOD:    usize = 0xD;
+pub const CHOWN:    usize = 0xE;
+pub const TOUCH:    usize = 0xF;
+pub const LINK:     usize = 0x10;
+pub const MOUNT:    usize = 0x11;
+pub const UMOUNT:   usize = 0x12;
+pub const RENAME:   usize = 0x13;
+pub const READ_DIR: usize = 0x14;
+pub const CONTROL:  usize = 0x15;
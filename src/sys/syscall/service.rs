@@ -1,8 +1,9 @@
 use crate::sys;
-use crate::sys::fs::FileInfo;
+use crate::sys::fs::{ControlRequest, FileInfo};
 use crate::sys::fs::FileIO;
 use crate::sys::process::Process;
 use alloc::vec;
+use core::convert::TryFrom;
 
 pub fn exit(_code: usize) -> usize {
     sys::process::exit();
@@ -29,6 +30,98 @@ pub fn delete(path: &str) -> isize {
     }
 }
 
+pub fn chmod(path: &str, mode: u16) -> isize {
+    let path = match sys::fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(_) => return -1,
+    };
+    if sys::fs::chmod(&path, mode).is_ok() {
+        0
+    } else {
+        -1
+    }
+}
+
+pub fn chown(path: &str, uid: u16) -> isize {
+    let path = match sys::fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(_) => return -1,
+    };
+    if sys::fs::chown(&path, uid).is_ok() {
+        0
+    } else {
+        -1
+    }
+}
+
+pub fn touch(path: &str, time: u64) -> isize {
+    let path = match sys::fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(_) => return -1,
+    };
+    if sys::fs::touch(&path, time).is_ok() {
+        0
+    } else {
+        -1
+    }
+}
+
+pub fn link(source: &str, dest: &str) -> isize {
+    let source = match sys::fs::canonicalize(source) {
+        Ok(path) => path,
+        Err(_) => return -1,
+    };
+    let dest = match sys::fs::canonicalize(dest) {
+        Ok(path) => path,
+        Err(_) => return -1,
+    };
+    if sys::fs::link(&source, &dest).is_ok() {
+        0
+    } else {
+        -1
+    }
+}
+
+pub fn mount(dev: &str, path: &str) -> isize {
+    let path = match sys::fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(_) => return -1,
+    };
+    if sys::fs::mount(dev, &path).is_ok() {
+        0
+    } else {
+        -1
+    }
+}
+
+pub fn umount(path: &str) -> isize {
+    let path = match sys::fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(_) => return -1,
+    };
+    if sys::fs::umount(&path).is_ok() {
+        0
+    } else {
+        -1
+    }
+}
+
+pub fn rename(source: &str, dest: &str) -> isize {
+    let source = match sys::fs::canonicalize(source) {
+        Ok(path) => path,
+        Err(_) => return -1,
+    };
+    let dest = match sys::fs::canonicalize(dest) {
+        Ok(path) => path,
+        Err(_) => return -1,
+    };
+    if sys::fs::rename(&source, &dest).is_ok() {
+        0
+    } else {
+        -1
+    }
+}
+
 pub fn info(path: &str, info: &mut FileInfo) -> isize {
     let path = match sys::fs::canonicalize(path) {
         Ok(path) => path,
@@ -73,6 +166,41 @@ pub fn read(handle: usize, buf: &mut [u8]) -> isize {
     -1
 }
 
+// Read the next entry of an opened dir into a serialized file info, and return
+// its length or 0 after the last entry.
+pub fn read_dir(handle: usize, buf: &mut [u8]) -> isize {
+    if let Some(mut file) = sys::process::file_handle(handle) {
+        match file.next_entry() {
+            Ok(Some(info)) => {
+                let bytes = info.as_bytes();
+                let n = bytes.len();
+                if n <= buf.len() {
+                    buf[0..n].copy_from_slice(&bytes);
+                    sys::process::update_file_handle(handle, *file);
+                    return n as isize;
+                }
+            }
+            Ok(None) => return 0,
+            Err(()) => {}
+        }
+    }
+    -1
+}
+
+// Send a control request to a device handle, and return the length of the
+// result written back into the buffer
+pub fn control(handle: usize, request: usize, buf: &mut [u8]) -> isize {
+    if let Ok(request) = ControlRequest::try_from(request) {
+        if let Some(mut file) = sys::process::file_handle(handle) {
+            if let Ok(bytes) = file.control(request, buf) {
+                sys::process::update_file_handle(handle, *file);
+                return bytes as isize;
+            }
+        }
+    }
+    -1
+}
+
 pub fn write(handle: usize, buf: &mut [u8]) -> isize {
     if let Some(mut file) = sys::process::file_handle(handle) {
         if let Ok(bytes) = file.write(buf) {
@@ -32,6 +32,7 @@ lazy_static! {
     pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
         cursor: [0; 2],
         writer: [0; 2],
+        mouse: None,
         color_code: ColorCode::new(FG, BG),
         buffer: unsafe { &mut *(0xB8000 as *mut Buffer) },
     });
@@ -65,6 +66,7 @@ struct Buffer {
 pub struct Writer {
     cursor: [usize; 2], // x, y
     writer: [usize; 2], // x, y
+    mouse: Option<[usize; 2]>, // x, y
     color_code: ColorCode,
     buffer: &'static mut Buffer,
 }
@@ -125,6 +127,24 @@ impl Writer {
         }
     }
 
+    // The cell of the mouse cursor is drawn by swapping its colors, which is
+    // undone before writing to the screen and redone after.
+    fn toggle_mouse_cursor(&mut self) {
+        if let Some([x, y]) = self.mouse {
+            unsafe {
+                let mut c = core::ptr::read_volatile(&self.buffer.chars[y][x]);
+                c.color_code = ColorCode(c.color_code.0.rotate_left(4));
+                core::ptr::write_volatile(&mut self.buffer.chars[y][x], c);
+            }
+        }
+    }
+
+    pub fn set_mouse_cursor(&mut self, pos: Option<(usize, usize)>) {
+        self.toggle_mouse_cursor();
+        self.mouse = pos.map(|(x, y)| [x.min(BUFFER_WIDTH - 1), y.min(BUFFER_HEIGHT - 1)]);
+        self.toggle_mouse_cursor();
+    }
+
     fn write_byte(&mut self, byte: u8) {
         match byte {
             0x0A => { // Newline
@@ -413,9 +433,11 @@ impl Perform for Writer {
 impl fmt::Write for Writer {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         let mut parser = PARSER.lock();
+        self.toggle_mouse_cursor();
         for byte in s.bytes() {
             parser.advance(self, byte);
         }
+        self.toggle_mouse_cursor();
         let (x, y) = self.writer_position();
         self.set_cursor_position(x, y);
         Ok(())
@@ -464,6 +486,12 @@ pub fn set_font(font: &Font) {
     })
 }
 
+pub fn set_mouse_cursor(pos: Option<(usize, usize)>) {
+    interrupts::without_interrupts(|| {
+        WRITER.lock().set_mouse_cursor(pos)
+    })
+}
+
 pub fn set_palette(palette: Palette) {
     interrupts::without_interrupts(|| {
         WRITER.lock().set_palette(palette)
@@ -0,0 +1,511 @@
+use crate::sys;
+use crate::sys::allocator::PhysBuf;
+use alloc::vec::Vec;
+use bit_field::BitField;
+use core::fmt;
+use core::hint::spin_loop;
+use core::sync::atomic::{fence, Ordering};
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+use x86_64::PhysAddr;
+
+// VirtIO block devices using the legacy PCI interface of transitional devices
+// or the PCI capabilities of modern devices
+//
+// See "Virtual I/O Device (VIRTIO) Version 1.1" (2019), sections 2.6 "Split
+// Virtqueues", 4.1.4 "Virtio Structure PCI Capabilities", 4.1.4.8 "Legacy
+// Interfaces: A Note on PCI Device Layout" and 5.2 "Block Device"
+
+pub const BLOCK_SIZE: usize = 512;
+
+// Maximum number of blocks transferred by a single request
+pub const MAX_BLOCKS: usize = 64;
+
+const VENDOR_ID: u16 = 0x1AF4;
+const TRANSITIONAL_DEVICE_ID: u16 = 0x1001;
+const MODERN_DEVICE_ID: u16 = 0x1042;
+
+// Alignment of the used ring and of the queue address given to the device
+const QUEUE_ALIGN: usize = 4096;
+
+// Size of the header of a request
+const HEADER_SIZE: usize = 16;
+
+// Offset of the status byte written by the device at the end of a request
+const STATUS_OFFSET: usize = HEADER_SIZE + MAX_BLOCKS * BLOCK_SIZE;
+
+#[repr(u16)]
+enum Register {
+    DeviceFeatures = 0x00,
+    GuestFeatures = 0x04,
+    QueueAddress = 0x08,
+    QueueSize = 0x0C,
+    QueueSelect = 0x0E,
+    QueueNotify = 0x10,
+    DeviceStatus = 0x12,
+    IsrStatus = 0x13,
+    Capacity = 0x14, // First field of the device configuration
+}
+
+// Registers of the common configuration structure of modern devices
+#[repr(u64)]
+enum CommonRegister {
+    DeviceFeatureSelect = 0x00,
+    DeviceFeature = 0x04,
+    DriverFeatureSelect = 0x08,
+    DriverFeature = 0x0C,
+    DeviceStatus = 0x14,
+    QueueSelect = 0x16,
+    QueueSize = 0x18,
+    QueueEnable = 0x1C,
+    QueueNotifyOff = 0x1E,
+    QueueDesc = 0x20,
+    QueueDriver = 0x28,
+    QueueDevice = 0x30,
+}
+
+// Vendor specific PCI capability and the types of structures it can locate
+const CAP_VENDOR: u8 = 0x09;
+const CAP_COMMON_CFG: u8 = 1;
+const CAP_NOTIFY_CFG: u8 = 2;
+const CAP_ISR_CFG: u8 = 3;
+const CAP_DEVICE_CFG: u8 = 4;
+
+#[repr(u8)]
+enum Status {
+    Acknowledge = 1,
+    Driver = 2,
+    DriverOk = 4,
+    FeaturesOk = 8,
+    Failed = 128,
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq)]
+enum Request {
+    In = 0,
+    Out = 1,
+}
+
+// Descriptor flags
+const DESC_NEXT: u16 = 1;
+const DESC_WRITE: u16 = 2;
+
+// Feature bit of read-only devices
+const FEATURE_RO: usize = 5;
+
+// Feature bit required from modern devices
+const FEATURE_VERSION_1: usize = 32;
+
+lazy_static! {
+    static ref DEVICES: Mutex<Vec<Device>> = Mutex::new(Vec::new());
+}
+
+// A split virtqueue made of a descriptor table, an available ring written by
+// the driver, and a used ring written by the device, which must start on the
+// next aligned boundary.
+struct Virtqueue {
+    mem: PhysBuf,
+    offset: usize, // Aligned start of the queue in the buffer
+    size: usize,
+    avail: usize,
+    used: usize,
+    last_used_idx: u16,
+}
+
+impl Virtqueue {
+    fn new(size: usize) -> Self {
+        let avail = 16 * size;
+        let used = align(avail + 6 + 2 * size);
+        let len = used + align(6 + 8 * size);
+        let mem = PhysBuf::new(len + QUEUE_ALIGN);
+        let offset = (QUEUE_ALIGN - (mem.addr() as usize % QUEUE_ALIGN)) % QUEUE_ALIGN;
+        Self { mem, offset, size, avail, used, last_used_idx: 0 }
+    }
+
+    fn addr(&self) -> u64 {
+        self.mem.addr() + self.offset as u64
+    }
+
+    fn set_descriptor(&mut self, i: usize, addr: u64, len: usize, flags: u16, next: u16) {
+        let j = self.offset + 16 * i;
+        self.mem[j..j + 8].copy_from_slice(&addr.to_le_bytes());
+        self.mem[j + 8..j + 12].copy_from_slice(&(len as u32).to_le_bytes());
+        self.mem[j + 12..j + 14].copy_from_slice(&flags.to_le_bytes());
+        self.mem[j + 14..j + 16].copy_from_slice(&next.to_le_bytes());
+    }
+
+    // Make a chain of descriptors available to the device
+    fn push(&mut self, head: u16) {
+        let idx = self.read_u16(self.avail + 2);
+        self.write_u16(self.avail + 4 + 2 * (idx as usize % self.size), head);
+        fence(Ordering::SeqCst);
+        self.write_u16(self.avail + 2, idx.wrapping_add(1));
+        fence(Ordering::SeqCst);
+    }
+
+    // Return true if the device has used a new chain of descriptors
+    fn pop(&mut self) -> bool {
+        let idx = self.read_u16(self.used + 2);
+        if idx != self.last_used_idx {
+            self.last_used_idx = self.last_used_idx.wrapping_add(1);
+            true
+        } else {
+            false
+        }
+    }
+
+    // The used ring is written by the device behind the back of the compiler
+    fn read_u16(&self, i: usize) -> u16 {
+        let ptr = self.mem[self.offset + i..].as_ptr() as *const u16;
+        u16::from_le(unsafe { core::ptr::read_volatile(ptr) })
+    }
+
+    fn write_u16(&mut self, i: usize, value: u16) {
+        let ptr = self.mem[self.offset + i..].as_mut_ptr() as *mut u16;
+        unsafe { core::ptr::write_volatile(ptr, value.to_le()) }
+    }
+}
+
+// Memory mapped structures of a modern device
+struct Mmio {
+    common: u64,
+    notify: u64, // Notification address of the queue once it is set up
+    notify_multiplier: u32,
+    isr: u64,
+    device: u64,
+}
+
+impl Mmio {
+    // Map the first structure of each type found in the capabilities
+    fn new(pci: &sys::pci::DeviceConfig) -> Option<Self> {
+        let (mut common, mut notify, mut isr, mut device) = (None, None, None, None);
+        let mut notify_multiplier = 0;
+        for offset in pci.capabilities(CAP_VENDOR) {
+            if offset > 0xFF - 20 { // Larger than the configuration space
+                continue;
+            }
+            let cfg_type = pci.read_register(offset).get_bits(24..32) as u8;
+            let bar = pci.read_register(offset + 4).get_bits(0..8) as usize;
+            let start = pci.read_register(offset + 8) as u64;
+            let len = pci.read_register(offset + 12) as u64;
+            let map = || {
+                let base = pci.memory_address(bar).filter(|&base| base != 0)?;
+                sys::mem::map_mmio(PhysAddr::new(base + start), len).map(|addr| addr.as_u64())
+            };
+            match cfg_type {
+                CAP_COMMON_CFG if common.is_none() => common = map(),
+                CAP_NOTIFY_CFG if notify.is_none() => {
+                    notify = map();
+                    notify_multiplier = pci.read_register(offset + 16);
+                }
+                CAP_ISR_CFG if isr.is_none() => isr = map(),
+                CAP_DEVICE_CFG if device.is_none() => device = map(),
+                _ => {}
+            }
+        }
+        Some(Self { common: common?, notify: notify?, notify_multiplier, isr: isr?, device: device? })
+    }
+
+    fn read<T: Copy>(&self, register: CommonRegister) -> T {
+        mmio_read(self.common + register as u64)
+    }
+
+    fn write<T>(&self, register: CommonRegister, value: T) {
+        mmio_write(self.common + register as u64, value)
+    }
+}
+
+// Registers of a device in the I/O space given by the BAR0 of transitional
+// devices, or in the memory mapped structures of modern devices
+enum Transport {
+    Legacy(u16),
+    Modern(Mmio),
+}
+
+struct Device {
+    transport: Transport,
+    queue: Virtqueue,
+    buf: PhysBuf, // Header, data, and status of a request
+    blocks: u64,
+    read_only: bool,
+}
+
+impl Device {
+    fn legacy(io_base: u16) -> Option<Self> {
+        let mut status: Port<u8> = Port::new(io_base + Register::DeviceStatus as u16);
+        let mut device_features: Port<u32> = Port::new(io_base + Register::DeviceFeatures as u16);
+        let mut guest_features: Port<u32> = Port::new(io_base + Register::GuestFeatures as u16);
+        let mut queue_select: Port<u16> = Port::new(io_base + Register::QueueSelect as u16);
+        let mut queue_size: Port<u16> = Port::new(io_base + Register::QueueSize as u16);
+        let mut queue_address: Port<u32> = Port::new(io_base + Register::QueueAddress as u16);
+        let mut capacity: Port<u32> = Port::new(io_base + Register::Capacity as u16);
+        let mut capacity_high: Port<u32> = Port::new(io_base + Register::Capacity as u16 + 4);
+
+        unsafe {
+            status.write(0); // Reset
+            status.write(Status::Acknowledge as u8);
+            status.write(Status::Acknowledge as u8 | Status::Driver as u8);
+
+            // No optional feature is used
+            let features = device_features.read();
+            guest_features.write(0);
+
+            // The size of the queue is fixed by legacy devices
+            queue_select.write(0);
+            let size = queue_size.read() as usize;
+            if size == 0 {
+                status.write(Status::Failed as u8);
+                return None;
+            }
+            let queue = Virtqueue::new(size);
+            queue_address.write((queue.addr() / QUEUE_ALIGN as u64) as u32);
+            status.write(Status::Acknowledge as u8 | Status::Driver as u8 | Status::DriverOk as u8);
+
+            let blocks = (capacity.read() as u64) | (capacity_high.read() as u64) << 32;
+            let read_only = features.get_bit(FEATURE_RO);
+            let buf = PhysBuf::new(STATUS_OFFSET + 1);
+            let transport = Transport::Legacy(io_base);
+            Some(Self { transport, queue, buf, blocks, read_only })
+        }
+    }
+
+    fn modern(pci: &sys::pci::DeviceConfig) -> Option<Self> {
+        let mut mmio = Mmio::new(pci)?;
+        let status = Status::Acknowledge as u8 | Status::Driver as u8;
+        mmio.write(CommonRegister::DeviceStatus, 0u8); // Reset
+        mmio.write(CommonRegister::DeviceStatus, Status::Acknowledge as u8);
+        mmio.write(CommonRegister::DeviceStatus, status);
+
+        let mut features = 0u64;
+        for i in 0..2 {
+            mmio.write(CommonRegister::DeviceFeatureSelect, i as u32);
+            let bits: u32 = mmio.read(CommonRegister::DeviceFeature);
+            features |= (bits as u64) << (32 * i);
+        }
+
+        // No optional feature is used
+        let status = status | Status::FeaturesOk as u8;
+        let mut is_accepted = features.get_bit(FEATURE_VERSION_1);
+        if is_accepted {
+            mmio.write(CommonRegister::DriverFeatureSelect, 0u32);
+            mmio.write(CommonRegister::DriverFeature, 0u32);
+            mmio.write(CommonRegister::DriverFeatureSelect, 1u32);
+            mmio.write(CommonRegister::DriverFeature, 1u32 << (FEATURE_VERSION_1 - 32));
+            mmio.write(CommonRegister::DeviceStatus, status);
+            let res: u8 = mmio.read(CommonRegister::DeviceStatus);
+            is_accepted = res & Status::FeaturesOk as u8 != 0;
+        }
+        mmio.write(CommonRegister::QueueSelect, 0u16);
+        let size: u16 = mmio.read(CommonRegister::QueueSize);
+        if !is_accepted || size == 0 {
+            mmio.write(CommonRegister::DeviceStatus, Status::Failed as u8);
+            return None;
+        }
+
+        // The parts of the queue are given separately to modern devices
+        let queue = Virtqueue::new(size as usize);
+        let addr = queue.addr();
+        for (register, addr) in [
+            (CommonRegister::QueueDesc, addr),
+            (CommonRegister::QueueDriver, addr + queue.avail as u64),
+            (CommonRegister::QueueDevice, addr + queue.used as u64),
+        ] {
+            let offset = register as u64;
+            mmio_write(mmio.common + offset, addr as u32);
+            mmio_write(mmio.common + offset + 4, (addr >> 32) as u32);
+        }
+        let notify_off: u16 = mmio.read(CommonRegister::QueueNotifyOff);
+        mmio.notify += notify_off as u64 * mmio.notify_multiplier as u64;
+        mmio.write(CommonRegister::QueueEnable, 1u16);
+        mmio.write(CommonRegister::DeviceStatus, status | Status::DriverOk as u8);
+
+        let capacity: u32 = mmio_read(mmio.device);
+        let capacity_high: u32 = mmio_read(mmio.device + 4);
+        let blocks = (capacity as u64) | (capacity_high as u64) << 32;
+        let read_only = features.get_bit(FEATURE_RO);
+        let buf = PhysBuf::new(STATUS_OFFSET + 1);
+        let transport = Transport::Modern(mmio);
+        Some(Self { transport, queue, buf, blocks, read_only })
+    }
+
+    fn notify(&self) {
+        match &self.transport {
+            Transport::Legacy(io_base) => unsafe {
+                Port::<u16>::new(io_base + Register::QueueNotify as u16).write(0);
+            }
+            Transport::Modern(mmio) => mmio_write(mmio.notify, 0u16),
+        }
+    }
+
+    // Reading the ISR status clears the interrupt
+    fn clear_interrupt(&self) {
+        match &self.transport {
+            Transport::Legacy(io_base) => unsafe {
+                Port::<u8>::new(io_base + Register::IsrStatus as u16).read();
+            }
+            Transport::Modern(mmio) => {
+                mmio_read::<u8>(mmio.isr);
+            }
+        }
+    }
+
+    fn read(&mut self, block: u64, buf: &mut [u8]) -> Result<(), ()> {
+        let n = buf.len();
+        self.transfer(Request::In, block, n)?;
+        buf.copy_from_slice(&self.buf[HEADER_SIZE..HEADER_SIZE + n]);
+        Ok(())
+    }
+
+    fn write(&mut self, block: u64, buf: &[u8]) -> Result<(), ()> {
+        if self.read_only {
+            return Err(());
+        }
+        let n = buf.len();
+        self.buf[HEADER_SIZE..HEADER_SIZE + n].copy_from_slice(buf);
+        self.transfer(Request::Out, block, n)
+    }
+
+    // Send a request with a chain of three descriptors for its header, its
+    // data, and its status, then poll the used ring for its completion.
+    fn transfer(&mut self, request: Request, block: u64, len: usize) -> Result<(), ()> {
+        if sys::fs::blocks_count(len, BLOCK_SIZE, MAX_BLOCKS).is_err() || block + (len / BLOCK_SIZE) as u64 > self.blocks {
+            return Err(());
+        }
+        self.buf[0..4].copy_from_slice(&(request as u32).to_le_bytes());
+        self.buf[4..8].copy_from_slice(&0u32.to_le_bytes()); // Reserved
+        self.buf[8..16].copy_from_slice(&block.to_le_bytes());
+        self.buf[STATUS_OFFSET] = 0xFF;
+
+        let addr = self.buf.addr();
+        let flags = if request == Request::In { DESC_NEXT | DESC_WRITE } else { DESC_NEXT };
+        self.queue.set_descriptor(0, addr, HEADER_SIZE, DESC_NEXT, 1);
+        self.queue.set_descriptor(1, addr + HEADER_SIZE as u64, len, flags, 2);
+        self.queue.set_descriptor(2, addr + STATUS_OFFSET as u64, 1, DESC_WRITE, 0);
+        self.queue.push(0);
+        self.notify();
+
+        let start = sys::clock::uptime();
+        while !self.queue.pop() {
+            if sys::clock::uptime() - start > 1.0 {
+                debug!("VirtIO hanged while waiting for request");
+                return Err(());
+            }
+            spin_loop();
+        }
+        fence(Ordering::SeqCst);
+        self.clear_interrupt();
+        if self.buf[STATUS_OFFSET] == 0 {
+            Ok(())
+        } else {
+            debug!("VirtIO request error: {}", self.buf[STATUS_OFFSET]);
+            Err(())
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Drive {
+    pub id: usize,
+    blocks: u64,
+    read_only: bool,
+}
+
+impl Drive {
+    pub fn open(id: usize) -> Option<Self> {
+        let devices = DEVICES.lock();
+        devices.get(id).map(|dev| {
+            Self { id, blocks: dev.blocks, read_only: dev.read_only }
+        })
+    }
+
+    pub const fn block_size(&self) -> u32 {
+        BLOCK_SIZE as u32
+    }
+
+    pub fn block_count(&self) -> u64 {
+        self.blocks
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+}
+
+impl fmt::Display for Drive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes = self.block_size() as usize * self.block_count() as usize;
+        let (size, unit) = sys::fs::humanized_size(bytes);
+        write!(f, "VirtIO Block Device ({} {})", size, unit)
+    }
+}
+
+pub fn list() -> Vec<Drive> {
+    let n = DEVICES.lock().len();
+    (0..n).filter_map(Drive::open).collect()
+}
+
+// Read blocks into a buffer of a multiple of the block size, splitting the
+// transfer into requests of up to `MAX_BLOCKS`.
+pub fn read(id: usize, block: u64, buf: &mut [u8]) -> Result<(), ()> {
+    let mut devices = DEVICES.lock();
+    let dev = devices.get_mut(id).ok_or(())?;
+    sys::fs::read_chunks(block, buf, MAX_BLOCKS * BLOCK_SIZE, BLOCK_SIZE, |addr, chunk| {
+        dev.read(addr, chunk)
+    })
+}
+
+pub fn write(id: usize, block: u64, buf: &[u8]) -> Result<(), ()> {
+    let mut devices = DEVICES.lock();
+    let dev = devices.get_mut(id).ok_or(())?;
+    sys::fs::write_chunks(block, buf, MAX_BLOCKS * BLOCK_SIZE, BLOCK_SIZE, |addr, chunk| {
+        dev.write(addr, chunk)
+    })
+}
+
+pub fn init() {
+    for &device_id in [TRANSITIONAL_DEVICE_ID, MODERN_DEVICE_ID].iter() {
+        for mut pci_device in sys::pci::find_devices(VENDOR_ID, device_id) {
+            pci_device.enable_bus_mastering();
+
+            let dev = if device_id == TRANSITIONAL_DEVICE_ID {
+                let io_base = (pci_device.base_addresses[0] as u16) & 0xFFFC;
+                Device::legacy(io_base)
+            } else {
+                Device::modern(&pci_device)
+            };
+            if let Some(dev) = dev {
+                DEVICES.lock().push(dev);
+            }
+        }
+    }
+    for drive in list() {
+        log!("VirtIO {} {}\n", drive.id, drive);
+    }
+}
+
+fn align(len: usize) -> usize {
+    (len + QUEUE_ALIGN - 1) / QUEUE_ALIGN * QUEUE_ALIGN
+}
+
+// The device writes to the structures behind the back of the compiler
+fn mmio_read<T: Copy>(addr: u64) -> T {
+    unsafe { core::ptr::read_volatile(addr as *const T) }
+}
+
+fn mmio_write<T>(addr: u64, value: T) {
+    unsafe { core::ptr::write_volatile(addr as *mut T, value) }
+}
+
+#[test_case]
+fn test_virtqueue_layout() {
+    assert_eq!(align(0), 0);
+    assert_eq!(align(1), QUEUE_ALIGN);
+    assert_eq!(align(QUEUE_ALIGN), QUEUE_ALIGN);
+
+    let queue = Virtqueue::new(256);
+    assert_eq!(queue.addr() % QUEUE_ALIGN as u64, 0);
+    assert_eq!(queue.used, 2 * QUEUE_ALIGN);
+    assert_eq!(queue.read_u16(queue.used + 2), 0);
+}
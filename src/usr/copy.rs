@@ -1,24 +1,51 @@
 use crate::usr;
 use crate::api::fs;
 
+use alloc::format;
+
 pub fn main(args: &[&str]) -> usr::shell::ExitCode {
-    if args.len() != 3 {
-        eprintln!("Usage: copy <source> <dest>");
+    if args.len() < 3 {
+        eprintln!("Usage: copy <source>... <dest>");
         return usr::shell::ExitCode::CommandError;
     }
+    for_each_source(args, copy_file)
+}
 
-    let source = args[1];
-    let dest = args[2];
+// Call the function with each source of the args and its dest, until it
+// fails. The sources are put into the dest when it is a dir, which is
+// required when there are more than one of them like in `copy *.txt /tmp`
+pub fn for_each_source<F>(args: &[&str], mut f: F) -> usr::shell::ExitCode where F: FnMut(&str, &str) -> usr::shell::ExitCode {
+    let n = args.len();
+    let dest = args[n - 1];
+    let is_dir = fs::is_dir(dest);
+    if n > 3 && !is_dir {
+        eprintln!("Could not find dir '{}'", dest);
+        return usr::shell::ExitCode::CommandError;
+    }
 
-    if let Ok(contents) = fs::read_to_bytes(source) {
-        if fs::write(dest, &contents).is_ok() {
-            usr::shell::ExitCode::CommandSuccessful
+    for &source in &args[1..(n - 1)] {
+        let dest = if is_dir {
+            format!("{}/{}", dest.trim_end_matches('/'), fs::filename(source))
         } else {
+            dest.into()
+        };
+        let code = f(source, &dest);
+        if code != usr::shell::ExitCode::CommandSuccessful {
+            return code;
+        }
+    }
+    usr::shell::ExitCode::CommandSuccessful
+}
+
+pub fn copy_file(source: &str, dest: &str) -> usr::shell::ExitCode {
+    if let Ok(contents) = fs::read_to_bytes(source) {
+        if fs::write(dest, &contents).is_err() {
             eprintln!("Could not write to '{}'", dest);
-            usr::shell::ExitCode::CommandError
+            return usr::shell::ExitCode::CommandError;
         }
     } else {
         eprintln!("File not found '{}'", source);
-        usr::shell::ExitCode::CommandError
+        return usr::shell::ExitCode::CommandError;
     }
+    usr::shell::ExitCode::CommandSuccessful
 }
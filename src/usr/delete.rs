@@ -3,11 +3,22 @@ use crate::api::syscall;
 use crate::api::fs;
 
 pub fn main(args: &[&str]) -> usr::shell::ExitCode {
-    if args.len() != 2 {
+    if args.len() < 2 {
         return usr::shell::ExitCode::CommandError;
     }
 
-    let mut pathname = args[1];
+    // Every path is deleted even if one of them could not be
+    let mut res = usr::shell::ExitCode::CommandSuccessful;
+    for pathname in &args[1..] {
+        if delete(pathname) != usr::shell::ExitCode::CommandSuccessful {
+            res = usr::shell::ExitCode::CommandError;
+        }
+    }
+    res
+}
+
+fn delete(pathname: &str) -> usr::shell::ExitCode {
+    let mut pathname = pathname;
 
     // The commands `delete /usr/alice/` and `delete /usr/alice` are equivalent,
     // but `delete /` should not be modified.
@@ -15,13 +26,16 @@ pub fn main(args: &[&str]) -> usr::shell::ExitCode {
         pathname = pathname.trim_end_matches('/');
     }
 
-    if !fs::exists(pathname) {
+    // A symbolic link is deleted without looking at its target
+    let is_link = fs::read_link(pathname).is_ok();
+
+    if !is_link && !fs::exists(pathname) {
         eprintln!("File not found '{}'", pathname);
         return usr::shell::ExitCode::CommandError;
     }
 
     if let Some(info) = syscall::info(pathname) {
-        if info.is_dir() && info.size() > 0 {
+        if !is_link && info.is_dir() && info.size() > 0 {
             eprintln!("Directory '{}' not empty", pathname);
             return usr::shell::ExitCode::CommandError;
         }
@@ -1,13 +1,19 @@
 use crate::{sys, usr};
 use crate::api::console::Style;
+use crate::api::fs;
 use crate::api::io;
 use crate::sys::ata::Drive;
+use crate::sys::fs::{BlockDevice, BlockDeviceIO, FileIO, OpenFlag, SnapshotError};
+use crate::sys::net::tcp::TcpStream;
 
+use alloc::boxed::Box;
 use alloc::format;
 use alloc::string::String;
 use alloc::string::ToString;
 use alloc::vec::Vec;
 use alloc::vec;
+use core::str::FromStr;
+use smoltcp::wire::IpAddress;
 
 pub fn main(args: &[&str]) -> usr::shell::ExitCode {
     if args.len() == 1 {
@@ -16,6 +22,11 @@ pub fn main(args: &[&str]) -> usr::shell::ExitCode {
     match args[1] {
         "format" if args.len() == 3 => format(args[2]),
         "erase" if args.len() == 3 => erase(args[2]),
+        "check" => check(&args[2..]),
+        "migrate" => migrate(),
+        "snapshot" if args.len() == 4 => snapshot(args[2], args[3]),
+        "restore" if args.len() == 4 => restore(args[2], args[3]),
+        "mem" if args.len() == 4 => mem(args[2], args[3]),
         "usage" => usage(),
         "list" => list(),
         _ => help(),
@@ -33,10 +44,23 @@ fn parse_disk_path(pathname: &str) -> Result<(u8, u8), String> {
 }
 
 fn format(pathname: &str) -> usr::shell::ExitCode {
-    match parse_disk_path(pathname) {
-        Ok((bus, dsk)) => {
-            sys::fs::mount_ata(bus, dsk);
-            sys::fs::format_ata();
+    match open_disk(pathname) {
+        Ok(dev) => {
+            if dev.is_read_only() {
+                eprintln!("Could not format read-only disk");
+                return usr::shell::ExitCode::CommandError;
+            }
+            // MFS block addresses are limited to 32 bits
+            if dev.block_count() as u64 > u32::MAX as u64 {
+                let max = (u32::MAX as usize) * dev.block_size();
+                let (size, unit) = sys::fs::humanized_size(max);
+                eprintln!("Could not format disk larger than {} {}", size, unit);
+                return usr::shell::ExitCode::CommandError;
+            }
+            if sys::fs::format_disk(dev).is_err() {
+                eprintln!("Could not format disk");
+                return usr::shell::ExitCode::CommandError;
+            }
             println!("Disk successfully formatted");
             println!("MFS is now mounted to '/'");
             usr::shell::ExitCode::CommandSuccessful
@@ -83,14 +107,273 @@ fn erase(pathname: &str) -> usr::shell::ExitCode {
     }
 }
 
+fn check(args: &[&str]) -> usr::shell::ExitCode {
+    let mut repair = false;
+    for arg in args {
+        match *arg {
+            "-r" | "--repair" => repair = true,
+            _ => return help(),
+        }
+    }
+    match sys::fs::check(repair) {
+        Ok(issues) => {
+            for issue in &issues {
+                println!("{}", issue);
+            }
+            let n = issues.len();
+            let s = if n == 1 { "" } else { "s" };
+            if n == 0 {
+                println!("No issue found");
+                usr::shell::ExitCode::CommandSuccessful
+            } else if repair {
+                println!("Repaired {} issue{}", n, s);
+                usr::shell::ExitCode::CommandSuccessful
+            } else {
+                println!("Found {} issue{}", n, s);
+                usr::shell::ExitCode::CommandError
+            }
+        }
+        Err(()) => {
+            eprintln!("MFS is not mounted to '/'");
+            usr::shell::ExitCode::CommandError
+        }
+    }
+}
+
+fn migrate() -> usr::shell::ExitCode {
+    if sys::fs::disk_version().is_none() {
+        eprintln!("MFS is not mounted to '/'");
+        return usr::shell::ExitCode::CommandError;
+    }
+    if sys::fs::migrate().is_ok() {
+        println!("Filesystem successfully migrated");
+        usr::shell::ExitCode::CommandSuccessful
+    } else {
+        eprintln!("Could not migrate filesystem");
+        usr::shell::ExitCode::CommandError
+    }
+}
+
+// The target of a snapshot is either a file or a "<host>:<port>" address
+fn is_remote(target: &str) -> bool {
+    !target.starts_with('/') && target.contains(':')
+}
+
+fn connect(target: &str) -> Result<TcpStream, String> {
+    let i = target.rfind(':').unwrap();
+    let (host, port) = (&target[..i], &target[(i + 1)..]);
+    let port: u16 = port.parse().or(Err("Could not parse <port>".to_string()))?;
+    let addr = if host.ends_with(char::is_numeric) {
+        IpAddress::from_str(host).or(Err(format!("Could not parse address '{}'", host)))?
+    } else {
+        usr::host::resolve(host).or(Err(format!("Could not resolve host '{}'", host)))?
+    };
+    TcpStream::connect(addr, port).or(Err(format!("Could not connect to {}:{}", addr, port)))
+}
+
+fn open_disk(pathname: &str) -> Result<BlockDevice, String> {
+    sys::fs::open_device(pathname).ok_or(format!("Could not find disk at '{}'", pathname))
+}
+
+fn confirm() -> bool {
+    print!("Proceed? [y/N] ");
+    let res = io::stdin().read_line().trim() == "y";
+    println!();
+    res
+}
+
+fn print_progress(action: &str, i: u32, n: u32) {
+    print!("\x1b[2K\x1b[1G");
+    print!("{} block {}/{}", action, i, n);
+}
+
+fn print_snapshot_error(err: SnapshotError) {
+    match err {
+        SnapshotError::Read => eprintln!("Could not read snapshot"),
+        SnapshotError::Write => eprintln!("Could not write snapshot"),
+        SnapshotError::Header => eprintln!("Could not use snapshot with this disk"),
+        SnapshotError::Checksum(addr) => eprintln!("Could not verify checksum at block {}", addr),
+    }
+}
+
+fn snapshot(pathname: &str, target: &str) -> usr::shell::ExitCode {
+    let dev = match open_disk(pathname) {
+        Ok(dev) => dev,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            return usr::shell::ExitCode::CommandError;
+        }
+    };
+    if sys::fs::is_disk_mounted(pathname) {
+        // The blocks of a mounted filesystem could change during the copy
+        eprintln!("Could not copy mounted disk '{}'", pathname);
+        return usr::shell::ExitCode::CommandError;
+    }
+    let mut out: Box<dyn FileIO> = if is_remote(target) {
+        match connect(target) {
+            Ok(stream) => Box::new(stream),
+            Err(msg) => {
+                eprintln!("{}", msg);
+                return usr::shell::ExitCode::CommandError;
+            }
+        }
+    } else {
+        let target = fs::realpath(target);
+        sys::fs::delete(&target).ok();
+        match sys::fs::open(&target, OpenFlag::Create as usize) {
+            Some(file) => Box::new(file),
+            None => {
+                eprintln!("Could not create file '{}'", target);
+                return usr::shell::ExitCode::CommandError;
+            }
+        }
+    };
+    print!("\x1b[?25l"); // Disable cursor
+    let res = sys::fs::snapshot(&dev, out.as_mut(), |i, n| print_progress("Copying", i, n));
+    drop(out); // Close the connection before printing the result
+    println!();
+    print!("\x1b[?25h"); // Enable cursor
+    match res {
+        Ok(()) => {
+            println!("Disk successfully copied to '{}'", target);
+            usr::shell::ExitCode::CommandSuccessful
+        }
+        Err(err) => {
+            print_snapshot_error(err);
+            usr::shell::ExitCode::CommandError
+        }
+    }
+}
+
+fn restore(pathname: &str, source: &str) -> usr::shell::ExitCode {
+    let mut dev = match open_disk(pathname) {
+        Ok(dev) => dev,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            return usr::shell::ExitCode::CommandError;
+        }
+    };
+    if sys::fs::is_disk_mounted(pathname) {
+        eprintln!("Could not restore mounted disk '{}'", pathname);
+        return usr::shell::ExitCode::CommandError;
+    }
+    let mut input: Box<dyn FileIO> = if is_remote(source) {
+        // The checksum of each range is verified before writing its blocks
+        if !confirm() {
+            return usr::shell::ExitCode::CommandSuccessful;
+        }
+        match connect(source) {
+            Ok(stream) => Box::new(stream),
+            Err(msg) => {
+                eprintln!("{}", msg);
+                return usr::shell::ExitCode::CommandError;
+            }
+        }
+    } else {
+        // The whole file is verified before writing anything to the disk
+        let source = fs::realpath(source);
+        let mut file = match sys::fs::open(&source, 0) {
+            Some(file) => file,
+            None => {
+                eprintln!("Could not open file '{}'", source);
+                return usr::shell::ExitCode::CommandError;
+            }
+        };
+        print!("\x1b[?25l"); // Disable cursor
+        let res = sys::fs::verify_snapshot(&mut file, |i, n| print_progress("Verifying", i, n));
+        println!();
+        print!("\x1b[?25h"); // Enable cursor
+        if let Err(err) = res {
+            print_snapshot_error(err);
+            return usr::shell::ExitCode::CommandError;
+        }
+        if !confirm() {
+            return usr::shell::ExitCode::CommandSuccessful;
+        }
+        Box::new(sys::fs::open(&source, 0).unwrap())
+    };
+    print!("\x1b[?25l"); // Disable cursor
+    let res = sys::fs::restore_snapshot(&mut dev, input.as_mut(), |i, n| print_progress("Restoring", i, n));
+    println!();
+    print!("\x1b[?25h"); // Enable cursor
+    match res {
+        Ok(_) => {
+            println!("Disk successfully restored from '{}'", source);
+            usr::shell::ExitCode::CommandSuccessful
+        }
+        Err(err) => {
+            print_snapshot_error(err);
+            usr::shell::ExitCode::CommandError
+        }
+    }
+}
+
+fn mem(size: &str, pathname: &str) -> usr::shell::ExitCode {
+    let size = match parse_size(size) {
+        Some(size) => size,
+        None => {
+            eprintln!("Could not parse <size>");
+            return usr::shell::ExitCode::CommandError;
+        }
+    };
+    let pathname = fs::realpath(pathname);
+    if pathname == "/" && sys::fs::is_mounted() {
+        // Mounting a RAM disk to `/` would drop the other mounts
+        eprintln!("Could not replace the filesystem mounted to '/'");
+        return usr::shell::ExitCode::CommandError;
+    }
+    if pathname != "/" && !fs::is_dir(&pathname) {
+        eprintln!("Could not find dir '{}'", pathname);
+        return usr::shell::ExitCode::CommandError;
+    }
+    if sys::fs::mount_mem_disk(&pathname, size).is_ok() {
+        println!("RAM disk successfully formatted");
+        println!("MFS is now mounted to '{}'", pathname);
+        usr::shell::ExitCode::CommandSuccessful
+    } else {
+        eprintln!("Could not create RAM disk of {} bytes", size);
+        usr::shell::ExitCode::CommandError
+    }
+}
+
+// Parse a size in bytes with an optional K or M suffix
+fn parse_size(s: &str) -> Option<usize> {
+    let (n, unit) = match s.chars().last()? {
+        'K' | 'k' => (&s[..(s.len() - 1)], 1 << 10),
+        'M' | 'm' => (&s[..(s.len() - 1)], 1 << 20),
+        _ => (s, 1),
+    };
+    n.parse::<usize>().ok()?.checked_mul(unit)
+}
+
 fn list() -> usr::shell::ExitCode {
     println!("Path            Name (Size)");
     for drive in sys::ata::list() {
         println!("/dev/ata/{}/{}    {}", drive.bus, drive.dsk, drive);
+        list_partitions(&format!("/dev/ata/{}/{}", drive.bus, drive.dsk));
+    }
+    for drive in sys::ahci::list() {
+        println!("/dev/ahci/{}     {}", drive.id, drive);
+        list_partitions(&format!("/dev/ahci/{}", drive.id));
+    }
+    for drive in sys::virtio::list() {
+        println!("/dev/virtio/{}   {}", drive.id, drive);
+        list_partitions(&format!("/dev/virtio/{}", drive.id));
     }
     usr::shell::ExitCode::CommandSuccessful
 }
 
+fn list_partitions(pathname: &str) {
+    if let Some(dev) = sys::fs::open_device(pathname) {
+        for (n, partition) in sys::fs::partitions(&dev) {
+            let size = partition.len as usize * dev.block_size();
+            let (size, unit) = sys::fs::humanized_size(size);
+            let path = format!("{}/{}", pathname, n);
+            println!("{:16}Partition ({} {})", path, size, unit);
+        }
+    }
+}
+
 fn usage() -> usr::shell::ExitCode {
     let size = sys::fs::disk_size();
     let used = sys::fs::disk_used();
@@ -112,9 +395,17 @@ fn help() -> usr::shell::ExitCode {
     println!("{}Usage:{} disk {}<command>{}", csi_title, csi_reset, csi_option, csi_reset);
     println!();
     println!("{}Commands:{}", csi_title, csi_reset);
-    println!("  {}list{}             List detected disks", csi_option, csi_reset);
-    println!("  {}usage{}            List disk usage", csi_option, csi_reset);
-    println!("  {}format <path>{}    Format disk", csi_option, csi_reset);
-    println!("  {}erase <path>{}     Erase disk", csi_option, csi_reset);
+    println!("  {}list{}                   List detected disks", csi_option, csi_reset);
+    println!("  {}usage{}                  List disk usage", csi_option, csi_reset);
+    println!("  {}format <path>{}          Format disk or partition", csi_option, csi_reset);
+    println!("  {}erase <path>{}           Erase disk", csi_option, csi_reset);
+    println!("  {}snapshot <path> <dst>{}  Copy disk to a file or a host:port", csi_option, csi_reset);
+    println!("  {}restore <path> <src>{}   Restore disk from a file or a host:port", csi_option, csi_reset);
+    println!("  {}check{}                  Check filesystem", csi_option, csi_reset);
+    println!("  {}migrate{}                Migrate filesystem to current version", csi_option, csi_reset);
+    println!("  {}mem <size> <path>{}      Create a RAM disk of the given size", csi_option, csi_reset);
+    println!();
+    println!("{}Options:{}", csi_title, csi_reset);
+    println!("  {0}-r{1},{0} --repair{1}           Repair filesystem while checking", csi_option, csi_reset);
     usr::shell::ExitCode::CommandSuccessful
 }
@@ -1,4 +1,4 @@
-use crate::{sys, usr};
+use crate::usr;
 use crate::api::{console, fs, io};
 use crate::api::console::Style;
 use alloc::format;
@@ -370,11 +370,11 @@ impl Editor {
     }
 
     fn rows(&self) -> usize {
-        sys::console::rows() - 1 // Leave out one line for status line
+        console::size().1 - 1 // Leave out one line for status line
     }
 
     fn cols(&self) -> usize {
-        sys::console::cols()
+        console::size().0
     }
 }
 
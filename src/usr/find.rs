@@ -1,6 +1,7 @@
-use crate::{sys, usr};
+use crate::usr;
 use crate::api::fs;
 use crate::api::regex::Regex;
+use crate::api::wildcard;
 use crate::api::console::Style;
 
 use alloc::format;
@@ -22,9 +23,9 @@ impl PrintingState {
     }
 }
 
-// > find /tmp -name *.txt -line hello
+// > find /tmp --name "*.txt" --line hello
 pub fn main(args: &[&str]) -> usr::shell::ExitCode {
-    let mut path: &str = &sys::process::dir(); // TODO: use '.'
+    let mut path = ".";
     let mut name = None;
     let mut line = None;
     let mut i = 1;
@@ -61,26 +62,42 @@ pub fn main(args: &[&str]) -> usr::shell::ExitCode {
         path = path.trim_end_matches('/');
     }
 
-    if name.is_some() {
-        todo!();
-    }
-
     let mut state = PrintingState::new();
     if let Some(pattern) = line {
-        print_matching_lines(path, pattern, &mut state);
+        print_matching_lines(path, pattern, name, &mut state);
+    } else if let Some(pattern) = name {
+        print_matching_names(path, pattern);
     }
 
     usr::shell::ExitCode::CommandSuccessful
 }
 
-fn print_matching_lines(path: &str, pattern: &str, state: &mut PrintingState) {
+fn print_matching_names(path: &str, pattern: &str) {
+    let color = Style::color("LightBlue");
+    let reset = Style::reset();
+    if let Ok(files) = fs::read_dir(path) {
+        for file in files {
+            let file_path = format!("{}/{}", path.trim_end_matches('/'), file.name());
+            if wildcard::is_match(pattern, &file.name()) {
+                println!("{}{}{}", color, file_path, reset);
+            }
+            if file.is_dir() {
+                print_matching_names(&file_path, pattern);
+            }
+        }
+    }
+}
+
+// Print the lines matching a pattern in the files of a path, or only in the
+// files with a name matching the given wildcard pattern.
+fn print_matching_lines(path: &str, pattern: &str, name: Option<&str>, state: &mut PrintingState) {
     if let Ok(files) = fs::read_dir(path) {
         state.is_recursive = true;
         for file in files {
-            let file_path = format!("{}/{}", path, file.name());
+            let file_path = format!("{}/{}", path.trim_end_matches('/'), file.name());
             if file.is_dir() {
-                print_matching_lines(&file_path, pattern, state);
-            } else {
+                print_matching_lines(&file_path, pattern, name, state);
+            } else if name.map_or(true, |name| wildcard::is_match(name, &file.name())) {
                 print_matching_lines_in_file(&file_path, pattern, state);
             }
         }
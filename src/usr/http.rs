@@ -1,14 +1,11 @@
 use crate::{sys, usr};
 use crate::api::console::Style;
-use crate::api::random;
-use crate::api::syscall;
+use crate::sys::fs::FileIO;
+use crate::sys::net::tcp::TcpStream;
 use alloc::borrow::ToOwned;
 use alloc::string::String;
 use alloc::vec;
-use core::str::{self, FromStr};
-use core::time::Duration;
-use smoltcp::socket::{SocketSet, TcpSocket, TcpSocketBuffer};
-use smoltcp::time::Instant;
+use core::str::FromStr;
 use smoltcp::wire::IpAddress;
 
 #[derive(Debug)]
@@ -100,119 +97,76 @@ pub fn main(args: &[&str]) -> usr::shell::ExitCode {
         }
     };
 
-    let tcp_rx_buffer = TcpSocketBuffer::new(vec![0; 1024]);
-    let tcp_tx_buffer = TcpSocketBuffer::new(vec![0; 1024]);
-    let tcp_socket = TcpSocket::new(tcp_rx_buffer, tcp_tx_buffer);
-
-    let mut sockets = SocketSet::new(vec![]);
-    let tcp_handle = sockets.add(tcp_socket);
-
-    enum State { Connect, Request, Response }
-    let mut state = State::Connect;
+    let is_ready = match *sys::net::IFACE.lock() {
+        Some(ref iface) => iface.ipv4_addr().map_or(false, |ip| !ip.is_unspecified()),
+        None => false,
+    };
+    if !is_ready {
+        eprintln!("Error: Interface not ready");
+        return usr::shell::ExitCode::CommandError;
+    }
 
-    if let Some(ref mut iface) = *sys::net::IFACE.lock() {
-        match iface.ipv4_addr() {
-            None => {
-                eprintln!("Error: Interface not ready");
-                return usr::shell::ExitCode::CommandError;
-            }
-            Some(ip_addr) if ip_addr.is_unspecified() => {
-                eprintln!("Error: Interface not ready");
-                return usr::shell::ExitCode::CommandError;
-            }
-            _ => {}
+    if is_verbose {
+        println!("* Connecting to {}:{}", address, url.port);
+    }
+    let mut stream = match TcpStream::connect(address, url.port) {
+        Ok(stream) => stream,
+        Err(_) => {
+            eprintln!("Could not connect to {}:{}", address, url.port);
+            return usr::shell::ExitCode::CommandError;
         }
+    };
 
-        let mut is_header = true;
-        let timeout = 5.0;
-        let started = syscall::realtime();
-        loop {
-            if syscall::realtime() - started > timeout {
-                eprintln!("Timeout reached");
-                return usr::shell::ExitCode::CommandError;
-            }
-            if sys::console::end_of_text() {
-                eprintln!();
+    let http_get = "GET ".to_owned() + &url.path + " HTTP/1.1\r\n";
+    let http_host = "Host: ".to_owned() + &url.host + "\r\n";
+    let http_ua = "User-Agent: MOROS/".to_owned() + env!("CARGO_PKG_VERSION") + "\r\n";
+    let http_connection = "Connection: close\r\n".to_owned();
+    if is_verbose {
+        print!("> {}", http_get);
+        print!("> {}", http_host);
+        print!("> {}", http_ua);
+        print!("> {}", http_connection);
+        println!(">");
+    }
+    let request = http_get + &http_host + &http_ua + &http_connection + "\r\n";
+    let mut sent = 0;
+    while sent < request.len() {
+        match stream.write(&request.as_bytes()[sent..]) {
+            Ok(n) => sent += n,
+            Err(_) => {
+                eprintln!("Could not send request");
                 return usr::shell::ExitCode::CommandError;
             }
-            let timestamp = Instant::from_millis((syscall::realtime() * 1000.0) as i64);
-            match iface.poll(&mut sockets, timestamp) {
-                Err(smoltcp::Error::Unrecognized) => {}
-                Err(e) => {
-                    eprintln!("Network Error: {}", e);
-                }
-                Ok(_) => {}
-            }
-
-            {
-                let mut socket = sockets.get::<TcpSocket>(tcp_handle);
+        }
+    }
 
-                state = match state {
-                    State::Connect if !socket.is_active() => {
-                        let local_port = 49152 + random::get_u16() % 16384;
-                        if is_verbose {
-                            println!("* Connecting to {}:{}", address, url.port);
-                        }
-                        if socket.connect((address, url.port), local_port).is_err() {
-                            eprintln!("Could not connect to {}:{}", address, url.port);
-                            return usr::shell::ExitCode::CommandError;
+    let mut is_header = true;
+    let mut buf = vec![0; 1024];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                let contents = String::from_utf8_lossy(&buf[..n]);
+                for line in contents.lines() {
+                    if is_header {
+                        if line.is_empty() {
+                            is_header = false;
                         }
-                        State::Request
-                    }
-                    State::Request if socket.may_send() => {
-                        let http_get = "GET ".to_owned() + &url.path + " HTTP/1.1\r\n";
-                        let http_host = "Host: ".to_owned() + &url.host + "\r\n";
-                        let http_ua = "User-Agent: MOROS/".to_owned() + env!("CARGO_PKG_VERSION") + "\r\n";
-                        let http_connection = "Connection: close\r\n".to_owned();
                         if is_verbose {
-                            print!("> {}", http_get);
-                            print!("> {}", http_host);
-                            print!("> {}", http_ua);
-                            print!("> {}", http_connection);
-                            println!(">");
+                            println!("< {}", line);
                         }
-                        socket.send_slice(http_get.as_ref()).expect("cannot send");
-                        socket.send_slice(http_host.as_ref()).expect("cannot send");
-                        socket.send_slice(http_ua.as_ref()).expect("cannot send");
-                        socket.send_slice(http_connection.as_ref()).expect("cannot send");
-                        socket.send_slice(b"\r\n").expect("cannot send");
-                        State::Response
+                    } else {
+                        println!("{}", line);
                     }
-                    State::Response if socket.can_recv() => {
-                        socket.recv(|data| {
-                            let contents = String::from_utf8_lossy(data);
-                            for line in contents.lines() {
-                                if is_header {
-                                    if line.is_empty() {
-                                        is_header = false;
-                                    }
-                                    if is_verbose {
-                                        println!("< {}", line);
-                                    }
-                                } else {
-                                    println!("{}", line);
-                                }
-                            }
-                            (data.len(), ())
-                        }).unwrap();
-                        State::Response
-                    }
-                    State::Response if !socket.may_recv() => {
-                        break;
-                    }
-                    _ => state
-                };
+                }
             }
-
-            if let Some(wait_duration) = iface.poll_delay(&sockets, timestamp) {
-                let wait_duration: Duration = wait_duration.into();
-                syscall::sleep(wait_duration.as_secs_f64());
+            Err(_) => {
+                eprintln!("Could not receive response");
+                return usr::shell::ExitCode::CommandError;
             }
         }
-        usr::shell::ExitCode::CommandSuccessful
-    } else {
-        usr::shell::ExitCode::CommandError
     }
+    usr::shell::ExitCode::CommandSuccessful
 }
 
 fn help() -> usr::shell::ExitCode {
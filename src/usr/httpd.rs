@@ -97,7 +97,8 @@ pub fn main(_args: &[&str]) -> usr::shell::ExitCode {
                                         res.push_str("HTTP/1.0 200 OK\r\n");
                                         body = contents.replace("\n", "\r\n");
                                         mime = "text/plain";
-                                    } else if let Ok(mut files) = fs::read_dir(path) {
+                                    } else if let Ok(entries) = fs::read_dir(path) {
+                                        let mut files: Vec<_> = entries.collect();
                                         code = 200;
                                         res.push_str("HTTP/1.0 200 OK\r\n");
                                         body = format!("<h1>Index of {}</h1>\r\n", path);
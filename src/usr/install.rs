@@ -4,6 +4,8 @@ use crate::api::fs;
 use crate::api::fs::DeviceType;
 use crate::api::io;
 use crate::api::syscall;
+use crate::api::tar;
+use alloc::format;
 use alloc::string::String;
 
 pub fn copy_files(verbose: bool) {
@@ -17,37 +19,26 @@ pub fn copy_files(verbose: bool) {
     create_dir("/usr", verbose); // User directories
     create_dir("/var", verbose); // Variables
 
-    copy_file("/bin/hello", include_bytes!("../../dsk/bin/hello"), verbose);
-    copy_file("/bin/sleep", include_bytes!("../../dsk/bin/sleep"), verbose);
-
     create_dir("/dev/clk", verbose); // Clocks
-    create_dev("/dev/clk/uptime", DeviceType::File, verbose); // TODO
-    create_dev("/dev/clk/realtime", DeviceType::File, verbose); // TODO
-    create_dev("/dev/rtc", DeviceType::File, verbose); // TODO
+    create_dev("/dev/clk/uptime", DeviceType::Uptime, verbose);
+    create_dev("/dev/clk/realtime", DeviceType::Realtime, verbose);
+    create_dev("/dev/rtc", DeviceType::RTC, verbose);
     create_dev("/dev/null", DeviceType::Null, verbose);
     create_dev("/dev/random", DeviceType::Random, verbose);
     create_dev("/dev/console", DeviceType::Console, verbose);
-
-    copy_file("/ini/boot.sh", include_bytes!("../../dsk/ini/boot.sh"), verbose);
-    copy_file("/ini/banner.txt", include_bytes!("../../dsk/ini/banner.txt"), verbose);
-    copy_file("/ini/version.txt", include_bytes!("../../dsk/ini/version.txt"), verbose);
-    copy_file("/ini/palette.csv", include_bytes!("../../dsk/ini/palette.csv"), verbose);
-
-    create_dir("/ini/lisp", verbose);
-    copy_file("/ini/lisp/core.lsp", include_bytes!("../../dsk/ini/lisp/core.lsp"), verbose);
-
-    create_dir("/ini/fonts", verbose);
-    copy_file("/ini/fonts/lat15-terminus-8x16.psf", include_bytes!("../../dsk/ini/fonts/lat15-terminus-8x16.psf"), verbose);
-    copy_file("/ini/fonts/zap-light-8x16.psf", include_bytes!("../../dsk/ini/fonts/zap-light-8x16.psf"), verbose);
-    copy_file("/ini/fonts/zap-vga-8x16.psf", include_bytes!("../../dsk/ini/fonts/zap-vga-8x16.psf"), verbose);
-
-    copy_file("/tmp/alice.txt", include_bytes!("../../dsk/tmp/alice.txt"), verbose);
-    copy_file("/tmp/fibonacci.lsp", include_bytes!("../../dsk/tmp/fibonacci.lsp"), verbose);
-
-    create_dir("/tmp/beep", verbose);
-    copy_file("/tmp/beep/tetris.sh", include_bytes!("../../dsk/tmp/beep/tetris.sh"), verbose);
-    copy_file("/tmp/beep/starwars.sh", include_bytes!("../../dsk/tmp/beep/starwars.sh"), verbose);
-    copy_file("/tmp/beep/mario.sh", include_bytes!("../../dsk/tmp/beep/mario.sh"), verbose);
+    create_dev("/dev/net", DeviceType::Net, verbose);
+    create_dev("/dev/mouse", DeviceType::Mouse, verbose);
+    create_dir("/dev/serial", verbose); // Serial ports
+    create_dev("/dev/serial/0", DeviceType::Serial0, verbose);
+    create_dev("/dev/serial/1", DeviceType::Serial1, verbose);
+
+    // The dirs copied from the disk are embedded in an archive during build
+    let archive = include_bytes!(concat!(env!("OUT_DIR"), "/dsk.tar"));
+    if let Ok(entries) = tar::parse(archive) {
+        for entry in entries {
+            unpack(entry, verbose);
+        }
+    }
 }
 
 pub fn main(_args: &[&str]) -> usr::shell::ExitCode {
@@ -119,22 +110,21 @@ fn create_dev(pathname: &str, dev: DeviceType, verbose: bool) {
     }
 }
 
-fn copy_file(pathname: &str, buf: &[u8], verbose: bool) {
-    if fs::exists(pathname) {
+fn unpack(mut entry: tar::Entry, verbose: bool) {
+    let pathname = format!("/{}", entry.name);
+    if fs::exists(&pathname) {
         return;
     }
     if pathname.ends_with(".txt") {
-        if let Ok(text) = String::from_utf8(buf.to_vec()) {
-            let text = text.replace("{x.x.x}", env!("CARGO_PKG_VERSION"));
-            fs::write(pathname, text.as_bytes()).ok();
-        } else {
-            fs::write(pathname, buf).ok();
+        if let Ok(text) = String::from_utf8(entry.data.clone()) {
+            entry.data = text.replace("{x.x.x}", env!("CARGO_PKG_VERSION")).into_bytes();
         }
-    } else {
-        fs::write(pathname, buf).ok();
     }
-    // TODO: add File::write_all to split buf if needed
-    if verbose {
-        println!("Copied '{}'", pathname);
+    if tar::unpack(&entry, "/").is_ok() && verbose {
+        if entry.kind == tar::EntryKind::Dir {
+            println!("Created '{}'", pathname);
+        } else {
+            println!("Copied '{}'", pathname);
+        }
     }
 }
@@ -0,0 +1,49 @@
+use crate::usr;
+use crate::api::console::Style;
+use crate::api::fs;
+use crate::api::syscall;
+
+use alloc::vec::Vec;
+
+pub fn main(args: &[&str]) -> usr::shell::ExitCode {
+    let mut symbolic = false;
+    let mut paths = Vec::new();
+    for arg in &args[1..] {
+        match *arg {
+            "-h" | "--help" => return help(),
+            "-s" | "--symbolic" => symbolic = true,
+            _ => paths.push(*arg),
+        }
+    }
+    if paths.len() != 2 {
+        return help();
+    }
+
+    let target = paths[0];
+    let path = paths[1];
+    if symbolic {
+        if let Some(handle) = fs::create_link(path, target) {
+            syscall::close(handle);
+            usr::shell::ExitCode::CommandSuccessful
+        } else {
+            eprintln!("Could not create link '{}'", path);
+            usr::shell::ExitCode::CommandError
+        }
+    } else if fs::link(target, path).is_ok() {
+        usr::shell::ExitCode::CommandSuccessful
+    } else {
+        eprintln!("Could not link '{}' to '{}'", path, target);
+        usr::shell::ExitCode::CommandError
+    }
+}
+
+fn help() -> usr::shell::ExitCode {
+    let csi_option = Style::color("LightCyan");
+    let csi_title = Style::color("Yellow");
+    let csi_reset = Style::reset();
+    println!("{}Usage:{} link {}<options> <target> <path>{}", csi_title, csi_reset, csi_option, csi_reset);
+    println!();
+    println!("{}Options:{}", csi_title, csi_reset);
+    println!("  {0}-s{1},{0} --symbolic{1}    Create a symbolic link instead of a hard link", csi_option, csi_reset);
+    usr::shell::ExitCode::CommandSuccessful
+}
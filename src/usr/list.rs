@@ -1,23 +1,25 @@
-use crate::{sys, usr};
+use crate::usr;
 use crate::api::console::Style;
 use crate::api::time;
 use crate::api::fs;
 use crate::api::syscall;
 use crate::api::fs::FileInfo;
 
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 
 pub fn main(args: &[&str]) -> usr::shell::ExitCode {
-    let mut path: &str = &sys::process::dir(); // TODO: use '.'
+    let mut path = ".";
     let mut sort = "name";
     let mut hide_dot_files = true;
+    let mut long = false;
 
     let n = args.len();
     for i in 1..n {
         match args[i] {
             "-h" | "--help" => return help(),
             "-a" | "--all"  => hide_dot_files = false,
+            "-l" | "--long" => long = true,
             "-n" | "--name" => sort = "name",
             "-s" | "--size" => sort = "size",
             "-t" | "--time" => sort = "time",
@@ -34,7 +36,7 @@ pub fn main(args: &[&str]) -> usr::shell::ExitCode {
     if let Some(info) = syscall::info(path) {
         if info.is_dir() {
             if let Ok(entries) = fs::read_dir(path) {
-                let mut files: Vec<_> = entries.iter().filter(|entry| {
+                let mut files: Vec<_> = entries.filter(|entry| {
                     !(entry.name().starts_with('.') && hide_dot_files)
                 }).collect();
 
@@ -55,8 +57,8 @@ pub fn main(args: &[&str]) -> usr::shell::ExitCode {
                 let width = max_size.to_string().len();
 
 
-                for file in files {
-                    print_file(file, width);
+                for file in &files {
+                    print_file(file, width, long);
                 }
                 usr::shell::ExitCode::CommandSuccessful
             } else {
@@ -64,7 +66,7 @@ pub fn main(args: &[&str]) -> usr::shell::ExitCode {
                 usr::shell::ExitCode::CommandError
             }
         } else {
-            print_file(&info, info.size().to_string().len());
+            print_file(&info, info.size().to_string().len(), long);
             usr::shell::ExitCode::CommandSuccessful
         }
     } else {
@@ -73,9 +75,10 @@ pub fn main(args: &[&str]) -> usr::shell::ExitCode {
     }
 }
 
-fn print_file(file: &FileInfo, width: usize) {
+fn print_file(file: &FileInfo, width: usize, long: bool) {
     let csi_dir_color = Style::color("Blue");
     let csi_dev_color = Style::color("Yellow");
+    let csi_link_color = Style::color("LightCyan");
     let csi_reset = Style::reset();
 
     let date = time::from_timestamp(file.time() as i64);
@@ -83,12 +86,39 @@ fn print_file(file: &FileInfo, width: usize) {
         csi_dir_color
     } else if file.is_device() {
         csi_dev_color
+    } else if file.is_link() {
+        csi_link_color
     } else {
         csi_reset
     };
+    if long {
+        print!("{} {:5} ", mode_string(file), file.uid());
+    }
     println!("{:width$} {} {}{}{}", file.size(), date.format("%F %H:%M:%S"), color, file.name(), csi_reset, width = width);
 }
 
+// Format the kind and the permission bits of a file like "drwxr-xr-x"
+fn mode_string(file: &FileInfo) -> String {
+    let mut res = String::new();
+    res.push(if file.is_dir() {
+        'd'
+    } else if file.is_device() {
+        'c'
+    } else if file.is_link() {
+        'l'
+    } else {
+        '-'
+    });
+    let mode = file.mode();
+    for i in (0..3).rev() {
+        let bits = (mode >> (i * 3)) & 0o7;
+        res.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        res.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        res.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    res
+}
+
 fn help() -> usr::shell::ExitCode {
     let csi_option = Style::color("LightCyan");
     let csi_title = Style::color("Yellow");
@@ -97,6 +127,7 @@ fn help() -> usr::shell::ExitCode {
     println!();
     println!("{}Options:{}", csi_title, csi_reset);
     println!("  {0}-a{1},{0} --all{1}     Show dot files", csi_option, csi_reset);
+    println!("  {0}-l{1},{0} --long{1}    Show permissions and owner", csi_option, csi_reset);
     println!("  {0}-n{1},{0} --name{1}    Sort by name", csi_option, csi_reset);
     println!("  {0}-s{1},{0} --size{1}    Sort by size", csi_option, csi_reset);
     println!("  {0}-t{1},{0} --time{1}    Sort by time", csi_option, csi_reset);
@@ -23,9 +23,11 @@ pub mod httpd;
 pub mod install;
 pub mod ip;
 pub mod keyboard;
+pub mod link;
 pub mod lisp;
 pub mod list;
 pub mod memory;
+pub mod mount;
 pub mod net;
 pub mod pci;
 pub mod pow;
@@ -35,7 +37,9 @@ pub mod read;
 pub mod route;
 pub mod shell;
 pub mod sleep;
+pub mod tar;
 pub mod tcp;
+pub mod umount;
 pub mod uptime;
 pub mod user;
 pub mod vga;
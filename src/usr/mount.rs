@@ -0,0 +1,40 @@
+use crate::{sys, usr};
+use crate::api::console::Style;
+use crate::api::syscall;
+
+pub fn main(args: &[&str]) -> usr::shell::ExitCode {
+    match args.len() {
+        1 => {
+            for (path, dev, fs) in sys::fs::mounts() {
+                println!("{} on {} type {}", dev, path, fs);
+            }
+            usr::shell::ExitCode::CommandSuccessful
+        }
+        3 => {
+            let dev = args[1];
+            let path = args[2];
+            if syscall::mount(dev, path).is_ok() {
+                usr::shell::ExitCode::CommandSuccessful
+            } else {
+                eprintln!("Could not mount '{}' on '{}'", dev, path);
+                usr::shell::ExitCode::CommandError
+            }
+        }
+        _ => help(),
+    }
+}
+
+fn help() -> usr::shell::ExitCode {
+    let csi_option = Style::color("LightCyan");
+    let csi_title = Style::color("Yellow");
+    let csi_reset = Style::reset();
+    println!("{}Usage:{} mount {}[<device> <path>]{}", csi_title, csi_reset, csi_option, csi_reset);
+    println!();
+    println!("{}Devices:{}", csi_title, csi_reset);
+    println!("  {}mem{}                       RAM disk", csi_option, csi_reset);
+    println!("  {}/dev/ata/<bus>/<dsk>{}      ATA disk with MFS or FAT32, or ATAPI disc with ISO 9660", csi_option, csi_reset);
+    println!("  {}/dev/ahci/<id>{}            AHCI disk with MFS or FAT32", csi_option, csi_reset);
+    println!("  {}/dev/virtio/<id>{}          VirtIO disk with MFS or FAT32", csi_option, csi_reset);
+    println!("  {}<disk>/<n>{}                Partition of a disk with MFS or FAT32", csi_option, csi_reset);
+    usr::shell::ExitCode::CommandSuccessful
+}
@@ -1,13 +1,19 @@
 use crate::usr;
+use crate::api::fs;
 
 pub fn main(args: &[&str]) -> usr::shell::ExitCode {
-    if args.len() != 3 {
+    if args.len() < 3 {
         return usr::shell::ExitCode::CommandError;
     }
-
-    // TODO: Avoid doing copy+delete
-    match usr::copy::main(args) {
-        usr::shell::ExitCode::CommandSuccessful => usr::delete::main(&args[0..2]),
-        _ => usr::shell::ExitCode::CommandError,
-    }
+    usr::copy::for_each_source(args, |source, dest| {
+        // Move the entry when possible or fallback to copy+delete
+        if fs::rename(source, dest).is_ok() {
+            return usr::shell::ExitCode::CommandSuccessful;
+        }
+        let code = usr::copy::copy_file(source, dest);
+        if code != usr::shell::ExitCode::CommandSuccessful {
+            return code;
+        }
+        usr::delete::main(&["delete", source])
+    })
 }
@@ -1,43 +1,33 @@
 use crate::{sys, usr};
+use crate::api::fs;
+use crate::api::fs::ControlRequest;
 use crate::api::syscall;
 use crate::api::console::Style;
 //use smoltcp::wire::Ipv4Address;
 use smoltcp::socket::{SocketSet, TcpSocket, TcpSocketBuffer};
 use smoltcp::time::Instant;
 
+const DEVICE: &str = "/dev/net";
+
 pub fn main(args: &[&str]) -> usr::shell::ExitCode {
     if args.len() == 1 {
         eprintln!("Usage: net <command>");
         return usr::shell::ExitCode::CommandError;
     }
 
+    if args[1] == "config" {
+        if args.len() < 4 {
+            eprintln!("Usage: net config <key> <value>");
+            return usr::shell::ExitCode::CommandError;
+        }
+        return config(args[2], args[3]);
+    }
+
     if let Some(ref mut iface) = *sys::net::IFACE.lock() {
         match args[1] {
             "-h" | "--help" => {
                 return help();
             }
-            "config" => {
-                if args.len() < 4 {
-                    eprintln!("Usage: net config <key> <value>");
-                    return usr::shell::ExitCode::CommandError;
-                }
-                match args[2] {
-                    "debug" => {
-                        iface.device_mut().debug_mode = match args[3] {
-                            "1" | "true" => true,
-                            "0" | "false" => false,
-                            _ => {
-                                eprintln!("Invalid config value");
-                                return usr::shell::ExitCode::CommandError;
-                            }
-                        }
-                    }
-                    _ => {
-                        eprintln!("Invalid config key");
-                        return usr::shell::ExitCode::CommandError;
-                    }
-                }
-            }
             "monitor" => {
                 iface.device_mut().debug_mode = true;
 
@@ -85,6 +75,38 @@ pub fn main(args: &[&str]) -> usr::shell::ExitCode {
     usr::shell::ExitCode::CommandSuccessful
 }
 
+fn config(key: &str, value: &str) -> usr::shell::ExitCode {
+    let request = match key {
+        "debug" => ControlRequest::SetNetDebug,
+        _ => {
+            eprintln!("Invalid config key");
+            return usr::shell::ExitCode::CommandError;
+        }
+    };
+    let mut buf = match value {
+        "1" | "true" => [1],
+        "0" | "false" => [0],
+        _ => {
+            eprintln!("Invalid config value");
+            return usr::shell::ExitCode::CommandError;
+        }
+    };
+    let res = if let Some(handle) = fs::open_device(DEVICE) {
+        let res = syscall::control(handle, request, &mut buf);
+        syscall::close(handle);
+        res.ok_or(())
+    } else {
+        // The device file is only created by the install command, so the
+        // interface is configured directly on the disks installed before it
+        sys::net::Net::new().control(request, &mut buf)
+    };
+    if res.is_ok() {
+        return usr::shell::ExitCode::CommandSuccessful;
+    }
+    eprintln!("Could not configure network device '{}'", DEVICE);
+    usr::shell::ExitCode::CommandError
+}
+
 fn help() -> usr::shell::ExitCode {
     let csi_option = Style::color("LightCyan");
     let csi_title = Style::color("Yellow");
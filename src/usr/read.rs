@@ -2,10 +2,13 @@ use crate::{api, sys, usr};
 use crate::api::console;
 use crate::api::fs;
 use crate::api::syscall;
-use crate::sys::cmos::CMOS;
+use crate::sys::cmos::RTC;
+use crate::sys::fs::{DeviceType, FileIO};
 
 use alloc::borrow::ToOwned;
+use alloc::string::String;
 use alloc::vec::Vec;
+use core::convert::TryInto;
 
 pub fn main(args: &[&str]) -> usr::shell::ExitCode {
     if args.len() != 2 {
@@ -20,106 +23,148 @@ pub fn main(args: &[&str]) -> usr::shell::ExitCode {
         path = path.trim_end_matches('/');
     }
 
-    // TODO: Create device drivers for `/dev` and `/net` hardcoded commands
+    // TODO: Create device drivers for `/net` hardcoded commands
+    if path.starts_with("/net/") {
+        // Examples:
+        // > read /net/http/example.com/articles
+        // > read /net/http/example.com:8080/articles/index.html
+        // > read /net/daytime/time.nist.gov
+        // > read /net/tcp/time.nist.gov:13
+        let parts: Vec<_> = path.split('/').collect();
+        if parts.len() < 4 {
+            eprintln!("Usage: read /net/http/<host>/<path>");
+            usr::shell::ExitCode::CommandError
+        } else {
+            match parts[2] {
+                "tcp" => {
+                    let host = parts[3];
+                    usr::tcp::main(&["tcp", host])
+                }
+                "daytime" => {
+                    let host = parts[3];
+                    let port = "13";
+                    usr::tcp::main(&["tcp", host, port])
+                }
+                "http" => {
+                    let host = parts[3];
+                    let path = "/".to_owned() + &parts[4..].join("/");
+                    usr::http::main(&["http", host, &path])
+                }
+                _ => {
+                    eprintln!("Error: unknown protocol '{}'", parts[2]);
+                    usr::shell::ExitCode::CommandError
+                }
+            }
+        }
+    } else if let Some(info) = syscall::info(path) {
+        if info.is_file() {
+            if let Ok(contents) = api::fs::read_to_string(path) {
+                print!("{}", contents);
+                usr::shell::ExitCode::CommandSuccessful
+            } else {
+                eprintln!("Could not read '{}'", path);
+                usr::shell::ExitCode::CommandError
+            }
+        } else if info.is_dir() {
+            usr::list::main(args)
+        } else if info.is_device() {
+            read_device(path)
+        } else {
+            eprintln!("Could not read type of '{}'", path);
+            usr::shell::ExitCode::CommandError
+        }
+    } else {
+        eprintln!("File not found '{}'", path);
+        usr::shell::ExitCode::CommandError
+    }
+}
+
+fn read_device(path: &str) -> usr::shell::ExitCode {
+    let kind = match sys::fs::device_type(path) {
+        Some(kind) => kind,
+        None => {
+            eprintln!("Could not read type of '{}'", path);
+            return usr::shell::ExitCode::CommandError;
+        }
+    };
+    match kind {
+        DeviceType::File => read_legacy_device(path),
+        DeviceType::Uptime | DeviceType::Realtime => {
+            match fs::read_to_bytes(path) {
+                Ok(bytes) if bytes.len() == 8 => {
+                    println!("{:.6}", f64::from_be_bytes(bytes[0..8].try_into().unwrap()));
+                    usr::shell::ExitCode::CommandSuccessful
+                }
+                _ => {
+                    eprintln!("Could not read '{}'", path);
+                    usr::shell::ExitCode::CommandError
+                }
+            }
+        }
+        DeviceType::RTC => {
+            match fs::read_to_string(path) {
+                Ok(date) => {
+                    println!("{}", date);
+                    usr::shell::ExitCode::CommandSuccessful
+                }
+                Err(_) => {
+                    eprintln!("Could not read '{}'", path);
+                    usr::shell::ExitCode::CommandError
+                }
+            }
+        }
+        _ => loop {
+            if sys::console::end_of_text() {
+                println!();
+                return usr::shell::ExitCode::CommandSuccessful;
+            }
+            if let Ok(bytes) = fs::read_to_bytes(path) {
+                if kind == DeviceType::Console && bytes.len() == 1 {
+                    match bytes[0] as char {
+                        console::ETX_KEY => {
+                            println!("^C");
+                            return usr::shell::ExitCode::CommandSuccessful;
+                        }
+                        console::EOT_KEY => {
+                            println!("^D");
+                            return usr::shell::ExitCode::CommandSuccessful;
+                        }
+                        _ => {}
+                    }
+                }
+                for b in bytes {
+                    print!("{}", b as char);
+                }
+            } else {
+                eprintln!("Could not read '{}'", path);
+                return usr::shell::ExitCode::CommandError;
+            }
+        }
+    }
+}
+
+// The clocks are plain device files on the disks installed before they had
+// their own devices
+fn read_legacy_device(path: &str) -> usr::shell::ExitCode {
     match path {
         "/dev/rtc" => {
-            let rtc = CMOS::new().rtc();
-            println!(
-                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
-                rtc.year, rtc.month, rtc.day,
-                rtc.hour, rtc.minute, rtc.second
-            );
+            let mut buf = [0; RTC::size()];
+            if let Ok(n) = RTC::new().read(&mut buf) {
+                println!("{}", String::from_utf8_lossy(&buf[0..n]));
+            }
             usr::shell::ExitCode::CommandSuccessful
-        },
+        }
         "/dev/clk/realtime" => {
             println!("{:.6}", syscall::realtime());
             usr::shell::ExitCode::CommandSuccessful
-        },
+        }
         "/dev/clk/uptime" => {
             println!("{:.6}", syscall::uptime());
             usr::shell::ExitCode::CommandSuccessful
-        },
+        }
         _ => {
-            if path.starts_with("/net/") {
-                // Examples:
-                // > read /net/http/example.com/articles
-                // > read /net/http/example.com:8080/articles/index.html
-                // > read /net/daytime/time.nist.gov
-                // > read /net/tcp/time.nist.gov:13
-                let parts: Vec<_> = path.split('/').collect();
-                if parts.len() < 4 {
-                    eprintln!("Usage: read /net/http/<host>/<path>");
-                    usr::shell::ExitCode::CommandError
-                } else {
-                    match parts[2] {
-                        "tcp" => {
-                            let host = parts[3];
-                            usr::tcp::main(&["tcp", host])
-                        }
-                        "daytime" => {
-                            let host = parts[3];
-                            let port = "13";
-                            usr::tcp::main(&["tcp", host, port])
-                        }
-                        "http" => {
-                            let host = parts[3];
-                            let path = "/".to_owned() + &parts[4..].join("/");
-                            usr::http::main(&["http", host, &path])
-                        }
-                        _ => {
-                            eprintln!("Error: unknown protocol '{}'", parts[2]);
-                            usr::shell::ExitCode::CommandError
-                        }
-                    }
-                }
-            } else if let Some(info) = syscall::info(path) {
-                if info.is_file() {
-                    if let Ok(contents) = api::fs::read_to_string(path) {
-                        print!("{}", contents);
-                        usr::shell::ExitCode::CommandSuccessful
-                    } else {
-                        eprintln!("Could not read '{}'", path);
-                        usr::shell::ExitCode::CommandError
-                    }
-                } else if info.is_dir() {
-                    usr::list::main(args)
-                } else if info.is_device() {
-                    let is_console = info.size() == 4; // TODO: Improve device detection
-                    loop {
-                        if sys::console::end_of_text() {
-                            println!();
-                            return usr::shell::ExitCode::CommandSuccessful;
-                        }
-                        if let Ok(bytes) = fs::read_to_bytes(path) {
-                            if is_console && bytes.len() == 1 {
-                                match bytes[0] as char {
-                                    console::ETX_KEY => {
-                                        println!("^C");
-                                        return usr::shell::ExitCode::CommandSuccessful;
-                                    }
-                                    console::EOT_KEY => {
-                                        println!("^D");
-                                        return usr::shell::ExitCode::CommandSuccessful;
-                                    }
-                                    _ => {}
-                                }
-                            }
-                            for b in bytes {
-                                print!("{}", b as char);
-                            }
-                        } else {
-                            eprintln!("Could not read '{}'", path);
-                            return usr::shell::ExitCode::CommandError;
-                        }
-                    }
-                } else {
-                    eprintln!("Could not read type of '{}'", path);
-                    usr::shell::ExitCode::CommandError
-                }
-            } else {
-                eprintln!("File not found '{}'", path);
-                usr::shell::ExitCode::CommandError
-            }
+            eprintln!("Could not read '{}'", path);
+            usr::shell::ExitCode::CommandError
         }
     }
 }
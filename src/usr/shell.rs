@@ -3,16 +3,17 @@ use crate::api::fs;
 use crate::api::regex::Regex;
 use crate::api::prompt::Prompt;
 use crate::api::console::Style;
+use crate::api::wildcard;
 use alloc::format;
 use alloc::vec::Vec;
-use alloc::string::String;
+use alloc::string::{String, ToString};
 
 // TODO: Scan /bin
-const AUTOCOMPLETE_COMMANDS: [&str; 39] = [
+const AUTOCOMPLETE_COMMANDS: [&str; 43] = [
     "2048", "base64", "calc", "clear", "colors", "copy", "date", "delete", "dhcp", "disk", "edit",
     "env", "exit", "geotime", "goto", "halt", "help", "hex", "host", "http", "httpd", "install",
-    "ip", "keyboard", "lisp", "list", "memory", "move", "net", "pci", "print", "read", "route",
-    "shell", "sleep", "tcp", "user", "vga", "write"
+    "ip", "keyboard", "link", "lisp", "list", "memory", "mount", "move", "net", "pci", "print",
+    "read", "route", "shell", "sleep", "tar", "tcp", "umount", "user", "vga", "write"
 ];
 
 #[repr(u8)]
@@ -27,7 +28,7 @@ pub enum ExitCode {
 fn shell_completer(line: &str) -> Vec<String> {
     let mut entries = Vec::new();
 
-    let args = split_args(line);
+    let args: Vec<&str> = split_args(line).into_iter().map(|(arg, _)| arg).collect();
     let i = args.len() - 1;
     if args.len() == 1 { // Autocomplete command
         for &cmd in &AUTOCOMPLETE_COMMANDS {
@@ -36,17 +37,18 @@ fn shell_completer(line: &str) -> Vec<String> {
             }
         }
     } else { // Autocomplete path
-        let pathname = fs::realpath(args[i]);
-        let dirname = fs::dirname(&pathname);
-        let filename = fs::filename(&pathname);
-        let sep = if dirname.ends_with('/') { "" } else { "/" };
-        if let Ok(files) = fs::read_dir(dirname) {
+        // The dir part of the arg is kept as typed, with its trailing slash,
+        // and resolved from the current dir to list the entries to complete.
+        let (dirname, filename) = match args[i].rfind('/') {
+            Some(j) => (&args[i][..(j + 1)], &args[i][(j + 1)..]),
+            None => (".", args[i]),
+        };
+        if let Ok(files) = fs::read_dir(&fs::realpath(dirname)) {
             for file in files {
                 let name = file.name();
-                if name.starts_with(filename) {
+                if let Some(rest) = name.strip_prefix(filename) {
                     let end = if file.is_dir() { "/" } else { "" };
-                    let path = format!("{}{}{}{}", dirname, sep, name, end);
-                    entries.push(path[pathname.len()..].into());
+                    entries.push(format!("{}{}", rest, end));
                 }
             }
         }
@@ -61,8 +63,10 @@ pub fn prompt_string(success: bool) -> String {
     format!("{}>{} ", if success { csi_color } else { csi_error }, csi_reset)
 }
 
-pub fn split_args(cmd: &str) -> Vec<&str> {
-    let mut args: Vec<&str> = Vec::new();
+// Split a command into its args, each with a flag telling if it was between
+// quotes in the command
+pub fn split_args(cmd: &str) -> Vec<(&str, bool)> {
+    let mut args: Vec<(&str, bool)> = Vec::new();
     let mut i = 0;
     let mut n = cmd.len();
     let mut is_quote = false;
@@ -73,13 +77,13 @@ pub fn split_args(cmd: &str) -> Vec<&str> {
             break;
         } else if c == ' ' && !is_quote {
             if i != j {
-                args.push(&cmd[i..j]);
+                args.push((&cmd[i..j], false));
             }
             i = j + 1;
         } else if c == '"' {
             is_quote = !is_quote;
             if !is_quote {
-                args.push(&cmd[i..j]);
+                args.push((&cmd[i..j], true));
             }
             i = j + 1;
         }
@@ -89,11 +93,11 @@ pub fn split_args(cmd: &str) -> Vec<&str> {
         if is_quote {
             n -= 1;
         }
-        args.push(&cmd[i..n]);
+        args.push((&cmd[i..n], is_quote));
     }
 
     if n == 0 || cmd.ends_with(' ') {
-        args.push("");
+        args.push(("", false));
     }
 
     args
@@ -136,10 +140,7 @@ fn change_dir(args: &[&str]) -> ExitCode {
             ExitCode::CommandSuccessful
         },
         2 => {
-            let mut pathname = fs::realpath(args[1]);
-            if pathname.len() > 1 {
-                pathname = pathname.trim_end_matches('/').into();
-            }
+            let pathname = sys::fs::canonicalize(args[1]).unwrap_or_default();
             if api::fs::is_dir(&pathname) {
                 sys::process::set_dir(&pathname);
                 ExitCode::CommandSuccessful
@@ -171,13 +172,14 @@ pub fn exec(cmd: &str) -> ExitCode {
         let mut is_thin_arrow = false;
         let mut left_handle;
 
-        if Regex::new("<=+").is_match(args[i]) { // Redirect input stream
+        let arg = args[i].0;
+        if Regex::new("<=+").is_match(arg) { // Redirect input stream
             is_fat_arrow = true;
             left_handle = 0;
-        } else if Regex::new("\\d*=+>").is_match(args[i]) { // Redirect output stream(s)
+        } else if Regex::new("\\d*=+>").is_match(arg) { // Redirect output stream(s)
             is_fat_arrow = true;
             left_handle = 1;
-        } else if Regex::new("\\d*-*>\\d*").is_match(args[i]) { // Pipe output stream(s)
+        } else if Regex::new("\\d*-*>\\d*").is_match(arg) { // Pipe output stream(s)
             is_thin_arrow = true;
             left_handle = 1;
             // TODO: right_handle?
@@ -186,7 +188,7 @@ pub fn exec(cmd: &str) -> ExitCode {
             continue;
         }
 
-        let s = args[i].chars().take_while(|c| c.is_numeric()).collect::<String>();
+        let s = arg.chars().take_while(|c| c.is_numeric()).collect::<String>();
         if let Ok(h) = s.parse() {
             left_handle = h;
         }
@@ -197,7 +199,7 @@ pub fn exec(cmd: &str) -> ExitCode {
                 println!("Could not parse path for redirection");
                 return ExitCode::CommandError;
             }
-            let path = args[i + 1];
+            let path = args[i + 1].0;
             if api::fs::reopen(path, left_handle).is_err() {
                 println!("Could not open path for redirection");
                 return ExitCode::CommandError;
@@ -211,6 +213,21 @@ pub fn exec(cmd: &str) -> ExitCode {
         }
     }
 
+    // Expand unquoted globs like `delete /tmp/*.log` into the matching paths
+    // or keep them unchanged if nothing matches.
+    let mut expanded_args = Vec::new();
+    for (i, &(arg, is_quoted)) in args.iter().enumerate() {
+        if i > 0 && !is_quoted && wildcard::is_glob(arg) {
+            let paths = wildcard::expand(arg);
+            if !paths.is_empty() {
+                expanded_args.extend(paths);
+                continue;
+            }
+        }
+        expanded_args.push(arg.to_string());
+    }
+    let args: Vec<&str> = expanded_args.iter().map(String::as_str).collect();
+
     let res = match args[0] {
         ""                     => ExitCode::CommandSuccessful,
         "a" | "alias"          => ExitCode::CommandUnknown,
@@ -265,6 +282,10 @@ pub fn exec(cmd: &str) -> ExitCode {
         "mem" | "memory"       => usr::memory::main(&args),
         "kb" | "keyboard"      => usr::keyboard::main(&args),
         "lisp"                 => usr::lisp::main(&args),
+        "link"                 => usr::link::main(&args),
+        "mount"                => usr::mount::main(&args),
+        "umount"               => usr::umount::main(&args),
+        "tar"                  => usr::tar::main(&args),
         "chess"                => usr::chess::main(&args),
         "beep"                 => usr::beep::main(&args),
         "elf"                  => usr::elf::main(&args),
@@ -372,3 +393,10 @@ fn test_shell() {
 
     sys::fs::dismount();
 }
+
+#[test_case]
+fn test_split_args() {
+    assert_eq!(split_args("print \"*.txt\" *.lsp"), [("print", false), ("*.txt", true), ("*.lsp", false)]);
+    assert_eq!(split_args("print \"a # b\" c"), [("print", false), ("a # b", true), ("c", false)]);
+    assert_eq!(split_args("list # comment"), [("list", false)]);
+}
@@ -0,0 +1,98 @@
+use crate::{sys, usr};
+use crate::api::console::Style;
+use crate::api::fs;
+use crate::api::tar::{self, EntryKind};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+pub fn main(args: &[&str]) -> usr::shell::ExitCode {
+    if args.len() < 3 {
+        return help();
+    }
+    match args[1] {
+        "create" if args.len() > 3 => create(args[2], &args[3..]),
+        "list" if args.len() == 3 => list(args[2]),
+        "extract" if args.len() == 3 => extract(args[2], &sys::process::dir()),
+        "extract" if args.len() == 4 => extract(args[2], args[3]),
+        _ => help(),
+    }
+}
+
+fn create(pathname: &str, paths: &[&str]) -> usr::shell::ExitCode {
+    let mut entries = Vec::new();
+    for path in paths {
+        match tar::collect(path) {
+            Ok(res) => entries.extend(res),
+            Err(()) => {
+                eprintln!("Could not read '{}'", path);
+                return usr::shell::ExitCode::CommandError;
+            }
+        }
+    }
+    if let Ok(buf) = tar::build(&entries) {
+        if fs::write(pathname, &buf).is_ok() {
+            return usr::shell::ExitCode::CommandSuccessful;
+        }
+    }
+    eprintln!("Could not write to '{}'", pathname);
+    usr::shell::ExitCode::CommandError
+}
+
+fn list(pathname: &str) -> usr::shell::ExitCode {
+    match read(pathname) {
+        Some(entries) => {
+            let csi_dir = Style::color("LightCyan");
+            let csi_reset = Style::reset();
+            for entry in entries {
+                match entry.kind {
+                    EntryKind::Dir => println!("{}{}/{}", csi_dir, entry.name, csi_reset),
+                    EntryKind::Link => println!("{} -> {}", entry.name, String::from_utf8_lossy(&entry.data)),
+                    EntryKind::File => println!("{}", entry.name),
+                }
+            }
+            usr::shell::ExitCode::CommandSuccessful
+        }
+        None => usr::shell::ExitCode::CommandError,
+    }
+}
+
+fn extract(pathname: &str, dest: &str) -> usr::shell::ExitCode {
+    match read(pathname) {
+        Some(entries) => {
+            for entry in entries {
+                if let Err(()) = tar::unpack(&entry, dest) {
+                    eprintln!("Could not extract '{}'", entry.name);
+                    return usr::shell::ExitCode::CommandError;
+                }
+            }
+            usr::shell::ExitCode::CommandSuccessful
+        }
+        None => usr::shell::ExitCode::CommandError,
+    }
+}
+
+fn read(pathname: &str) -> Option<Vec<tar::Entry>> {
+    if let Ok(buf) = fs::read_to_bytes(pathname) {
+        if let Ok(entries) = tar::parse(&buf) {
+            return Some(entries);
+        }
+        eprintln!("Could not parse archive '{}'", pathname);
+    } else {
+        eprintln!("Could not read '{}'", pathname);
+    }
+    None
+}
+
+fn help() -> usr::shell::ExitCode {
+    let csi_option = Style::color("LightCyan");
+    let csi_title = Style::color("Yellow");
+    let csi_reset = Style::reset();
+    println!("{}Usage:{} tar {}<command> <archive>{}", csi_title, csi_reset, csi_option, csi_reset);
+    println!();
+    println!("{}Commands:{}", csi_title, csi_reset);
+    println!("  {}create <archive> <path>...{}    Create an archive of the given paths", csi_option, csi_reset);
+    println!("  {}list <archive>{}                List the entries of an archive", csi_option, csi_reset);
+    println!("  {}extract <archive> [<dir>]{}     Extract an archive into a dir", csi_option, csi_reset);
+    usr::shell::ExitCode::CommandSuccessful
+}
@@ -1,14 +1,11 @@
 use crate::{sys, usr};
-use crate::api::syscall;
-use crate::api::random;
+use crate::sys::fs::FileIO;
+use crate::sys::net::tcp::TcpStream;
 use alloc::borrow::ToOwned;
 use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
-use core::str::{self, FromStr};
-use core::time::Duration;
-use smoltcp::socket::{SocketSet, TcpSocket, TcpSocketBuffer};
-use smoltcp::time::Instant;
+use core::str::FromStr;
 use smoltcp::wire::IpAddress;
 
 pub fn main(args: &[&str]) -> usr::shell::ExitCode {
@@ -47,92 +44,42 @@ pub fn main(args: &[&str]) -> usr::shell::ExitCode {
         }
     };
 
-    let tcp_rx_buffer = TcpSocketBuffer::new(vec![0; 1024]);
-    let tcp_tx_buffer = TcpSocketBuffer::new(vec![0; 1024]);
-    let tcp_socket = TcpSocket::new(tcp_rx_buffer, tcp_tx_buffer);
-
-    let mut sockets = SocketSet::new(vec![]);
-    let tcp_handle = sockets.add(tcp_socket);
-
-    enum State { Connect, Request, Response }
-    let mut state = State::Connect;
+    let is_ready = match *sys::net::IFACE.lock() {
+        Some(ref iface) => iface.ipv4_addr().map_or(false, |ip| !ip.is_unspecified()),
+        None => false,
+    };
+    if !is_ready {
+        eprintln!("Interface not ready");
+        return usr::shell::ExitCode::CommandError;
+    }
 
-    if let Some(ref mut iface) = *sys::net::IFACE.lock() {
-        match iface.ipv4_addr() {
-            None => {
-                eprintln!("Interface not ready");
-                return usr::shell::ExitCode::CommandError;
-            }
-            Some(ip_addr) if ip_addr.is_unspecified() => {
-                eprintln!("Interface not ready");
-                return usr::shell::ExitCode::CommandError;
-            }
-            _ => {}
+    println!("Connecting to {}:{}", address, port);
+    let mut stream = match TcpStream::connect(address, port) {
+        Ok(stream) => stream,
+        Err(_) => {
+            eprintln!("Could not connect to {}:{}", address, port);
+            return usr::shell::ExitCode::CommandError;
         }
-
-        let timeout = 5.0;
-        let started = syscall::realtime();
-        loop {
-            if syscall::realtime() - started > timeout {
-                eprintln!("Timeout reached");
-                return usr::shell::ExitCode::CommandError;
-            }
-            if sys::console::end_of_text() {
-                eprintln!();
-                return usr::shell::ExitCode::CommandError;
-            }
-            let timestamp = Instant::from_millis((syscall::realtime() * 1000.0) as i64);
-            match iface.poll(&mut sockets, timestamp) {
-                Err(smoltcp::Error::Unrecognized) => {}
-                Err(e) => {
-                    println!("Network Error: {}", e);
+    };
+    if !request.is_empty() && stream.write(request.as_bytes()).is_err() {
+        eprintln!("Could not send request");
+        return usr::shell::ExitCode::CommandError;
+    }
+    let mut buf = vec![0; 1024];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                let contents = String::from_utf8_lossy(&buf[..n]);
+                for line in contents.lines() {
+                    println!("{}", line);
                 }
-                Ok(_) => {}
             }
-
-            {
-                let mut socket = sockets.get::<TcpSocket>(tcp_handle);
-
-                state = match state {
-                    State::Connect if !socket.is_active() => {
-                        let local_port = 49152 + random::get_u16() % 16384;
-                        println!("Connecting to {}:{}", address, port);
-                        if socket.connect((address, port), local_port).is_err() {
-                            eprintln!("Could not connect to {}:{}", address, port);
-                            return usr::shell::ExitCode::CommandError;
-                        }
-                        State::Request
-                    }
-                    State::Request if socket.may_send() => {
-                        if !request.is_empty() {
-                            socket.send_slice(request.as_ref()).expect("cannot send");
-                        }
-                        State::Response
-                    }
-                    State::Response if socket.can_recv() => {
-                        socket.recv(|data| {
-                            let contents = String::from_utf8_lossy(data);
-                            for line in contents.lines() {
-                                println!("{}", line);
-                            }
-                            (data.len(), ())
-                        }).unwrap();
-                        State::Response
-                    }
-                    State::Response if !socket.may_recv() => {
-                        break;
-                    }
-                    _ => state
-                };
-            }
-
-            if let Some(wait_duration) = iface.poll_delay(&sockets, timestamp) {
-                let wait_duration: Duration = wait_duration.into();
-                syscall::sleep(wait_duration.as_secs_f64());
+            Err(_) => {
+                eprintln!("Could not receive response");
+                return usr::shell::ExitCode::CommandError;
             }
         }
-        usr::shell::ExitCode::CommandSuccessful
-    } else {
-        usr::shell::ExitCode::CommandError
     }
+    usr::shell::ExitCode::CommandSuccessful
 }
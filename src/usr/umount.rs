@@ -0,0 +1,24 @@
+use crate::usr;
+use crate::api::console::Style;
+use crate::api::syscall;
+
+pub fn main(args: &[&str]) -> usr::shell::ExitCode {
+    if args.len() != 2 || args[1] == "-h" || args[1] == "--help" {
+        return help();
+    }
+    let path = args[1];
+    if syscall::umount(path).is_ok() {
+        usr::shell::ExitCode::CommandSuccessful
+    } else {
+        eprintln!("Could not unmount '{}'", path);
+        usr::shell::ExitCode::CommandError
+    }
+}
+
+fn help() -> usr::shell::ExitCode {
+    let csi_option = Style::color("LightCyan");
+    let csi_title = Style::color("Yellow");
+    let csi_reset = Style::reset();
+    println!("{}Usage:{} umount {}<path>{}", csi_title, csi_reset, csi_option, csi_reset);
+    usr::shell::ExitCode::CommandSuccessful
+}
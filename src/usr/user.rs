@@ -1,4 +1,5 @@
 use crate::{api, sys, usr};
+use crate::api::console;
 use crate::api::fs;
 use crate::api::io;
 use crate::api::random;
@@ -55,9 +56,9 @@ pub fn login(username: &str) -> usr::shell::ExitCode {
     match hashed_password(username) {
         Some(hash) => {
             print!("Password: ");
-            sys::console::disable_echo();
+            console::disable_echo();
             let password = io::stdin().read_line().trim_end().to_string();
-            sys::console::enable_echo();
+            console::enable_echo();
             println!();
             if !check(&password, &hash) {
                 println!();
@@ -92,9 +93,9 @@ pub fn create(username: &str) -> usr::shell::ExitCode {
     }
 
     print!("Password: ");
-    sys::console::disable_echo();
+    console::disable_echo();
     let password = io::stdin().read_line().trim_end().to_string();
-    sys::console::enable_echo();
+    console::enable_echo();
     println!();
 
     if password.is_empty() {
@@ -102,9 +103,9 @@ pub fn create(username: &str) -> usr::shell::ExitCode {
     }
 
     print!("Confirm: ");
-    sys::console::disable_echo();
+    console::disable_echo();
     let confirm = io::stdin().read_line().trim_end().to_string();
-    sys::console::enable_echo();
+    console::enable_echo();
     println!();
 
     if password != confirm {